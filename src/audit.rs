@@ -0,0 +1,110 @@
+use alloc::collections::VecDeque;
+use crate::config::AuditConfig;
+use serde::Deserialize;
+use serde::Serialize;
+use std::time::SystemTime;
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt as _;
+use tokio::sync::Mutex;
+use tokio::sync::broadcast;
+
+/// One mutating control request and how it ended.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuditEntry {
+    pub at: u64,
+    /// `control-token`, `key:<id>`, or `anonymous` when the bearer wasn't recognized.
+    pub actor: String,
+    /// Method and route, e.g. `DELETE /broadcasters/{login}`.
+    pub action: String,
+    pub path: String,
+    /// SHA-256 of the request body, if it had one.
+    pub digest: Option<String>,
+    pub status: u16
+}
+
+/// Who made a request, set on the response by the auth middleware for the audit log to pick up.
+#[derive(Clone, Debug)]
+pub struct Actor(pub String);
+
+/// Recent entries in memory (and so in snapshots), with every entry also appended as a JSON
+/// line to `audit.path` when set, and handed to any sink subscribed.
+#[derive(Debug)]
+pub struct AuditLog {
+    retain: usize,
+    entries: Mutex<VecDeque<AuditEntry>>,
+    file: Option<Mutex<File>>,
+    sender: broadcast::Sender<AuditEntry>
+}
+
+impl AuditLog {
+    pub async fn open(config: &AuditConfig) -> anyhow::Result<Self> {
+        let file = if config.path.is_empty() {
+            None
+        } else {
+            Some(Mutex::new(tokio::fs::OpenOptions::new().create(true).append(true).open(&config.path).await?))
+        };
+
+        Ok(Self {
+            retain: config.retain,
+            entries: Mutex::new(VecDeque::new()),
+            file,
+            sender: broadcast::channel(1024).0
+        })
+    }
+
+    pub async fn record(&self, actor: String, action: String, path: String, digest: Option<String>, status: u16) {
+        let entry = AuditEntry {
+            at: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default(),
+            actor,
+            action,
+            path,
+            digest,
+            status
+        };
+
+        if let Some(file) = &self.file {
+            match serde_json::to_string(&entry) {
+                Ok(mut line) => {
+                    line.push('\n');
+                    if let Err(e) = file.lock().await.write_all(line.as_bytes()).await {
+                        tracing::error!("failed to append to the audit log: {e:?}");
+                    }
+                },
+                Err(e) => tracing::error!("failed to serialize audit entry: {e:?}")
+            }
+        }
+
+        // only fails with no receivers
+        drop(self.sender.send(entry.clone()));
+
+        let mut entries = self.entries.lock().await;
+        entries.push_back(entry);
+        while entries.len() > self.retain {
+            entries.pop_front();
+        }
+        drop(entries);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<AuditEntry> {
+        self.sender.subscribe()
+    }
+
+    /// Newest first, optionally only one actor's.
+    pub async fn recent(&self, actor: Option<&str>, limit: usize) -> Vec<AuditEntry> {
+        self.entries.lock().await.iter().rev().filter(|entry| actor.is_none_or(|actor| entry.actor == actor)).take(limit).cloned().collect()
+    }
+
+    pub async fn all(&self) -> Vec<AuditEntry> {
+        self.entries.lock().await.iter().cloned().collect()
+    }
+
+    pub async fn restore(&self, entries: Vec<AuditEntry>) {
+        let mut retained = self.entries.lock().await;
+        retained.clear();
+        retained.extend(entries);
+        while retained.len() > self.retain {
+            retained.pop_front();
+        }
+        drop(retained);
+    }
+}
@@ -0,0 +1,157 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use anyhow::Context as _;
+use anyhow::anyhow;
+use axum::Router;
+use axum::extract::Request;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse as _;
+use axum::response::Response;
+use crate::config::Config;
+use crate::conduit::DEFAULT;
+use crate::helix::retry::RetryClient;
+use crate::metrics::Metrics;
+use crate::twitch::TwitchControl;
+use core::slice;
+use core::time::Duration;
+use std::sync::OnceLock;
+use tower::ServiceExt as _;
+use twitch_api::TwitchClient;
+use twitch_api::eventsub::Conduit;
+use twitch_api::helix::users::User;
+use twitch_api::twitch_oauth2::AccessToken;
+use twitch_api::twitch_oauth2::AppAccessToken;
+use twitch_api::twitch_oauth2::ClientSecret;
+use twitch_api::twitch_oauth2::RefreshToken;
+use twitch_api::twitch_oauth2::UserToken;
+use twitch_api::types::UserName;
+
+const BASE_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Everything the control plane needs from Twitch before it can serve.
+pub struct Bootstrap {
+    pub app_token: AppAccessToken,
+    pub conduit: Conduit,
+    /// The `[conduits]`, by name.
+    pub routed: BTreeMap<String, Conduit>,
+    pub my_user: User,
+    pub bot_token: Option<UserToken>
+}
+
+/// Keeps retrying the Twitch bootstrap with backoff rather than exiting on a blip. `stored_conduit_ids`
+/// are the conduits persisted by a previous run, by name. Only a `leading` replica creates or
+/// resizes conduits; a follower looks up the leader's.
+pub async fn run(config: &Config, client: &TwitchClient<'_, RetryClient>, twitch: &dyn TwitchControl, metrics: &Metrics, leading: bool, stored_conduit_ids: &BTreeMap<String, String>) -> Bootstrap {
+    let mut delay = BASE_DELAY;
+
+    loop {
+        match attempt(config, client, twitch, metrics, leading, stored_conduit_ids).await {
+            Ok(bootstrap) => return bootstrap,
+            Err(e) => tracing::error!("bootstrap failed, retrying in {delay:?}: {e:?}")
+        }
+
+        tokio::time::sleep(delay).await;
+        delay = delay.saturating_mul(2).min(MAX_DELAY);
+    }
+}
+
+async fn attempt(config: &Config, client: &TwitchClient<'_, RetryClient>, twitch: &dyn TwitchControl, metrics: &Metrics, leading: bool, stored_conduit_ids: &BTreeMap<String, String>) -> anyhow::Result<Bootstrap> {
+    let app_token = AppAccessToken::get_app_access_token(
+        client,
+        config.twitch.client_id.clone().into(),
+        config.twitch.client_secret.clone().into(),
+        vec![]
+    ).await?;
+
+    let mut conduits = metrics.helix("get_conduits", twitch.conduits(&app_token)).await?;
+
+    tracing::info!("{conduits:?}");
+
+    let known_id = |name: &str, pinned: &str| Some(pinned.to_owned()).filter(|id| !id.is_empty()).or_else(|| stored_conduit_ids.get(name).cloned());
+
+    // routed conduits are only ever pinned, persisted or created, never adopted
+    let mut routed = BTreeMap::new();
+    for (name, routed_config) in &config.conduits {
+        let existing = known_id(name, &routed_config.id).and_then(|id| take(&mut conduits, &id));
+        let conduit = sized(twitch, metrics, &app_token, leading, existing, routed_config.shard_count).await?;
+        tracing::info!("{name} conduit: {conduit:?}");
+        routed.insert(name.clone(), conduit);
+    }
+
+    // a pinned or persisted id that's gone means ours was deleted, so don't adopt someone else's
+    let existing = match known_id(DEFAULT, &config.conduit.id) {
+        Some(id) => take(&mut conduits, &id),
+        None if config.conduit.dedicated => None,
+        None => conduits.into_iter().find(|conduit| !stored_conduit_ids.values().any(|id| id == conduit.id.as_str()))
+    };
+
+    // when autoscaling, an existing conduit keeps the size it was scaled to
+    let shard_count = match &existing {
+        Some(c) if config.conduit.max_shards > 0 => c.shard_count.clamp(config.conduit.min_shards, config.conduit.max_shards),
+        _ => config.conduit.shard_count
+    };
+    let conduit = sized(twitch, metrics, &app_token, leading, existing, shard_count).await?;
+
+    tracing::info!("{conduit:?}");
+
+    let my_user = metrics.helix("get_users", twitch.users(slice::from_ref(&UserName::new(config.twitch.user_login.clone())), &app_token)).await?.into_iter().next().ok_or_else(|| anyhow!("failed to retrieve my user"))?;
+
+    let bot_token = match config.twitch.bot_access_token.as_deref().filter(|token| !token.is_empty()) {
+        Some(access_token) => {
+            let token = UserToken::from_existing(
+                client,
+                AccessToken::new(access_token.to_owned()),
+                config.twitch.bot_refresh_token.clone().filter(|token| !token.is_empty()).map(RefreshToken::new),
+                ClientSecret::new(config.twitch.client_secret.clone())
+            ).await.context("invalid bot access token (TWITCH_BOT_ACCESS_TOKEN)")?;
+
+            if token.user_id != my_user.id {
+                return Err(anyhow!("bot access token belongs to {}, not {}", token.login, my_user.login));
+            }
+
+            Some(token)
+        },
+        None => None
+    };
+
+    Ok(Bootstrap { app_token, conduit, routed, my_user, bot_token })
+}
+
+/// Removes the conduit `id` from `conduits`, warning if it's not there any more.
+fn take(conduits: &mut Vec<Conduit>, id: &str) -> Option<Conduit> {
+    let existing = conduits.iter().position(|conduit| conduit.id.as_str() == id).map(|index| conduits.swap_remove(index));
+    if existing.is_none() {
+        tracing::warn!("conduit {id} no longer exists");
+    }
+    existing
+}
+
+/// `existing` resized to `shard_count` if it isn't already, or else a new conduit. A follower
+/// takes the leader's conduit as it is, and fails for the attempt to be retried if it's missing.
+async fn sized(twitch: &dyn TwitchControl, metrics: &Metrics, app_token: &AppAccessToken, leading: bool, existing: Option<Conduit>, shard_count: usize) -> anyhow::Result<Conduit> {
+    Ok(match existing {
+        Some(c) if c.shard_count == shard_count || !leading => c,
+        None if !leading => return Err(anyhow!("a conduit the leader stored isn't there, waiting for the leader to create it")),
+        Some(c) => metrics.helix("update_conduit", twitch.update_conduit(&c.id, shard_count, app_token)).await?,
+        None => metrics.helix("create_conduit", twitch.create_conduit(shard_count, app_token)).await?
+    })
+}
+
+/// Serves from the start so orchestration sees a live but not-ready pod while bootstrapping,
+/// then hands every request to the full router once it's set.
+pub fn router(app: Arc<OnceLock<Router>>) -> Router {
+    Router::new().fallback(dispatch).with_state(app)
+}
+
+async fn dispatch(
+    State(app): State<Arc<OnceLock<Router>>>,
+    request: Request
+) -> Response {
+    match app.get() {
+        Some(router) => router.clone().oneshot(request).await.into_response(),
+        None if request.uri().path() == "/healthz" => StatusCode::OK.into_response(),
+        None => (StatusCode::SERVICE_UNAVAILABLE, "bootstrapping").into_response()
+    }
+}
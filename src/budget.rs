@@ -0,0 +1,25 @@
+use anyhow::anyhow;
+use serde::Serialize;
+
+/// Subscription cost against Twitch's max_total_cost for our client id, as last reported by
+/// a listing or creation. Unknown (all zero) until Twitch has told us.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct CostBudget {
+    pub total: usize,
+    pub total_cost: usize,
+    pub max_total_cost: usize
+}
+
+impl CostBudget {
+    pub const fn remaining(&self) -> Option<usize> {
+        if self.max_total_cost == 0 { None } else { Some(self.max_total_cost.saturating_sub(self.total_cost)) }
+    }
+
+    /// Creations cost at most 1, so refuse once nothing is left rather than let Twitch reject it.
+    pub fn check(&self) -> anyhow::Result<()> {
+        match self.remaining() {
+            Some(0) => Err(anyhow!("subscription cost budget exhausted ({} of {} used)", self.total_cost, self.max_total_cost)),
+            _ => Ok(())
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use crate::ControlState;
+use crate::calendar;
+use crate::subscription::Description;
+use crate::events::ControlEvent;
+use crate::watch::Notice;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use axum::extract::Request;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse as _;
+use axum::response::Response;
+use core::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use tokio::sync::Mutex;
+use tokio::sync::RwLock;
+use twitch_api::eventsub::Event;
+use twitch_api::eventsub::EventType;
+use twitch_api::eventsub::Message;
+use twitch_api::eventsub::Transport;
+use twitch_api::eventsub::TransportResponse;
+use twitch_api::eventsub::conduit::ConduitShardDisabledV1;
+use twitch_api::eventsub::conduit::ConduitShardDisabledV1Payload;
+use twitch_api::helix::eventsub::EventSubSubscriptions;
+
+/// Deliveries are a single event; anything much bigger isn't from Twitch.
+const MAX_BODY: usize = 64 * 1024;
+
+/// How old a delivery can be before it's taken for a replay, as Twitch asks, and how far ahead
+/// of our clock one can be.
+const MAX_AGE: Duration = Duration::from_secs(10 * 60);
+const MAX_SKEW: Duration = Duration::from_secs(60);
+
+/// How long a message id is remembered: past the window a delivery is accepted in, so a replay
+/// is either stale or a duplicate.
+const SEEN_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// Our end of the webhook handshake: the secret Twitch signs deliveries with, persisted so the
+/// next leader verifies them with the same one, and the message ids already handled, as Twitch
+/// redelivers anything it didn't see a 2xx for.
+pub struct Receiver {
+    secret: RwLock<String>,
+    seen: Mutex<BTreeMap<String, Instant>>
+}
+
+impl Default for Receiver {
+    fn default() -> Self {
+        Self {
+            secret: RwLock::new(crate::keys::secret()),
+            seen: Mutex::new(BTreeMap::new())
+        }
+    }
+}
+
+impl Receiver {
+    pub async fn secret(&self) -> String {
+        self.secret.read().await.clone()
+    }
+
+    /// Takes over the stored secret, if an earlier leader left one.
+    pub async fn restore(&self, secret: Option<String>) {
+        if let Some(secret) = secret {
+            *self.secret.write().await = secret;
+        }
+    }
+
+    /// Remembers `id`, returning `false` if it was already seen.
+    async fn first_delivery(&self, id: &str, now: Instant) -> bool {
+        let mut seen = self.seen.lock().await;
+        seen.retain(|_, at| now.duration_since(*at) < SEEN_TTL);
+        seen.insert(id.to_owned(), now).is_none()
+    }
+}
+
+fn url(control_state: &ControlState<'_>) -> String {
+    format!("{}/eventsub/callback", control_state.config.control.public_url.trim_end_matches('/'))
+}
+
+/// Subscribes to conduit.shard.disabled for the client id, delivered to /eventsub/callback. One
+/// left by a previous leader is deleted first, in case it was created with another secret.
+pub async fn subscribe(control_state: &ControlState<'_>) -> anyhow::Result<()> {
+    let callback = url(control_state);
+    let app_token = control_state.app_token.get().await;
+
+    let pages: Vec<EventSubSubscriptions> = control_state.metrics.helix("get_eventsub_subscriptions", control_state.twitch.subscriptions(Some(EventType::ConduitShardDisabled), &app_token)).await?;
+
+    for subscription in pages.into_iter().flat_map(|page| page.subscriptions) {
+        if matches!(&subscription.transport, TransportResponse::Webhook(transport) if transport.callback == callback) {
+            control_state.metrics.helix("delete_eventsub_subscription", control_state.twitch.delete_subscription(&subscription.id, &app_token)).await?;
+        }
+    }
+
+    // without a conduit id in the condition, so it survives the conduit being recreated
+    let event_info = control_state.metrics.helix("create_eventsub_subscription", control_state.twitch.create_subscription(
+        &Description::of(&ConduitShardDisabledV1::client_id(control_state.config.twitch.client_id.as_str())),
+        Transport::webhook(&callback, control_state.callback.secret().await),
+        &app_token
+    )).await?;
+    tracing::info!("subscribed to conduit.shard.disabled at {callback} as {}", event_info.id);
+
+    Ok(())
+}
+
+/// Receives what Twitch sends for the subscriptions we hold on a webhook ourselves: answers
+/// the challenge when one is created, and acts on revocations and notifications once each.
+pub async fn receive(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    request: Request
+) -> Result<Response, StatusCode> {
+    let (parts, body) = request.into_parts();
+    let body = axum::body::to_bytes(body, MAX_BODY).await.map_err(|_err| StatusCode::PAYLOAD_TOO_LARGE)?;
+    let request = axum::http::Request::from_parts(parts, body);
+
+    if !Event::verify_payload(&request, control_state.callback.secret().await.as_bytes()) {
+        control_state.metrics.inc("control_callback_deliveries_total", &[("outcome", "bad_signature")]);
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    // signed, but an old delivery is still a replay; verify_payload has already required the headers
+    let header = |name: &str| request.headers().get(name).and_then(|value| value.to_str().ok()).unwrap_or_default();
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default();
+    let fresh = calendar::unix_secs(header("Twitch-Eventsub-Message-Timestamp"))
+        .is_some_and(|sent| now.saturating_sub(sent) <= MAX_AGE.as_secs() && sent.saturating_sub(now) <= MAX_SKEW.as_secs());
+    if !fresh {
+        control_state.metrics.inc("control_callback_deliveries_total", &[("outcome", "stale")]);
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let event = Event::parse_http(&request).map_err(|e| {
+        tracing::warn!("failed to parse an eventsub delivery: {e}");
+        control_state.metrics.inc("control_callback_deliveries_total", &[("outcome", "unparseable")]);
+        StatusCode::BAD_REQUEST
+    })?;
+
+    if let Some(verification) = event.get_verification_request() {
+        control_state.metrics.inc("control_callback_deliveries_total", &[("outcome", "challenge")]);
+        return Ok(verification.challenge.clone().into_response());
+    }
+
+    if !control_state.callback.first_delivery(header("Twitch-Eventsub-Message-Id"), Instant::now()).await {
+        control_state.metrics.inc("control_callback_deliveries_total", &[("outcome", "duplicate")]);
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    }
+    control_state.metrics.inc("control_callback_deliveries_total", &[("outcome", "ok")]);
+
+    if event.is_revocation() {
+        match event.subscription() {
+            Ok(subscription) => {
+                tracing::error!("{} subscription {} on our callback was revoked: {:?}", subscription.type_.to_str(), subscription.id, subscription.status);
+                control_state.events.publish(ControlEvent::SubscriptionRevoked {
+                    kind: subscription.type_.to_str().to_owned(),
+                    id: subscription.id.to_string(),
+                    status: serde_json::to_value(&subscription.status).ok().and_then(|status| status.as_str().map(str::to_owned)).unwrap_or_default()
+                });
+            },
+            Err(e) => tracing::error!("a subscription on our callback was revoked: {e}")
+        }
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    }
+
+    match event {
+        Event::ConduitShardDisabledV1(payload) => {
+            if let Message::Notification(disabled) = payload.message {
+                shard_disabled(&control_state, disabled).await;
+            }
+        },
+        event => tracing::debug!("ignored an eventsub delivery: {:?}", event.subscription().map(|subscription| subscription.type_))
+    }
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Frees a shard Twitch disabled and fails it over right away, rather than at the next health
+/// check. Ignored if the shard has moved on to another session since.
+async fn shard_disabled(control_state: &ControlState<'_>, disabled: ConduitShardDisabledV1Payload) {
+    let Some(lane) = control_state.conduits.by_id(&disabled.conduit_id).await else {
+        return;
+    };
+
+    let Ok(shard) = disabled.shard_id.parse() else {
+        return;
+    };
+
+    let session_id = match &disabled.transport {
+        TransportResponse::Websocket(transport) => transport.session_id.as_str(),
+        TransportResponse::Webhook(transport) => transport.callback.as_str(),
+        _ => return
+    };
+
+    let workers = control_state.workers.lock().await;
+    let mut scheduler = lane.scheduler.lock().await;
+    if scheduler.assignment(shard).is_some_and(|assignment| assignment.session_id == session_id)
+        && let Some(assignment) = scheduler.release(shard)
+    {
+        if let Some(worker_id) = &assignment.worker_id {
+            control_state.watch.notify(worker_id, Notice::Reconnect { conduit: lane.name.clone(), shard });
+        }
+        control_state.events.publish(ControlEvent::ShardUnassigned { conduit: lane.name.clone(), shard, worker_id: assignment.worker_id, reason: "disabled" });
+        tracing::warn!("shard {shard} of the {} conduit was disabled ({:?}), unassigned session {session_id}", lane.name, disabled.status);
+        crate::fail_over(control_state, &workers, lane, &mut scheduler, &[shard]);
+    }
+    drop(scheduler);
+    drop(workers);
+}
@@ -0,0 +1,269 @@
+use crate::ControlState;
+use crate::proxy;
+use crate::proxy::Rejection;
+use alloc::borrow::Cow;
+use alloc::sync::Arc;
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::Method;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+use twitch_api::HttpClient as _;
+use twitch_api::helix::ClientRequestError;
+use twitch_api::helix::Request as _;
+use twitch_api::helix::RequestGet as _;
+use twitch_api::helix::channels::AddChannelVipRequest;
+use twitch_api::helix::channels::ModifyChannelInformationBody;
+use twitch_api::helix::channels::ModifyChannelInformationRequest;
+use twitch_api::helix::channels::RemoveChannelVipRequest;
+use twitch_api::helix::clips::CreateClipRequest;
+use twitch_api::helix::clips::CreatedClip;
+use twitch_api::helix::games::GetGamesRequest;
+use twitch_api::helix::moderation::AddChannelModeratorRequest;
+use twitch_api::helix::moderation::RemoveChannelModeratorRequest;
+use twitch_api::helix::points::CreateCustomRewardBody;
+use twitch_api::helix::points::CreateCustomRewardRequest;
+use twitch_api::helix::points::CustomReward;
+use twitch_api::helix::points::DeleteCustomRewardRequest;
+use twitch_api::helix::points::GetCustomRewardRequest;
+use twitch_api::helix::points::UpdateCustomRewardBody;
+use twitch_api::helix::points::UpdateCustomRewardRequest;
+use twitch_api::helix::points::update_custom_reward::UpdateCustomReward;
+use twitch_api::helix::raids::CancelARaidRequest;
+use twitch_api::helix::raids::StartARaidRequest;
+use twitch_api::types::CategoryId;
+use twitch_api::twitch_oauth2::TwitchToken as _;
+use twitch_api::twitch_oauth2::UserToken;
+use twitch_api::types::RewardId;
+
+/// Only what's given changes.
+#[derive(Deserialize)]
+pub struct UpdateChannel {
+    title: Option<String>,
+    /// The category by name, as an alternative to `category_id`.
+    category: Option<String>,
+    category_id: Option<CategoryId>,
+    /// Replaces all tags; empty removes them.
+    tags: Option<Vec<String>>
+}
+
+/// Changes the broadcaster's stream title, category or tags, with the token they authorized us
+/// with.
+pub async fn update(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(broadcaster): Path<String>,
+    Json(body): Json<UpdateChannel>
+) -> Result<StatusCode, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::user_token(&control_state, &broadcaster_id, &ModifyChannelInformationRequest::SCOPE).await?;
+
+    let category_id = match (body.category_id, body.category) {
+        (Some(_), Some(_)) => return Err((StatusCode::UNPROCESSABLE_ENTITY, "give either category or category_id".to_owned())),
+        (Some(id), None) => Some(id),
+        (None, Some(name)) => Some(category_by_name(&control_state, name).await?),
+        (None, None) => None
+    };
+
+    let tags: Option<Vec<&str>> = body.tags.as_ref().map(|tags| tags.iter().map(String::as_str).collect());
+    let mut update = ModifyChannelInformationBody::new();
+    update.title = body.title.map(Cow::Owned);
+    update.game_id = category_id.as_deref().map(Cow::Borrowed);
+    update.tags = tags.as_deref().map(Cow::Borrowed);
+
+    let request = ModifyChannelInformationRequest::broadcaster_id(&broadcaster_id);
+    control_state.metrics.helix("modify_channel_information", control_state.client.helix.req_patch(request, update, &token)).await.map_err(|e| proxy::rejection(&e))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn category_by_name(control_state: &ControlState<'_>, name: String) -> Result<CategoryId, Rejection> {
+    let request = GetGamesRequest::names(vec![name.clone()]);
+    let games = control_state.metrics.helix("get_games", control_state.client.helix.req_get(request, &control_state.app_token.get().await)).await.map_err(|e| proxy::rejection(&e))?.data;
+
+    games.into_iter().find(|game| game.name.eq_ignore_ascii_case(&name)).map(|game| game.id)
+        .ok_or_else(|| (StatusCode::UNPROCESSABLE_ENTITY, format!("no such category {name}")))
+}
+
+#[derive(Deserialize)]
+pub struct RewardsQuery {
+    /// Only the rewards our client id created, which are the only ones it can change.
+    #[serde(default)]
+    manageable: bool
+}
+
+/// The broadcaster's custom channel point rewards.
+pub async fn rewards_list(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(broadcaster): Path<String>,
+    Query(query): Query<RewardsQuery>
+) -> Result<Json<Vec<CustomReward>>, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::user_token(&control_state, &broadcaster_id, &GetCustomRewardRequest::SCOPE).await?;
+
+    let request = GetCustomRewardRequest::broadcaster_id(&broadcaster_id).only_manageable_rewards(query.manageable);
+    let rewards = control_state.metrics.helix("get_custom_reward", control_state.client.helix.req_get(request, &token)).await.map_err(|e| proxy::rejection(&e))?.data;
+
+    Ok(Json(rewards))
+}
+
+/// Takes Twitch's own fields, of which `title` and `cost` are required.
+pub async fn rewards_create(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(broadcaster): Path<String>,
+    Json(body): Json<CreateCustomRewardBody<'static>>
+) -> Result<(StatusCode, Json<CustomReward>), Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::user_token(&control_state, &broadcaster_id, &CreateCustomRewardRequest::SCOPE).await?;
+
+    let request = CreateCustomRewardRequest::broadcaster_id(&broadcaster_id);
+    let reward = control_state.metrics.helix("create_custom_rewards", control_state.client.helix.req_post(request, body, &token)).await.map_err(|e| proxy::rejection(&e))?.data;
+
+    Ok((StatusCode::CREATED, Json(reward)))
+}
+
+/// Takes Twitch's own fields; only those given change.
+pub async fn rewards_update(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path((broadcaster, id)): Path<(String, RewardId)>,
+    Json(body): Json<UpdateCustomRewardBody<'static>>
+) -> Result<Json<CustomReward>, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::user_token(&control_state, &broadcaster_id, &UpdateCustomRewardRequest::SCOPE).await?;
+
+    let request = UpdateCustomRewardRequest::new(&broadcaster_id, &id);
+    let UpdateCustomReward::Success(reward) = control_state.metrics.helix("update_custom_reward", control_state.client.helix.req_patch(request, body, &token)).await.map_err(|e| proxy::rejection(&e))?.data else {
+        return Err((StatusCode::BAD_GATEWAY, "unexpected response from Twitch".to_owned()));
+    };
+
+    Ok(Json(reward))
+}
+
+pub async fn rewards_delete(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path((broadcaster, id)): Path<(String, RewardId)>
+) -> Result<StatusCode, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::user_token(&control_state, &broadcaster_id, &DeleteCustomRewardRequest::SCOPE).await?;
+
+    let request = DeleteCustomRewardRequest::new(&broadcaster_id, &id);
+    control_state.metrics.helix("delete_custom_reward", control_state.client.helix.req_delete(request, &token)).await.map_err(|e| proxy::rejection(&e))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct ClipQuery {
+    /// Clips with the stream's delay added, as viewers saw it.
+    #[serde(default)]
+    has_delay: bool
+}
+
+/// Clips the broadcaster's live stream as the bot. Twitch finishes the clip in the background;
+/// the edit url lets someone trim and title it.
+pub async fn clip(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(broadcaster): Path<String>,
+    Query(query): Query<ClipQuery>
+) -> Result<(StatusCode, Json<CreatedClip>), Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::bot_token(&control_state, &CreateClipRequest::SCOPE).await?;
+
+    let request = CreateClipRequest::broadcaster_id(&broadcaster_id).has_delay(query.has_delay);
+    let clips = control_state.metrics.helix("create_clip", create_clip(&control_state, request, &token)).await.map_err(|e| proxy::rejection(&e))?;
+
+    let clip = clips.into_iter().next().ok_or_else(|| (StatusCode::BAD_GATEWAY, "Twitch didn't return the clip".to_owned()))?;
+    Ok((StatusCode::ACCEPTED, Json(clip)))
+}
+
+/// Twitch creates clips with a POST, but twitch_api only builds the request as a GET.
+async fn create_clip(control_state: &ControlState<'_>, request: CreateClipRequest<'_>, token: &UserToken) -> Result<Vec<CreatedClip>, ClientRequestError<reqwest::Error>> {
+    let mut http_request = request.create_request(token.token().secret(), token.client_id().as_str())?;
+    *http_request.method_mut() = Method::POST;
+    let uri = http_request.uri().clone();
+
+    let response = control_state.client.helix.clone_client().req(http_request).await.map_err(ClientRequestError::RequestError)?;
+    Ok(CreateClipRequest::parse_response(Some(request), &uri, response)?.data)
+}
+
+/// Starts a raid of `target` with the broadcaster's token. It goes out when Twitch's 90 second
+/// countdown ends or the broadcaster clicks Raid Now, unless canceled first.
+pub async fn raid(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path((broadcaster, target)): Path<(String, String)>
+) -> Result<StatusCode, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let target_id = proxy::user_id(&control_state, &target).await?;
+    let token = proxy::user_token(&control_state, &broadcaster_id, &StartARaidRequest::SCOPE).await?;
+
+    control_state.metrics.helix("start_a_raid", control_state.client.helix.start_a_raid(&broadcaster_id, &target_id, &token)).await.map_err(|e| proxy::rejection(&e))?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+pub async fn raid_cancel(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(broadcaster): Path<String>
+) -> Result<StatusCode, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::user_token(&control_state, &broadcaster_id, &CancelARaidRequest::SCOPE).await?;
+
+    control_state.metrics.helix("cancel_a_raid", control_state.client.helix.cancel_a_raid(&broadcaster_id, &token)).await.map_err(|e| proxy::rejection(&e))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Makes `user` a VIP, with the broadcaster's token.
+pub async fn add_vip(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path((broadcaster, user)): Path<(String, String)>
+) -> Result<StatusCode, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let user_id = proxy::user_id(&control_state, &user).await?;
+    let token = proxy::user_token(&control_state, &broadcaster_id, &AddChannelVipRequest::SCOPE).await?;
+
+    control_state.metrics.helix("add_channel_vip", control_state.client.helix.add_channel_vip(&broadcaster_id, &user_id, &token)).await.map_err(|e| proxy::rejection(&e))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn remove_vip(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path((broadcaster, user)): Path<(String, String)>
+) -> Result<StatusCode, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let user_id = proxy::user_id(&control_state, &user).await?;
+    let token = proxy::user_token(&control_state, &broadcaster_id, &RemoveChannelVipRequest::SCOPE).await?;
+
+    control_state.metrics.helix("remove_channel_vip", control_state.client.helix.remove_channel_vip(&broadcaster_id, &user_id, &token)).await.map_err(|e| proxy::rejection(&e))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Makes `user` a moderator, with the broadcaster's token. Twitch refuses if they're a VIP.
+pub async fn add_moderator(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path((broadcaster, user)): Path<(String, String)>
+) -> Result<StatusCode, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let user_id = proxy::user_id(&control_state, &user).await?;
+    let token = proxy::user_token(&control_state, &broadcaster_id, &AddChannelModeratorRequest::SCOPE).await?;
+
+    control_state.metrics.helix("add_channel_moderator", control_state.client.helix.add_channel_moderator(&broadcaster_id, &user_id, &token)).await.map_err(|e| proxy::rejection(&e))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn remove_moderator(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path((broadcaster, user)): Path<(String, String)>
+) -> Result<StatusCode, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let user_id = proxy::user_id(&control_state, &user).await?;
+    let token = proxy::user_token(&control_state, &broadcaster_id, &RemoveChannelModeratorRequest::SCOPE).await?;
+
+    control_state.metrics.helix("remove_channel_moderator", control_state.client.helix.remove_channel_moderator(&broadcaster_id, &user_id, &token)).await.map_err(|e| proxy::rejection(&e))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
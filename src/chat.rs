@@ -0,0 +1,251 @@
+use crate::ControlState;
+use crate::proxy;
+use crate::proxy::Rejection;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::http::header::RETRY_AFTER;
+use axum::response::IntoResponse as _;
+use axum::response::Response;
+use axum::Json;
+use core::time::Duration;
+use futures_util::TryStreamExt as _;
+use serde::Deserialize;
+use serde::Serialize;
+use std::time::Instant;
+use tokio::sync::Mutex;
+use twitch_api::extra::AnnouncementColor;
+use twitch_api::helix::Request as _;
+use twitch_api::helix::chat::SendChatAnnouncementBody;
+use twitch_api::helix::chat::SendChatAnnouncementRequest;
+use twitch_api::helix::chat::SendChatMessageRequest;
+use twitch_api::helix::chat::SendChatMessageResponse;
+use twitch_api::helix::chat::SendAShoutoutRequest;
+use twitch_api::helix::chat::ChatSettings;
+use twitch_api::helix::chat::Chatter;
+use twitch_api::helix::chat::GetChattersRequest;
+use twitch_api::helix::chat::UpdateChatSettingsBody;
+use twitch_api::helix::chat::UpdateChatSettingsRequest;
+use twitch_api::helix::moderation::DeleteChatMessagesRequest;
+use twitch_api::types::UserId;
+
+/// Twitch allows a channel one shoutout every two minutes, and the same target one an hour.
+const CHANNEL_COOLDOWN: Duration = Duration::from_secs(2 * 60);
+const TARGET_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+/// How long a channel's chatters are served from cache.
+const CHATTERS_TTL: Duration = Duration::from_secs(30);
+
+type CachedChatters = Arc<Mutex<Option<(Instant, Vec<Chatter>)>>>;
+
+#[derive(Deserialize)]
+pub struct SendMessage {
+    message: String,
+    /// Sends it as a reply to this message.
+    reply_parent_message_id: Option<String>
+}
+
+/// Sends a message to the broadcaster's chat as the bot. One Twitch dropped comes back as 422,
+/// with its reason.
+pub async fn send_message(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(broadcaster): Path<String>,
+    Json(body): Json<SendMessage>
+) -> Result<(StatusCode, Json<SendChatMessageResponse>), Rejection> {
+    if body.message.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "the message is empty".to_owned()));
+    }
+
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::bot_token(&control_state, &SendChatMessageRequest::SCOPE).await?;
+
+    let helix = &control_state.client.helix;
+    let sent = match body.reply_parent_message_id.as_deref() {
+        Some(parent) => control_state.metrics.helix("send_chat_message", helix.send_chat_message_reply(&broadcaster_id, &control_state.my_user.id, parent, body.message.as_str(), &token)).await,
+        None => control_state.metrics.helix("send_chat_message", helix.send_chat_message(&broadcaster_id, &control_state.my_user.id, body.message.as_str(), &token)).await
+    }.map_err(|e| proxy::rejection(&e))?;
+
+    if !sent.is_sent {
+        tracing::info!("Twitch dropped a message to {broadcaster}: {:?}", sent.drop_reason);
+    }
+    Ok((if sent.is_sent { StatusCode::OK } else { StatusCode::UNPROCESSABLE_ENTITY }, Json(sent)))
+}
+
+#[derive(Deserialize)]
+pub struct SendAnnouncement {
+    message: String,
+    /// blue, green, orange, purple, or the channel's accent color by default.
+    #[serde(default)]
+    color: AnnouncementColor
+}
+
+/// Highlights a message in the broadcaster's chat, sent by the bot as one of its moderators.
+pub async fn send_announcement(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(broadcaster): Path<String>,
+    Json(body): Json<SendAnnouncement>
+) -> Result<StatusCode, Rejection> {
+    if body.message.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "the message is empty".to_owned()));
+    }
+
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::bot_token(&control_state, &SendChatAnnouncementRequest::SCOPE).await?;
+
+    let request = SendChatAnnouncementRequest::new(&broadcaster_id, &control_state.my_user.id);
+    let Ok(body) = SendChatAnnouncementBody::new(body.message, body.color);
+    control_state.metrics.helix("send_chat_announcement", control_state.client.helix.req_post(request, body, &token)).await.map_err(|e| proxy::rejection(&e))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Removes one message from the broadcaster's chat, as the bot moderating it.
+pub async fn delete_message(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path((broadcaster, message_id)): Path<(String, String)>
+) -> Result<StatusCode, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::bot_token(&control_state, &DeleteChatMessagesRequest::SCOPE).await?;
+
+    control_state.metrics.helix("delete_chat_messages", control_state.client.helix.delete_chat_message(&broadcaster_id, &control_state.my_user.id, message_id.as_str(), &token)).await.map_err(|e| proxy::rejection(&e))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Turns slow, follower-only, subscriber-only, emote-only or unique chat mode on or off, as the
+/// bot moderating the chat. Takes Twitch's own fields (e.g. `slow_mode_wait_time` in seconds,
+/// `follower_mode_duration` in minutes); only those given change.
+pub async fn update_settings(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(broadcaster): Path<String>,
+    Json(body): Json<UpdateChatSettingsBody>
+) -> Result<Json<ChatSettings>, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::bot_token(&control_state, &UpdateChatSettingsRequest::SCOPE).await?;
+
+    let request = UpdateChatSettingsRequest::new(&broadcaster_id, &control_state.my_user.id);
+    let settings = control_state.metrics.helix("update_chat_settings", control_state.client.helix.req_patch(request, body, &token)).await.map_err(|e| proxy::rejection(&e))?.data;
+
+    Ok(Json(settings))
+}
+
+/// Shoutouts sent through us within the last `TARGET_COOLDOWN`, to tell callers how long
+/// Twitch's cooldowns have left.
+#[derive(Default)]
+pub struct Shoutouts {
+    sent: Mutex<Vec<(UserId, UserId, Instant)>>
+}
+
+impl Shoutouts {
+    /// The cooldown standing in the way of a shoutout, and how long it has left.
+    async fn cooldown(&self, from: &UserId, to: &UserId) -> Option<(Cooldown, Duration)> {
+        let mut sent = self.sent.lock().await;
+        sent.retain(|(_, _, at)| at.elapsed() < TARGET_COOLDOWN);
+
+        let target = sent.iter().find(|(sender, target, _)| sender == from && target == to).map(|(_, _, at)| (Cooldown::Target, TARGET_COOLDOWN.saturating_sub(at.elapsed())));
+        let channel = sent.iter().find(|(sender, _, at)| sender == from && at.elapsed() < CHANNEL_COOLDOWN).map(|(_, _, at)| (Cooldown::Channel, CHANNEL_COOLDOWN.saturating_sub(at.elapsed())));
+        drop(sent);
+
+        target.into_iter().chain(channel).max_by_key(|(_, left)| *left)
+    }
+
+    async fn record(&self, from: UserId, to: UserId) {
+        self.sent.lock().await.push((from, to, Instant::now()));
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Cooldown {
+    /// The channel sent a shoutout to anyone within `CHANNEL_COOLDOWN`.
+    Channel,
+    /// The channel sent one to this target within `TARGET_COOLDOWN`.
+    Target
+}
+
+#[derive(Serialize)]
+struct OnCooldown {
+    cooldown: Cooldown,
+    /// Exact for shoutouts sent through us, otherwise the whole cooldown as an upper bound.
+    retry_after_secs: u64,
+    message: String
+}
+
+fn on_cooldown(cooldown: Cooldown, left: Duration, message: String) -> Response {
+    let retry_after_secs = left.as_secs().saturating_add(1);
+    (StatusCode::TOO_MANY_REQUESTS, [(RETRY_AFTER, retry_after_secs.to_string())], Json(OnCooldown { cooldown, retry_after_secs, message })).into_response()
+}
+
+/// Shouts out `target` in the broadcaster's chat, as the bot moderating it. A cooldown in the way
+/// comes back as 429 saying which one and for how long.
+pub async fn shoutout(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path((broadcaster, target)): Path<(String, String)>
+) -> Result<Response, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let target_id = proxy::user_id(&control_state, &target).await?;
+
+    if let Some((cooldown, left)) = control_state.shoutouts.cooldown(&broadcaster_id, &target_id).await {
+        return Ok(on_cooldown(cooldown, left, format!("{broadcaster} can't shout out {target} for another {}s", left.as_secs())));
+    }
+
+    let token = proxy::bot_token(&control_state, &SendAShoutoutRequest::SCOPE).await?;
+    let request = SendAShoutoutRequest::new(&broadcaster_id, &target_id, &control_state.my_user.id);
+    match control_state.metrics.helix("send_a_shoutout", control_state.client.helix.req_post(request, Default::default(), &token)).await {
+        Ok(_) => {
+            control_state.shoutouts.record(broadcaster_id, target_id).await;
+            Ok(StatusCode::NO_CONTENT.into_response())
+        },
+        // one sent some other way, so we can't know how much is left of it
+        Err(e) if crate::helix::status(&e) == Some(StatusCode::TOO_MANY_REQUESTS) => {
+            let message = crate::helix::message(&e).unwrap_or_default().to_owned();
+            let (cooldown, left) = if message.contains("same broadcaster") { (Cooldown::Target, TARGET_COOLDOWN) } else { (Cooldown::Channel, CHANNEL_COOLDOWN) };
+            Ok(on_cooldown(cooldown, left, message))
+        },
+        Err(e) => Err(proxy::rejection(&e))
+    }
+}
+
+/// The last chatters fetched per channel. Requests for a channel queue behind one fetch, so
+/// workers asking at once cost a single round of Helix calls.
+#[derive(Default)]
+pub struct Chatters {
+    cached: Mutex<BTreeMap<UserId, CachedChatters>>
+}
+
+#[derive(Serialize)]
+pub struct ChatterList {
+    total: usize,
+    /// How long ago Twitch was asked.
+    age_secs: u64,
+    chatters: Vec<Chatter>
+}
+
+/// Everyone connected to the broadcaster's chat, across all pages, as seen by the bot moderating
+/// it.
+pub async fn chatters(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(broadcaster): Path<String>
+) -> Result<Json<ChatterList>, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+
+    let entry = Arc::clone(control_state.chatters.cached.lock().await.entry(broadcaster_id.clone()).or_default());
+    let mut cached = entry.lock().await;
+    if let Some((fetched, chatters)) = cached.as_ref() && fetched.elapsed() < CHATTERS_TTL {
+        return Ok(Json(ChatterList { total: chatters.len(), age_secs: fetched.elapsed().as_secs(), chatters: chatters.clone() }));
+    }
+
+    let token = proxy::bot_token(&control_state, &GetChattersRequest::SCOPE).await?;
+    let chatters: Vec<Chatter> = control_state.metrics.helix("get_chatters", control_state.client.helix.get_chatters(
+        &broadcaster_id,
+        &control_state.my_user.id,
+        1000,
+        &token
+    ).try_collect()).await.map_err(|e| proxy::rejection(&e))?;
+
+    *cached = Some((Instant::now(), chatters.clone()));
+    drop(cached);
+
+    Ok(Json(ChatterList { total: chatters.len(), age_secs: 0, chatters }))
+}
@@ -0,0 +1,183 @@
+use crate::ControlState;
+use crate::config::Config;
+use crate::monitor::ConduitHealth;
+use crate::scheduler::Scheduler;
+use crate::subscription::SubscriptionKind;
+use crate::watch::Notice;
+use alloc::collections::BTreeMap;
+use tokio::sync::Mutex;
+use tokio::sync::RwLock;
+use twitch_api::eventsub::Conduit;
+use twitch_api::eventsub::Shard;
+use twitch_api::twitch_oauth2::AppAccessToken;
+
+/// The name of the `[conduit]` conduit, which carries every type not routed elsewhere.
+pub const DEFAULT: &str = "default";
+
+/// A conduit with its shard assignments and last health check.
+pub struct Lane {
+    pub name: String,
+    pub conduit: RwLock<Conduit>,
+    pub scheduler: Mutex<Scheduler>,
+    pub health: RwLock<ConduitHealth>
+}
+
+impl Lane {
+    pub fn new(name: String, conduit: Conduit) -> Self {
+        Self {
+            name,
+            scheduler: Mutex::new(Scheduler::new(conduit.shard_count)),
+            conduit: RwLock::new(conduit),
+            health: RwLock::new(ConduitHealth::default())
+        }
+    }
+}
+
+/// The default conduit and the ones configured under `[conduits]` for some types of their own.
+pub struct Conduits {
+    pub default: Lane,
+    pub routed: BTreeMap<String, Lane>
+}
+
+impl Conduits {
+    /// `None` is the default conduit.
+    pub fn get(&self, name: Option<&str>) -> Option<&Lane> {
+        match name {
+            None => Some(&self.default),
+            Some(name) if name == DEFAULT => Some(&self.default),
+            Some(name) => self.routed.get(name)
+        }
+    }
+
+    pub fn for_kind(&self, config: &Config, kind: SubscriptionKind) -> &Lane {
+        self.get(Some(config.conduit_for(kind))).unwrap_or(&self.default)
+    }
+
+    pub async fn by_id(&self, id: &str) -> Option<&Lane> {
+        for lane in self.iter() {
+            if lane.conduit.read().await.id.as_str() == id {
+                return Some(lane);
+            }
+        }
+        None
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Lane> {
+        core::iter::once(&self.default).chain(self.routed.values())
+    }
+}
+
+/// Replaces a conduit deleted out from under us (by Twitch or an operator) with a new one of the
+/// same size, and points the shards we know are assigned at their sessions again. The next
+/// reconciliation recreates the subscriptions on it and drops the ones left on the old id.
+pub async fn heal(control_state: &ControlState<'_>, lane: &Lane) -> anyhow::Result<()> {
+    let app_token = control_state.app_token.get().await;
+    let shard_count = lane.conduit.read().await.shard_count;
+
+    let conduit = match control_state.metrics.helix("create_conduit", control_state.twitch.create_conduit(shard_count, &app_token)).await {
+        Ok(conduit) => conduit,
+        Err(e) => {
+            control_state.metrics.inc("control_conduit_recreations_total", &[("outcome", "error")]);
+            return Err(e.into());
+        }
+    };
+    tracing::warn!("recreated the {} conduit as {}", lane.name, conduit.id);
+    control_state.metrics.inc("control_conduit_recreations_total", &[("outcome", "ok")]);
+    *lane.conduit.write().await = conduit.clone();
+
+    reapply(control_state, lane, &conduit, &app_token).await
+}
+
+/// Moves onto a fresh conduit and deletes the old one, for getting out of a conduit Twitch has
+/// wedged. Subscriptions are recreated before the old conduit takes its own with it, so events
+/// keep flowing, possibly twice for a moment; any Twitch wouldn't duplicate are created after.
+pub async fn migrate(control_state: &ControlState<'_>, lane: &Lane) -> anyhow::Result<Conduit> {
+    let app_token = control_state.app_token.get().await;
+    let old = lane.conduit.read().await.clone();
+
+    let conduit = control_state.metrics.helix("create_conduit", control_state.twitch.create_conduit(old.shard_count, &app_token)).await?;
+    tracing::warn!("migrating the {} conduit from {} to {}", lane.name, old.id, conduit.id);
+    *lane.conduit.write().await = conduit.clone();
+
+    reapply(control_state, lane, &conduit, &app_token).await?;
+    if let Err(e) = crate::reconcile::reconcile(control_state).await {
+        tracing::warn!("failed to recreate every subscription before deleting the old conduit: {e:?}");
+    }
+
+    control_state.metrics.helix("delete_conduit", control_state.twitch.delete_conduit(&old.id, &app_token)).await?;
+    tracing::info!("deleted the old conduit {}", old.id);
+    crate::reconcile::reconcile(control_state).await?;
+
+    Ok(conduit)
+}
+
+/// Points the shards we know are assigned at their sessions on `conduit`.
+async fn reapply(control_state: &ControlState<'_>, lane: &Lane, conduit: &Conduit, app_token: &AppAccessToken) -> anyhow::Result<()> {
+    let scheduler = lane.scheduler.lock().await;
+    let shards: Vec<Shard> = scheduler.assignments().filter(|(_, assignment)| !assignment.is_reserved()).map(|(shard, assignment)| Shard::new(shard.to_string(), assignment.transport())).collect();
+    drop(scheduler);
+
+    if !shards.is_empty() {
+        let response = control_state.metrics.helix("update_conduit_shards", control_state.twitch.update_shards(&conduit.id, &shards, app_token)).await?;
+        for error in response.errors {
+            tracing::warn!("failed to reapply shard {} to conduit {}: {}", error.id, conduit.id, error.message);
+        }
+    }
+
+    Ok(())
+}
+
+/// Resizes the conduit, moving sessions off shards that go away onto free ones that remain.
+/// Sessions there's no room for lose their shard, and get a new one when they assign again.
+pub async fn resize(control_state: &ControlState<'_>, lane: &Lane, shard_count: usize) -> anyhow::Result<Conduit> {
+    let app_token = control_state.app_token.get().await;
+    let conduit_id = lane.conduit.read().await.id.clone();
+
+    let mut scheduler = lane.scheduler.lock().await;
+
+    // Twitch drops the shards past the new count, which frees their sessions for moving
+    let conduit = control_state.metrics.helix("update_conduit", control_state.twitch.update_conduit(&conduit_id, shard_count, &app_token)).await?;
+    let (moved, dropped) = scheduler.resize(conduit.shard_count);
+    drop(scheduler);
+
+    tracing::info!("resized the {} conduit to {} shards, moving {} sessions and dropping {}", lane.name, conduit.shard_count, moved.len(), dropped.len());
+    *lane.conduit.write().await = conduit.clone();
+
+    for (shard, assignment) in &moved {
+        if let Some(worker_id) = &assignment.worker_id {
+            control_state.watch.notify(worker_id, Notice::Moved { conduit: lane.name.clone(), shard: *shard });
+        }
+    }
+    for (shard, assignment) in &dropped {
+        if let Some(worker_id) = &assignment.worker_id {
+            control_state.watch.notify(worker_id, Notice::Reconnect { conduit: lane.name.clone(), shard: *shard });
+        }
+    }
+
+    let shards: Vec<Shard> = moved.into_iter().filter(|(_, assignment)| !assignment.is_reserved()).map(|(shard, assignment)| Shard::new(shard.to_string(), assignment.transport())).collect();
+    if !shards.is_empty() {
+        let response = control_state.metrics.helix("update_conduit_shards", control_state.twitch.update_shards(&conduit.id, &shards, &app_token)).await?;
+        for error in response.errors {
+            tracing::warn!("failed to move a session to shard {}: {}", error.id, error.message);
+        }
+    }
+
+    Ok(conduit)
+}
+
+/// Grows or shrinks the default conduit to one shard per active worker, within the configured
+/// bounds. A no-op unless `conduit.max_shards` is set.
+pub async fn autoscale(control_state: &ControlState<'_>) -> anyhow::Result<()> {
+    let config = &control_state.config.conduit;
+    if config.max_shards == 0 {
+        return Ok(());
+    }
+
+    let wanted = control_state.workers.lock().await.active().clamp(config.min_shards, config.max_shards);
+    let lane = &control_state.conduits.default;
+    if wanted != lane.conduit.read().await.shard_count {
+        resize(control_state, lane, wanted).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,668 @@
+use crate::allowlist::Cidr;
+use crate::events::Severity;
+use crate::subscription::SubscriptionKind;
+use alloc::collections::BTreeMap;
+use anyhow::Context as _;
+use anyhow::anyhow;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub control: ControlConfig,
+    pub twitch: TwitchConfig,
+    pub conduit: ConduitConfig,
+    pub workers: WorkersConfig,
+    pub store: StoreConfig,
+    pub leader: LeaderConfig,
+    pub tls: TlsConfig,
+    pub audit: AuditConfig,
+    pub secrets: SecretsConfig,
+    pub nats: NatsConfig,
+    pub kafka: KafkaConfig,
+    /// Endpoints notable events are POSTed to, config file only.
+    pub webhooks: Vec<WebhookConfig>,
+    pub discord: DiscordConfig,
+    pub streams: StreamsConfig,
+    pub http: HttpConfig,
+    pub broadcasters: Vec<BroadcasterConfig>,
+    pub subscriptions: Vec<SubscriptionKind>,
+    /// Named sets of subscription types broadcasters can opt into instead of listing their own.
+    pub profiles: BTreeMap<String, Vec<SubscriptionKind>>,
+    /// Conduits of their own for some subscription types, by name; the rest go on `[conduit]`.
+    pub conduits: BTreeMap<String, RoutedConduitConfig>
+}
+
+/// Either a bare login or a table overriding the default subscription types for that channel,
+/// by listing them or naming a profile.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(from = "BroadcasterEntry")]
+pub struct BroadcasterConfig {
+    pub login: String,
+    pub subscriptions: Option<Vec<SubscriptionKind>>,
+    pub profile: Option<String>
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BroadcasterEntry {
+    Login(String),
+    Table {
+        login: String,
+        #[serde(default)]
+        subscriptions: Option<Vec<SubscriptionKind>>,
+        #[serde(default)]
+        profile: Option<String>
+    }
+}
+
+impl From<BroadcasterEntry> for BroadcasterConfig {
+    fn from(entry: BroadcasterEntry) -> Self {
+        match entry {
+            BroadcasterEntry::Login(login) => Self { login, subscriptions: None, profile: None },
+            BroadcasterEntry::Table { login, subscriptions, profile } => Self { login, subscriptions, profile }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControlConfig {
+    pub port: u16,
+    pub token: String,
+    pub shutdown_grace_secs: u64,
+    /// Mutating requests allowed per client token and per IP, or 0 for no limit.
+    pub rate_limit_per_minute: u32,
+    pub rate_limit_burst: u32,
+    /// Where admin routes may be called from; empty allows anywhere.
+    pub admin_allowlist: Vec<Cidr>,
+    /// How long a rotated API key's old secret keeps working, unless the rotation says otherwise.
+    pub key_rotation_grace_secs: u64,
+    /// Where Twitch can reach this server over https, for the webhooks it sends us directly.
+    pub public_url: String
+}
+
+impl Default for ControlConfig {
+    fn default() -> Self {
+        Self {
+            port: 0,
+            token: String::new(),
+            shutdown_grace_secs: 30,
+            rate_limit_per_minute: 600,
+            rate_limit_burst: 100,
+            admin_allowlist: Vec::new(),
+            key_rotation_grace_secs: 600,
+            public_url: String::new()
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TwitchConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    pub user_login: String,
+    /// The bot's own user token, for types it authorizes itself (e.g. as moderator).
+    pub bot_access_token: Option<String>,
+    pub bot_refresh_token: Option<String>,
+    /// Types subscribed for the bot account itself (e.g. whispers to it), independent of any broadcaster.
+    pub bot_subscriptions: Vec<SubscriptionKind>,
+    /// Where Twitch sends broadcasters back to after `/oauth/authorize`, i.e. this server's
+    /// `/oauth/callback`. It must be registered with the app.
+    pub redirect_url: String,
+    /// Bases to call instead of `https://api.twitch.tv/helix/` and `https://id.twitch.tv/oauth2/`,
+    /// e.g. the Twitch CLI's mock server; empty for Twitch.
+    pub helix_url: String,
+    pub auth_url: String,
+    /// Serve an in-process mock of Twitch and call that instead, in builds with the `mock` feature.
+    pub mock: bool
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConduitConfig {
+    /// Pins the conduit to use; otherwise it's the one we created last time, as persisted.
+    pub id: String,
+    /// Create our own conduit when none is pinned or persisted, rather than adopting the first
+    /// one the client id has, which may belong to another deployment sharing the app.
+    pub dedicated: bool,
+    pub shard_count: usize,
+    /// With `max_shards` set, reconciliation sizes the conduit to the active workers, within these
+    /// bounds; `shard_count` is then only the size of a new conduit.
+    pub min_shards: usize,
+    pub max_shards: usize,
+    pub health_check_interval_secs: u64,
+    pub reconcile_interval_secs: u64,
+    /// How many missing subscriptions reconciliation creates at a time.
+    pub create_concurrency: usize,
+    /// Named sets of shards a worker can ask to be assigned from, config file only.
+    pub pools: BTreeMap<String, Vec<usize>>,
+    /// What a worker must have declared to be given a shard of the conduit; empty for any.
+    pub capability: String
+}
+
+impl Default for ConduitConfig {
+    fn default() -> Self {
+        Self {
+            id: String::new(),
+            dedicated: false,
+            shard_count: 1,
+            min_shards: 1,
+            max_shards: 0,
+            health_check_interval_secs: 30,
+            reconcile_interval_secs: 300,
+            create_concurrency: 5,
+            pools: BTreeMap::new(),
+            capability: String::new()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoutedConduitConfig {
+    /// Pins the conduit, as `conduit.id` does.
+    pub id: String,
+    pub shard_count: usize,
+    pub subscriptions: Vec<SubscriptionKind>,
+    /// As `conduit.capability`.
+    pub capability: String
+}
+
+impl Default for RoutedConduitConfig {
+    fn default() -> Self {
+        Self {
+            id: String::new(),
+            shard_count: 1,
+            subscriptions: Vec::new(),
+            capability: String::new()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkersConfig {
+    pub lease_ttl_secs: u64
+}
+
+impl Default for WorkersConfig {
+    fn default() -> Self {
+        Self {
+            lease_ttl_secs: 30
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoreConfig {
+    /// `sqlite://control-plane.db`, `postgres://...`, or empty to keep state in memory only.
+    pub url: String,
+    /// Optionally keeps worker leases, shard assignments and retries in Redis instead.
+    pub redis_url: String,
+    pub flush_interval_secs: u64,
+    /// Helix calls per minute all replicas together stay under, through Redis; 0 to not share
+    /// a budget.
+    pub helix_rate_limit_per_minute: u64
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            redis_url: String::new(),
+            flush_interval_secs: 5,
+            helix_rate_limit_per_minute: 800
+        }
+    }
+}
+
+/// Elects one of several replicas to reconcile, assign shards and write the store; the others
+/// serve reads until they take over.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LeaderConfig {
+    /// `postgres://...` for an advisory lock, `redis://...`, `kubernetes:` for a Lease in the
+    /// pod's namespace, or empty for a lone replica that always leads.
+    pub url: String,
+    /// The lock, key or Lease the replicas contend for.
+    pub name: String,
+    /// This replica's name in the election; falls back to `HOSTNAME`, e.g. the pod name.
+    pub identity: String,
+    /// How long leadership lasts without a renewal.
+    pub lease_ttl_secs: u64,
+    pub renew_interval_secs: u64
+}
+
+impl Default for LeaderConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            name: "firin-bot-control-plane".to_owned(),
+            identity: String::new(),
+            lease_ttl_secs: 15,
+            renew_interval_secs: 5
+        }
+    }
+}
+
+/// Serves HTTPS when `cert_path` is set. With `client_ca_path` workers must also present a client
+/// certificate signed by it.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+    pub client_ca_path: String,
+    /// How often to check the files for rotation and reload them, or 0 to never.
+    pub reload_interval_secs: u64
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    /// File every mutation is appended to as a JSON line, or empty to only keep recent ones.
+    pub path: String,
+    /// Entries kept in memory and the store for `GET /audit`.
+    pub retain: usize
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            retain: 1000
+        }
+    }
+}
+
+/// A KV-over-HTTP secrets backend (e.g. a Vault KV path) for the client secret, control token
+/// and API keys, instead of plaintext config.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecretsConfig {
+    pub url: String,
+    pub token: String,
+    /// How often to refetch, or 0 for only at startup.
+    pub refresh_interval_secs: u64
+}
+
+impl Default for SecretsConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            token: String::new(),
+            refresh_interval_secs: 300
+        }
+    }
+}
+
+/// Publishes control events to NATS when `url` is set.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NatsConfig {
+    pub url: String,
+    /// Events go to `<subject_prefix>.<event>`, e.g. `control.shard_assigned`.
+    pub subject_prefix: String,
+    /// Subjects for some events instead, by event name; config file only.
+    pub subjects: BTreeMap<String, String>
+}
+
+impl Default for NatsConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            subject_prefix: "control".to_owned(),
+            subjects: BTreeMap::new()
+        }
+    }
+}
+
+/// Produces control events and audit entries to Kafka when `brokers` is set.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KafkaConfig {
+    pub brokers: Vec<String>,
+    /// Empty to not produce that stream.
+    pub events_topic: String,
+    pub audit_topic: String,
+    /// Records are sent once this many are waiting, or `linger_ms` after the first of them.
+    pub batch_size: usize,
+    pub linger_ms: u64
+}
+
+impl Default for KafkaConfig {
+    fn default() -> Self {
+        Self {
+            brokers: Vec::new(),
+            events_topic: "control-events".to_owned(),
+            audit_topic: "control-audit".to_owned(),
+            batch_size: 100,
+            linger_ms: 500
+        }
+    }
+}
+
+/// An endpoint events are POSTed to as JSON, signed with an HMAC-SHA256 of the timestamp and
+/// body keyed on `secret`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    pub secret: String,
+    /// Names of the events to send.
+    pub events: Vec<String>
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            secret: String::new(),
+            events: ["shard_unassigned", "broadcaster_revoked", "subscription_revoked", "worker_expired", "token_refresh_failed"].map(str::to_owned).to_vec()
+        }
+    }
+}
+
+/// Posts alerts to Discord webhooks, routed by severity.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiscordConfig {
+    /// Where alerts of at least `min_severity` go, unless `routes` has a webhook for theirs.
+    pub webhook_url: String,
+    pub min_severity: Severity,
+    /// Webhook URLs by severity, config file only.
+    pub routes: BTreeMap<Severity, String>,
+    /// How long a shard goes without a session before it's alerted on.
+    pub shard_down_secs: u64
+}
+
+impl Default for DiscordConfig {
+    fn default() -> Self {
+        Self {
+            webhook_url: String::new(),
+            min_severity: Severity::Warning,
+            routes: BTreeMap::new(),
+            shard_down_secs: 60
+        }
+    }
+}
+
+impl DiscordConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.webhook_url.is_empty() || !self.routes.is_empty()
+    }
+
+    pub fn url_for(&self, severity: Severity) -> Option<&str> {
+        self.routes.get(&severity).map(String::as_str).or_else(|| Some(self.webhook_url.as_str()).filter(|url| !url.is_empty() && severity >= self.min_severity))
+    }
+}
+
+/// Polls stream statuses for broadcasters whose stream.online and stream.offline subscriptions
+/// are missing or on an unhealthy conduit.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamsConfig {
+    /// 0 to only fetch on request.
+    pub poll_interval_secs: u64
+}
+
+/// The client Helix and id.twitch.tv calls go out on.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub connect_timeout_secs: u64,
+    /// Between reads of a response, so a stalled one is given up on.
+    pub read_timeout_secs: u64,
+    /// For a whole request, response included; each retry gets its own.
+    pub timeout_secs: u64,
+    pub pool_idle_timeout_secs: u64,
+    pub pool_max_idle_per_host: usize,
+    /// Proxy for everything, e.g. `http://proxy:3128`; empty for none.
+    pub proxy: String
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: 5,
+            read_timeout_secs: 15,
+            timeout_secs: 30,
+            pool_idle_timeout_secs: 90,
+            pool_max_idle_per_host: 32,
+            proxy: String::new()
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            control: ControlConfig::default(),
+            twitch: TwitchConfig::default(),
+            conduit: ConduitConfig::default(),
+            workers: WorkersConfig::default(),
+            store: StoreConfig::default(),
+            leader: LeaderConfig::default(),
+            tls: TlsConfig::default(),
+            audit: AuditConfig::default(),
+            secrets: SecretsConfig::default(),
+            nats: NatsConfig::default(),
+            kafka: KafkaConfig::default(),
+            webhooks: Vec::new(),
+            discord: DiscordConfig::default(),
+            streams: StreamsConfig::default(),
+            http: HttpConfig::default(),
+            broadcasters: Vec::new(),
+            subscriptions: vec![SubscriptionKind::ChannelChatMessage],
+            profiles: BTreeMap::new(),
+            conduits: BTreeMap::new()
+        }
+    }
+}
+
+impl Config {
+    /// Loads `CONTROL_CONFIG` (or `config.toml` if present) and layers env vars on top.
+    pub fn load() -> anyhow::Result<Self> {
+        let mut config = match std::env::var("CONTROL_CONFIG") {
+            Ok(path) => Self::from_file(&path)?,
+            Err(_) if std::fs::exists("config.toml")? => Self::from_file("config.toml")?,
+            Err(_) => Self::default()
+        };
+
+        config.apply_env()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn from_file(path: &str) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+        toml::from_str(&contents).with_context(|| format!("failed to parse {path}"))
+    }
+
+    fn apply_env(&mut self) -> anyhow::Result<()> {
+        if let Some(port)               = env("CONTROL_PORT"                      )? { self.control.port                       = port.parse().context("invalid CONTROL_PORT")?; }
+        if let Some(token)              = env("CONTROL_HARDCODED_TOKEN"           )? { self.control.token                      = token; }
+        if let Some(shutdown_grace)     = env("CONTROL_SHUTDOWN_GRACE_SECS"       )? { self.control.shutdown_grace_secs        = shutdown_grace.parse().context("invalid CONTROL_SHUTDOWN_GRACE_SECS")?; }
+        if let Some(rate_limit)         = env("CONTROL_RATE_LIMIT_PER_MINUTE"     )? { self.control.rate_limit_per_minute      = rate_limit.parse().context("invalid CONTROL_RATE_LIMIT_PER_MINUTE")?; }
+        if let Some(rate_limit_burst)   = env("CONTROL_RATE_LIMIT_BURST"          )? { self.control.rate_limit_burst           = rate_limit_burst.parse().context("invalid CONTROL_RATE_LIMIT_BURST")?; }
+        if let Some(allowlist)          = env("CONTROL_ADMIN_ALLOWLIST"           )? { self.control.admin_allowlist            = split_list(&allowlist).map(str::parse).collect::<Result<_, _>>().context("invalid CONTROL_ADMIN_ALLOWLIST")?; }
+        if let Some(rotation_grace)     = env("CONTROL_KEY_ROTATION_GRACE_SECS"   )? { self.control.key_rotation_grace_secs    = rotation_grace.parse().context("invalid CONTROL_KEY_ROTATION_GRACE_SECS")?; }
+        if let Some(public_url)         = env("CONTROL_PUBLIC_URL"                )? { self.control.public_url                 = public_url; }
+        if let Some(client_id)          = env("TWITCH_CLIENT_ID"                  )? { self.twitch.client_id                   = client_id; }
+        if let Some(client_secret)      = env("TWITCH_CLIENT_SECRET"              )? { self.twitch.client_secret               = client_secret; }
+        if let Some(user_login)         = env("TWITCH_USER_LOGIN"                 )? { self.twitch.user_login                  = user_login; }
+        if let Some(bot_access_token)   = env("TWITCH_BOT_ACCESS_TOKEN"           )? { self.twitch.bot_access_token            = Some(bot_access_token); }
+        if let Some(bot_refresh_token)  = env("TWITCH_BOT_REFRESH_TOKEN"          )? { self.twitch.bot_refresh_token           = Some(bot_refresh_token); }
+        if let Some(redirect_url)       = env("TWITCH_REDIRECT_URL"               )? { self.twitch.redirect_url                = redirect_url; }
+        if let Some(helix_url)          = env("TWITCH_HELIX_BASE_URL"             )? { self.twitch.helix_url                   = helix_url; }
+        if let Some(auth_url)           = env("TWITCH_AUTH_BASE_URL"              )? { self.twitch.auth_url                    = auth_url; }
+        if let Some(mock)               = env("TWITCH_MOCK"                       )? { self.twitch.mock                        = mock.parse().context("invalid TWITCH_MOCK")?; }
+        if let Some(kinds)              = env("TWITCH_BOT_SUBSCRIPTIONS"          )? { self.twitch.bot_subscriptions           = split_list(&kinds).map(str::parse).collect::<Result<_, _>>().context("invalid TWITCH_BOT_SUBSCRIPTIONS")?; }
+        if let Some(conduit_id)         = env("CONDUIT_ID"                        )? { self.conduit.id                         = conduit_id; }
+        if let Some(dedicated)          = env("CONDUIT_DEDICATED"                 )? { self.conduit.dedicated                  = dedicated.parse().context("invalid CONDUIT_DEDICATED")?; }
+        if let Some(shard_count)        = env("CONDUIT_SHARD_COUNT"               )? { self.conduit.shard_count                = shard_count.parse().context("invalid CONDUIT_SHARD_COUNT")?; }
+        if let Some(min_shards)         = env("CONDUIT_MIN_SHARDS"                )? { self.conduit.min_shards                 = min_shards.parse().context("invalid CONDUIT_MIN_SHARDS")?; }
+        if let Some(max_shards)         = env("CONDUIT_MAX_SHARDS"                )? { self.conduit.max_shards                 = max_shards.parse().context("invalid CONDUIT_MAX_SHARDS")?; }
+        if let Some(health_interval)    = env("CONDUIT_HEALTH_CHECK_INTERVAL_SECS")? { self.conduit.health_check_interval_secs = health_interval.parse().context("invalid CONDUIT_HEALTH_CHECK_INTERVAL_SECS")?; }
+        if let Some(reconcile_interval) = env("CONDUIT_RECONCILE_INTERVAL_SECS"   )? { self.conduit.reconcile_interval_secs    = reconcile_interval.parse().context("invalid CONDUIT_RECONCILE_INTERVAL_SECS")?; }
+        if let Some(create_concurrency) = env("CONDUIT_CREATE_CONCURRENCY"        )? { self.conduit.create_concurrency         = create_concurrency.parse().context("invalid CONDUIT_CREATE_CONCURRENCY")?; }
+        if let Some(capability)         = env("CONDUIT_CAPABILITY"                )? { self.conduit.capability                 = capability; }
+        if let Some(lease_ttl)          = env("WORKER_LEASE_TTL_SECS"             )? { self.workers.lease_ttl_secs             = lease_ttl.parse().context("invalid WORKER_LEASE_TTL_SECS")?; }
+        if let Some(url)                = env("STORE_URL"                         )? { self.store.url                          = url; }
+        if let Some(redis_url)          = env("STORE_REDIS_URL"                   )? { self.store.redis_url                    = redis_url; }
+        if let Some(flush_interval)     = env("STORE_FLUSH_INTERVAL_SECS"         )? { self.store.flush_interval_secs          = flush_interval.parse().context("invalid STORE_FLUSH_INTERVAL_SECS")?; }
+        if let Some(helix_rate_limit)   = env("STORE_HELIX_RATE_LIMIT_PER_MINUTE" )? { self.store.helix_rate_limit_per_minute = helix_rate_limit.parse().context("invalid STORE_HELIX_RATE_LIMIT_PER_MINUTE")?; }
+        if let Some(cert_path)          = env("TLS_CERT_PATH"                     )? { self.tls.cert_path                      = cert_path; }
+        if let Some(key_path)           = env("TLS_KEY_PATH"                      )? { self.tls.key_path                       = key_path; }
+        if let Some(client_ca_path)     = env("TLS_CLIENT_CA_PATH"                )? { self.tls.client_ca_path                 = client_ca_path; }
+        if let Some(reload_interval)    = env("TLS_RELOAD_INTERVAL_SECS"          )? { self.tls.reload_interval_secs           = reload_interval.parse().context("invalid TLS_RELOAD_INTERVAL_SECS")?; }
+        if let Some(path)               = env("AUDIT_PATH"                        )? { self.audit.path                         = path; }
+        if let Some(retain)             = env("AUDIT_RETAIN"                      )? { self.audit.retain                       = retain.parse().context("invalid AUDIT_RETAIN")?; }
+        if let Some(url)                = env("SECRETS_URL"                       )? { self.secrets.url                        = url; }
+        if let Some(token)              = env("SECRETS_TOKEN"                     )? { self.secrets.token                      = token; }
+        if let Some(refresh_interval)   = env("SECRETS_REFRESH_INTERVAL_SECS"     )? { self.secrets.refresh_interval_secs      = refresh_interval.parse().context("invalid SECRETS_REFRESH_INTERVAL_SECS")?; }
+        if let Some(url)                = env("NATS_URL"                          )? { self.nats.url                           = url; }
+        if let Some(subject_prefix)     = env("NATS_SUBJECT_PREFIX"               )? { self.nats.subject_prefix                = subject_prefix; }
+        if let Some(brokers)            = env("KAFKA_BROKERS"                     )? { self.kafka.brokers                      = split_list(&brokers).map(str::to_owned).collect(); }
+        if let Some(events_topic)       = env("KAFKA_EVENTS_TOPIC"                )? { self.kafka.events_topic                 = events_topic; }
+        if let Some(audit_topic)        = env("KAFKA_AUDIT_TOPIC"                 )? { self.kafka.audit_topic                  = audit_topic; }
+        if let Some(batch_size)         = env("KAFKA_BATCH_SIZE"                  )? { self.kafka.batch_size                   = batch_size.parse().context("invalid KAFKA_BATCH_SIZE")?; }
+        if let Some(linger)             = env("KAFKA_LINGER_MS"                   )? { self.kafka.linger_ms                    = linger.parse().context("invalid KAFKA_LINGER_MS")?; }
+        if let Some(webhook_url)        = env("DISCORD_WEBHOOK_URL"               )? { self.discord.webhook_url                = webhook_url; }
+        if let Some(min_severity)       = env("DISCORD_MIN_SEVERITY"              )? { self.discord.min_severity               = min_severity.parse().context("invalid DISCORD_MIN_SEVERITY")?; }
+        if let Some(shard_down)         = env("DISCORD_SHARD_DOWN_SECS"           )? { self.discord.shard_down_secs            = shard_down.parse().context("invalid DISCORD_SHARD_DOWN_SECS")?; }
+        if let Some(poll_interval)      = env("STREAMS_POLL_INTERVAL_SECS"        )? { self.streams.poll_interval_secs         = poll_interval.parse().context("invalid STREAMS_POLL_INTERVAL_SECS")?; }
+        if let Some(leader_url)         = env("LEADER_URL"                        )? { self.leader.url                         = leader_url; }
+        if let Some(leader_name)        = env("LEADER_NAME"                       )? { self.leader.name                        = leader_name; }
+        if let Some(identity)           = env("LEADER_IDENTITY"                   )? { self.leader.identity                    = identity; }
+        if let Some(lease_ttl)          = env("LEADER_LEASE_TTL_SECS"             )? { self.leader.lease_ttl_secs              = lease_ttl.parse().context("invalid LEADER_LEASE_TTL_SECS")?; }
+        if let Some(renew_interval)     = env("LEADER_RENEW_INTERVAL_SECS"        )? { self.leader.renew_interval_secs         = renew_interval.parse().context("invalid LEADER_RENEW_INTERVAL_SECS")?; }
+        if let Some(connect_timeout)    = env("HTTP_CONNECT_TIMEOUT_SECS"         )? { self.http.connect_timeout_secs          = connect_timeout.parse().context("invalid HTTP_CONNECT_TIMEOUT_SECS")?; }
+        if let Some(read_timeout)       = env("HTTP_READ_TIMEOUT_SECS"            )? { self.http.read_timeout_secs             = read_timeout.parse().context("invalid HTTP_READ_TIMEOUT_SECS")?; }
+        if let Some(timeout)            = env("HTTP_TIMEOUT_SECS"                 )? { self.http.timeout_secs                  = timeout.parse().context("invalid HTTP_TIMEOUT_SECS")?; }
+        if let Some(pool_idle_timeout)  = env("HTTP_POOL_IDLE_TIMEOUT_SECS"       )? { self.http.pool_idle_timeout_secs        = pool_idle_timeout.parse().context("invalid HTTP_POOL_IDLE_TIMEOUT_SECS")?; }
+        if let Some(pool_max_idle)      = env("HTTP_POOL_MAX_IDLE_PER_HOST"       )? { self.http.pool_max_idle_per_host        = pool_max_idle.parse().context("invalid HTTP_POOL_MAX_IDLE_PER_HOST")?; }
+        if let Some(proxy)              = env("HTTP_PROXY_URL"                    )? { self.http.proxy                         = proxy; }
+        if let Some(logins)             = env("TWITCH_BROADCASTER_LOGINS"         )? { self.broadcasters                       = split_list(&logins).map(|login| BroadcasterConfig { login: login.to_owned(), subscriptions: None, profile: None }).collect(); }
+        if let Some(kinds)              = env("TWITCH_SUBSCRIPTIONS"              )? { self.subscriptions                      = split_list(&kinds).map(str::parse).collect::<Result<_, _>>().context("invalid TWITCH_SUBSCRIPTIONS")?; }
+
+        Ok(())
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.control.port == 0                                              { return Err(anyhow!("missing control.port (CONTROL_PORT)")); }
+        if self.control.token.is_empty() && self.secrets.url.is_empty()        { return Err(anyhow!("missing control.token (CONTROL_HARDCODED_TOKEN)")); }
+        if self.control.shutdown_grace_secs == 0                               { return Err(anyhow!("control.shutdown_grace_secs (CONTROL_SHUTDOWN_GRACE_SECS) must be at least 1")); }
+        if self.twitch.client_id.is_empty()                                    { return Err(anyhow!("missing twitch.client_id (TWITCH_CLIENT_ID)")); }
+        if self.twitch.client_secret.is_empty() && self.secrets.url.is_empty() { return Err(anyhow!("missing twitch.client_secret (TWITCH_CLIENT_SECRET)")); }
+        if self.twitch.user_login.is_empty()                                   { return Err(anyhow!("missing twitch.user_login (TWITCH_USER_LOGIN)")); }
+        if self.twitch.mock && !cfg!(feature = "mock")                         { return Err(anyhow!("twitch.mock (TWITCH_MOCK) needs a build with the mock feature")); }
+        if self.conduit.shard_count == 0                                       { return Err(anyhow!("conduit.shard_count (CONDUIT_SHARD_COUNT) must be at least 1")); }
+        if self.conduit.health_check_interval_secs == 0                        { return Err(anyhow!("conduit.health_check_interval_secs (CONDUIT_HEALTH_CHECK_INTERVAL_SECS) must be at least 1")); }
+        if self.conduit.reconcile_interval_secs == 0                           { return Err(anyhow!("conduit.reconcile_interval_secs (CONDUIT_RECONCILE_INTERVAL_SECS) must be at least 1")); }
+        if self.conduit.create_concurrency == 0                                { return Err(anyhow!("conduit.create_concurrency (CONDUIT_CREATE_CONCURRENCY) must be at least 1")); }
+        if self.workers.lease_ttl_secs == 0                                    { return Err(anyhow!("workers.lease_ttl_secs (WORKER_LEASE_TTL_SECS) must be at least 1")); }
+        if self.store.flush_interval_secs == 0                                 { return Err(anyhow!("store.flush_interval_secs (STORE_FLUSH_INTERVAL_SECS) must be at least 1")); }
+        if self.leader.name.is_empty()                                         { return Err(anyhow!("leader.name (LEADER_NAME) can't be empty")); }
+        if self.leader.renew_interval_secs == 0                                { return Err(anyhow!("leader.renew_interval_secs (LEADER_RENEW_INTERVAL_SECS) must be at least 1")); }
+        if self.leader.lease_ttl_secs <= self.leader.renew_interval_secs       { return Err(anyhow!("leader.lease_ttl_secs (LEADER_LEASE_TTL_SECS) must exceed leader.renew_interval_secs (LEADER_RENEW_INTERVAL_SECS)")); }
+        if self.http.connect_timeout_secs == 0                                 { return Err(anyhow!("http.connect_timeout_secs (HTTP_CONNECT_TIMEOUT_SECS) must be at least 1")); }
+        if self.http.read_timeout_secs == 0                                    { return Err(anyhow!("http.read_timeout_secs (HTTP_READ_TIMEOUT_SECS) must be at least 1")); }
+        if self.http.timeout_secs == 0                                         { return Err(anyhow!("http.timeout_secs (HTTP_TIMEOUT_SECS) must be at least 1")); }
+
+        if self.tls.cert_path.is_empty() != self.tls.key_path.is_empty()        { return Err(anyhow!("set both tls.cert_path (TLS_CERT_PATH) and tls.key_path (TLS_KEY_PATH), or neither")); }
+        if self.tls.cert_path.is_empty() && !self.tls.client_ca_path.is_empty() { return Err(anyhow!("tls.client_ca_path (TLS_CLIENT_CA_PATH) needs tls.cert_path (TLS_CERT_PATH)")); }
+
+        if !self.leader.url.is_empty() && (self.store.url.is_empty() || self.store.url.starts_with("memory:")) { return Err(anyhow!("leader.url (LEADER_URL) needs a store.url (STORE_URL) the replicas share")); }
+        if !self.control.public_url.is_empty() && !self.control.public_url.starts_with("https://") { return Err(anyhow!("control.public_url (CONTROL_PUBLIC_URL) must be an https url")); }
+        if !self.twitch.helix_url.is_empty() && !self.twitch.helix_url.starts_with("http")         { return Err(anyhow!("twitch.helix_url (TWITCH_HELIX_BASE_URL) must be an http(s) url")); }
+        if !self.twitch.auth_url.is_empty() && !self.twitch.auth_url.starts_with("http")           { return Err(anyhow!("twitch.auth_url (TWITCH_AUTH_BASE_URL) must be an http(s) url")); }
+
+        if !self.nats.url.is_empty() && self.nats.subject_prefix.is_empty() { return Err(anyhow!("nats.subject_prefix (NATS_SUBJECT_PREFIX) can't be empty")); }
+        if !self.kafka.brokers.is_empty() && self.kafka.batch_size == 0     { return Err(anyhow!("kafka.batch_size (KAFKA_BATCH_SIZE) must be at least 1")); }
+
+        if self.conduit.max_shards > 0 && self.conduit.min_shards == 0                      { return Err(anyhow!("conduit.min_shards (CONDUIT_MIN_SHARDS) must be at least 1")); }
+        if self.conduit.max_shards > 0 && self.conduit.min_shards > self.conduit.max_shards { return Err(anyhow!("conduit.min_shards (CONDUIT_MIN_SHARDS) can't exceed conduit.max_shards (CONDUIT_MAX_SHARDS)")); }
+
+        for (name, shards) in &self.conduit.pools {
+            if shards.is_empty() {
+                return Err(anyhow!("conduit pool {name} has no shards"));
+            }
+        }
+
+        for webhook in &self.webhooks {
+            if !webhook.url.starts_with("https://") && !webhook.url.starts_with("http://") { return Err(anyhow!("webhook url {:?} must be an http or https url", webhook.url)); }
+            if webhook.secret.is_empty()                                                  { return Err(anyhow!("webhook {} needs a secret to sign with", webhook.url)); }
+
+            if let Some(unknown) = webhook.events.iter().find(|event| !crate::events::NAMES.contains(&event.as_str())) {
+                return Err(anyhow!("webhook {} asks for unknown event {unknown}", webhook.url));
+            }
+        }
+
+        for url in self.discord.routes.values().chain(Some(&self.discord.webhook_url).filter(|url| !url.is_empty())) {
+            if !url.starts_with("https://") { return Err(anyhow!("discord webhook url {url:?} must be an https url")); }
+        }
+
+        let mut routed = BTreeMap::new();
+        for (name, conduit) in &self.conduits {
+            if name == crate::conduit::DEFAULT  { return Err(anyhow!("conduits.{name} is reserved for [conduit]")); }
+            if conduit.shard_count == 0         { return Err(anyhow!("conduits.{name}.shard_count must be at least 1")); }
+            if conduit.subscriptions.is_empty() { return Err(anyhow!("conduits.{name} routes no subscription types")); }
+
+            for &kind in &conduit.subscriptions {
+                if let Some(other) = routed.insert(kind, name) {
+                    return Err(anyhow!("{kind} is routed to both conduits.{other} and conduits.{name}"));
+                }
+            }
+        }
+
+        for broadcaster in &self.broadcasters {
+            self.subscription_types(broadcaster.subscriptions.as_deref(), broadcaster.profile.as_deref()).with_context(|| format!("invalid broadcaster {}", broadcaster.login))?;
+        }
+
+        Ok(())
+    }
+
+    /// The name of the conduit `kind` is subscribed on.
+    pub fn conduit_for(&self, kind: SubscriptionKind) -> &str {
+        self.conduits.iter().find(|(_, conduit)| conduit.subscriptions.contains(&kind)).map_or(crate::conduit::DEFAULT, |(name, _)| name.as_str())
+    }
+
+    /// What a worker needs for a shard of the conduit `name`; empty if anything will do.
+    pub fn capability(&self, name: &str) -> &str {
+        self.conduits.get(name).map_or(self.conduit.capability.as_str(), |conduit| conduit.capability.as_str())
+    }
+
+    /// The types a broadcaster gets: its own list, its profile's, or the default list.
+    pub fn subscription_types<'a>(&'a self, subscriptions: Option<&'a [SubscriptionKind]>, profile: Option<&str>) -> anyhow::Result<&'a [SubscriptionKind]> {
+        match (subscriptions, profile) {
+            (Some(_), Some(_)) => Err(anyhow!("set either subscriptions or profile, not both")),
+            (Some(subscriptions), None) => Ok(subscriptions),
+            (None, Some(profile)) => self.profiles.get(profile).map(Vec::as_slice).ok_or_else(|| anyhow!("no such profile {profile}")),
+            (None, None) => Ok(&self.subscriptions)
+        }
+    }
+}
+
+/// `key`, or the contents of the file `<key>_FILE` points at, for secrets mounted by Docker or
+/// Kubernetes. Trailing newlines are trimmed, as editors and `echo` leave them in.
+fn env(key: &str) -> anyhow::Result<Option<String>> {
+    if let Ok(value) = std::env::var(key) {
+        return Ok(Some(value));
+    }
+
+    let Ok(path) = std::env::var(format!("{key}_FILE")) else {
+        return Ok(None);
+    };
+    let value = std::fs::read_to_string(&path).with_context(|| format!("failed to read {key}_FILE ({path})"))?;
+
+    Ok(Some(value.trim_end_matches(['\r', '\n']).to_owned()))
+}
+
+pub fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',').map(str::trim).filter(|item| !item.is_empty())
+}
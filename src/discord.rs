@@ -0,0 +1,163 @@
+use crate::ControlState;
+use crate::config::DiscordConfig;
+use crate::events;
+use crate::events::ControlEvent;
+use crate::events::Severity;
+use crate::monitor::ShardHealth;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::time::Duration;
+use serde::Serialize;
+use std::time::Instant;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize)]
+struct Message {
+    username: &'static str,
+    embeds: [Embed; 1]
+}
+
+#[derive(Serialize)]
+struct Embed {
+    title: String,
+    color: u32,
+    fields: Vec<Field>
+}
+
+#[derive(Serialize)]
+struct Field {
+    name: String,
+    value: String,
+    inline: bool
+}
+
+/// A shard without a session, and whether that's been alerted on yet.
+struct Down {
+    since: Instant,
+    worker_id: Option<String>,
+    reason: &'static str,
+    alerted: bool
+}
+
+/// Posts events to Discord as they happen, and alerts on shards left without a session for
+/// longer than `discord.shard_down_secs`, then again once they're back.
+pub async fn run(control_state: Arc<ControlState<'static>>) -> ! {
+    let config = &control_state.config.discord;
+    let client = reqwest::Client::new();
+    let mut events = control_state.events.subscribe();
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    let mut down: BTreeMap<(String, usize), Down> = BTreeMap::new();
+
+    loop {
+        tokio::select! {
+            stamped = events::next(&mut events, "Discord alerting") => {
+                if let Some(((conduit, shard), was)) = track(&mut down, &stamped.event)
+                    && was.alerted
+                {
+                    let title = format!("Shard {shard} of the {conduit} conduit is back after {}s", was.since.elapsed().as_secs());
+                    post(&control_state, &client, config, Severity::Info, title, Vec::new()).await;
+                }
+
+                let severity = stamped.event.severity();
+                post(&control_state, &client, config, severity, title(stamped.event.name()), fields(&stamped.event)).await;
+            },
+            _ = interval.tick() => {
+                let threshold = Duration::from_secs(config.shard_down_secs);
+                for ((conduit, shard), down) in down.iter_mut().filter(|(_, down)| !down.alerted && down.since.elapsed() >= threshold) {
+                    down.alerted = true;
+                    let title = format!("Shard {shard} of the {conduit} conduit has had no session for over {}s", threshold.as_secs());
+                    let mut context = vec![field("reason", down.reason.to_owned())];
+                    if let Some(worker_id) = &down.worker_id {
+                        context.push(field("last worker", worker_id.clone()));
+                    }
+                    post(&control_state, &client, config, Severity::Critical, title, context).await;
+                }
+            }
+        }
+    }
+}
+
+/// Follows which shards are without a session, returning one that just got one back.
+fn track(down: &mut BTreeMap<(String, usize), Down>, event: &ControlEvent) -> Option<((String, usize), Down)> {
+    match event {
+        ControlEvent::ShardUnassigned { conduit, shard, worker_id, reason } => {
+            down.entry((conduit.clone(), *shard)).or_insert_with(|| Down { since: Instant::now(), worker_id: worker_id.clone(), reason, alerted: false });
+            None
+        },
+        ControlEvent::ShardHealthChanged { conduit, shard, to: ShardHealth::Unhealthy, .. } => {
+            down.entry((conduit.clone(), *shard)).or_insert_with(|| Down { since: Instant::now(), worker_id: None, reason: "unhealthy", alerted: false });
+            None
+        },
+        ControlEvent::ShardAssigned { conduit, shard, .. } | ControlEvent::ShardHealthChanged { conduit, shard, to: ShardHealth::Healthy, .. } => {
+            down.remove_entry(&(conduit.clone(), *shard))
+        },
+        _ => None
+    }
+}
+
+/// `token_refresh_failed` as "Token refresh failed".
+fn title(name: &str) -> String {
+    let words = name.replace('_', " ");
+    let mut chars = words.chars();
+    chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or_default()
+}
+
+/// The event's own fields, for context.
+fn fields(event: &ControlEvent) -> Vec<Field> {
+    let Ok(serde_json::Value::Object(object)) = serde_json::to_value(event) else {
+        return Vec::new();
+    };
+
+    object.into_iter().filter(|(name, _)| name != "event").map(|(name, value)| {
+        let value = match value {
+            serde_json::Value::String(value) => value,
+            value => value.to_string()
+        };
+        field(&name.replace('_', " "), value)
+    }).collect()
+}
+
+fn field(name: &str, value: String) -> Field {
+    Field {
+        name: name.to_owned(),
+        // Discord rejects empty values and ones over 1024 characters
+        value: if value.is_empty() { "-".to_owned() } else { value.chars().take(1024).collect() },
+        inline: true
+    }
+}
+
+async fn post(control_state: &ControlState<'_>, client: &reqwest::Client, config: &DiscordConfig, severity: Severity, title: String, fields: Vec<Field>) {
+    let Some(url) = config.url_for(severity) else {
+        return;
+    };
+
+    let color = match severity {
+        Severity::Info => 0x0034_98db,
+        Severity::Warning => 0x00f1_c40f,
+        Severity::Critical => 0x00e7_4c3c
+    };
+    let message = Message {
+        username: "Control plane",
+        embeds: [Embed { title, color, fields }]
+    };
+
+    let body = match serde_json::to_vec(&message) {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!("failed to serialize a Discord alert: {e}");
+            return;
+        }
+    };
+    let result = client.post(url).timeout(TIMEOUT).header("Content-Type", "application/json").body(body).send().await.and_then(reqwest::Response::error_for_status);
+
+    let outcome = match result {
+        Ok(_) => "ok",
+        Err(e) => {
+            tracing::warn!("failed to post a Discord alert: {e}");
+            "error"
+        }
+    };
+    control_state.metrics.inc("control_event_publishes_total", &[("sink", "discord"), ("outcome", outcome)]);
+}
@@ -0,0 +1,178 @@
+use crate::ControlState;
+use crate::monitor::ShardHealth;
+use alloc::sync::Arc;
+use anyhow::anyhow;
+use axum::extract::State;
+use axum::response::Sse;
+use axum::response::sse::Event;
+use axum::response::sse::KeepAlive;
+use core::str::FromStr;
+use futures_util::Stream;
+use serde::Deserialize;
+use serde::Serialize;
+use std::time::SystemTime;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Something that happened to the control plane's state, for dashboards to follow live.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ControlEvent {
+    WorkerRegistered { worker_id: String, standby: bool },
+    WorkerExpired { worker_id: String },
+    ShardAssigned { conduit: String, shard: usize, worker_id: Option<String>, session_id: String },
+    ShardUnassigned { conduit: String, shard: usize, worker_id: Option<String>, reason: &'static str },
+    ShardHealthChanged { conduit: String, shard: usize, from: ShardHealth, to: ShardHealth },
+    /// A broadcaster revoked our authorization, taking its subscriptions with it.
+    BroadcasterRevoked { login: String, broadcaster_id: String, types: Vec<&'static str> },
+    /// Twitch revoked a subscription we hold on our own webhook.
+    SubscriptionRevoked { #[serde(rename = "type")] kind: String, id: String, status: String },
+    SubscriptionCreated { #[serde(rename = "type")] kind: &'static str, broadcaster_id: String, id: String },
+    SubscriptionFailed { #[serde(rename = "type")] kind: &'static str, broadcaster_id: String, error: String },
+    /// The bot isn't a moderator in a broadcaster's channel, as found at onboarding or since.
+    BotNotModerator { login: String, broadcaster_id: String },
+    TokenRefreshed { user_id: String, login: String },
+    TokenRefreshFailed { user_id: String, login: String, error: String },
+    AppTokenReplaced,
+    AppTokenReplaceFailed { error: String },
+    /// This replica was elected leader.
+    LeadershipAcquired { identity: String },
+    /// This replica stopped leading, having lost or failed to renew its lease.
+    LeadershipLost { identity: String }
+}
+
+/// How urgently an event needs a human, for routing alerts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical
+}
+
+impl FromStr for Severity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "info" => Ok(Self::Info),
+            "warning" => Ok(Self::Warning),
+            "critical" => Ok(Self::Critical),
+            _ => Err(anyhow!("unknown severity {s}, expected info, warning or critical"))
+        }
+    }
+}
+
+/// Every event's name, for config to be checked against.
+pub const NAMES: &[&str] = &[
+    "worker_registered",
+    "worker_expired",
+    "shard_assigned",
+    "shard_unassigned",
+    "shard_health_changed",
+    "broadcaster_revoked",
+    "subscription_revoked",
+    "subscription_created",
+    "subscription_failed",
+    "bot_not_moderator",
+    "token_refreshed",
+    "token_refresh_failed",
+    "app_token_replaced",
+    "app_token_replace_failed",
+    "leadership_acquired",
+    "leadership_lost"
+];
+
+impl ControlEvent {
+    /// As serialized in `event`.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::WorkerRegistered { .. } => "worker_registered",
+            Self::WorkerExpired { .. } => "worker_expired",
+            Self::ShardAssigned { .. } => "shard_assigned",
+            Self::ShardUnassigned { .. } => "shard_unassigned",
+            Self::ShardHealthChanged { .. } => "shard_health_changed",
+            Self::BroadcasterRevoked { .. } => "broadcaster_revoked",
+            Self::SubscriptionRevoked { .. } => "subscription_revoked",
+            Self::SubscriptionCreated { .. } => "subscription_created",
+            Self::SubscriptionFailed { .. } => "subscription_failed",
+            Self::BotNotModerator { .. } => "bot_not_moderator",
+            Self::TokenRefreshed { .. } => "token_refreshed",
+            Self::TokenRefreshFailed { .. } => "token_refresh_failed",
+            Self::AppTokenReplaced => "app_token_replaced",
+            Self::AppTokenReplaceFailed { .. } => "app_token_replace_failed",
+            Self::LeadershipAcquired { .. } => "leadership_acquired",
+            Self::LeadershipLost { .. } => "leadership_lost"
+        }
+    }
+
+    pub const fn severity(&self) -> Severity {
+        match self {
+            Self::AppTokenReplaceFailed { .. } | Self::SubscriptionRevoked { .. } => Severity::Critical,
+            Self::WorkerExpired { .. } | Self::BroadcasterRevoked { .. } | Self::SubscriptionFailed { .. } | Self::BotNotModerator { .. } | Self::TokenRefreshFailed { .. } | Self::LeadershipLost { .. } => Severity::Warning,
+            _ => Severity::Info
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Stamped {
+    /// Unix seconds.
+    pub at: u64,
+    #[serde(flatten)]
+    pub event: ControlEvent
+}
+
+/// Fans events out to whoever is following; with nobody following they're dropped.
+pub struct Events {
+    sender: broadcast::Sender<Stamped>
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(1024).0
+        }
+    }
+}
+
+impl Events {
+    pub fn publish(&self, event: ControlEvent) {
+        let at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default();
+        // only fails with no receivers
+        drop(self.sender.send(Stamped { at, event }));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Stamped> {
+        self.sender.subscribe()
+    }
+}
+
+/// The next item from a sink's subscription, skipping over any it missed by falling behind. The
+/// senders live as long as the control state, so it's never closed.
+pub async fn next<T: Clone>(receiver: &mut broadcast::Receiver<T>, sink: &str) -> T {
+    loop {
+        match receiver.recv().await {
+            Ok(item) => return item,
+            Err(RecvError::Lagged(skipped)) => tracing::warn!("{sink} fell behind, skipped {skipped} items"),
+            Err(RecvError::Closed) => core::future::pending().await
+        }
+    }
+}
+
+/// Server-sent events of everything published from the moment of connecting.
+pub async fn stream(
+    State(control_state): State<Arc<ControlState<'_>>>
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let events = futures_util::stream::unfold(control_state.events.subscribe(), |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(stamped) => return Some((Event::default().json_data(stamped), receiver)),
+                Err(RecvError::Lagged(skipped)) => tracing::warn!("an event stream fell behind, skipped {skipped} events"),
+                Err(RecvError::Closed) => return None
+            }
+        }
+    });
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
@@ -0,0 +1,97 @@
+pub mod breaker;
+pub mod bucket;
+pub mod retry;
+
+use crate::config::HttpConfig;
+use crate::config::TwitchConfig;
+use anyhow::Context as _;
+use axum::http::StatusCode;
+use core::time::Duration;
+use twitch_api::helix::ClientRequestError;
+use twitch_api::helix::HelixRequestDeleteError;
+use twitch_api::helix::HelixRequestGetError;
+use twitch_api::helix::HelixRequestPatchError;
+use twitch_api::helix::HelixRequestPostError;
+use twitch_api::helix::HelixRequestPutError;
+
+/// The client Twitch calls go out on, timing out rather than waiting on a hung endpoint forever.
+pub fn http_client(config: &HttpConfig) -> anyhow::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .user_agent(twitch_api::client::user_agent(None)?)
+        .redirect(reqwest::redirect::Policy::none())
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .read_timeout(Duration::from_secs(config.read_timeout_secs))
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .pool_idle_timeout(Duration::from_secs(config.pool_idle_timeout_secs))
+        .pool_max_idle_per_host(config.pool_max_idle_per_host);
+    if !config.proxy.is_empty() {
+        builder = builder.proxy(reqwest::Proxy::all(&config.proxy).context("invalid http.proxy (HTTP_PROXY_URL)")?);
+    }
+
+    Ok(builder.build()?)
+}
+
+const HELIX_URL: &str = "https://api.twitch.tv/helix/";
+const AUTH_URL: &str = "https://id.twitch.tv/oauth2/";
+
+/// Where Helix and OAuth calls go instead of Twitch, e.g. the Twitch CLI's `mock-api`.
+#[derive(Clone, Debug, Default)]
+pub struct BaseUrls {
+    /// Twitch's base and its replacement, with trailing slashes.
+    overrides: Vec<(&'static str, String)>
+}
+
+impl From<&TwitchConfig> for BaseUrls {
+    fn from(config: &TwitchConfig) -> Self {
+        let overrides = [(HELIX_URL, &config.helix_url), (AUTH_URL, &config.auth_url)].into_iter()
+            .filter(|(_, url)| !url.is_empty())
+            .map(|(twitch, url)| (twitch, format!("{}/", url.trim_end_matches('/'))))
+            .collect();
+
+        Self { overrides }
+    }
+}
+
+impl BaseUrls {
+    /// `url` moved onto the overridden base, `None` if that isn't overridden.
+    pub fn rewrite(&self, url: &str) -> Option<String> {
+        self.overrides.iter().find_map(|(twitch, base)| Some(format!("{base}{}", url.strip_prefix(twitch)?)))
+    }
+}
+
+/// Whether the request failed before Twitch could answer at all (DNS, connect, TLS, ...).
+pub const fn is_unreachable<RE: core::error::Error + Send + Sync + 'static>(err: &ClientRequestError<RE>) -> bool {
+    matches!(err, ClientRequestError::RequestError(_) | ClientRequestError::HyperError(_))
+}
+
+/// The status Twitch answered an error with, if it answered at all.
+pub const fn status<RE: core::error::Error + Send + Sync + 'static>(err: &ClientRequestError<RE>) -> Option<StatusCode> {
+    match err {
+        ClientRequestError::HelixRequestGetError(HelixRequestGetError::Error { status, .. })
+        | ClientRequestError::HelixRequestPutError(HelixRequestPutError::Error { status, .. })
+        | ClientRequestError::HelixRequestPostError(HelixRequestPostError::Error { status, .. })
+        | ClientRequestError::HelixRequestPatchError(HelixRequestPatchError::Error { status, .. })
+        | ClientRequestError::HelixRequestDeleteError(HelixRequestDeleteError::Error { status, .. }) => Some(*status),
+        _ => None
+    }
+}
+
+/// What Twitch said about an error it answered.
+pub fn message<RE: core::error::Error + Send + Sync + 'static>(err: &ClientRequestError<RE>) -> Option<&str> {
+    match err {
+        ClientRequestError::HelixRequestGetError(HelixRequestGetError::Error { message, .. })
+        | ClientRequestError::HelixRequestPutError(HelixRequestPutError::Error { message, .. })
+        | ClientRequestError::HelixRequestPostError(HelixRequestPostError::Error { message, .. })
+        | ClientRequestError::HelixRequestPatchError(HelixRequestPatchError::Error { message, .. })
+        | ClientRequestError::HelixRequestDeleteError(HelixRequestDeleteError::Error { message, .. }) => Some(message),
+        _ => None
+    }
+}
+
+/// Worth trying again later: Twitch was unreachable, rate limited us, or had a server error.
+/// Anything that isn't a Helix error at all (e.g. a local check) is not.
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<ClientRequestError<reqwest::Error>>().is_some_and(|err| {
+        is_unreachable(err) || status(err).is_some_and(|status| status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error())
+    })
+}
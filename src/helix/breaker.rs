@@ -0,0 +1,86 @@
+use core::time::Duration;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::time::Instant;
+
+/// Consecutive failed attempts before the circuit opens.
+const FAILURE_THRESHOLD: u32 = 5;
+/// How long to fail fast before letting a single probe through.
+const OPEN_FOR: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug)]
+enum BreakerState {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    /// One probe is in flight; everything else keeps failing fast until it answers, or until
+    /// `OPEN_FOR` passes in case it was dropped without an answer.
+    HalfOpen { since: Instant }
+}
+
+/// Stops calling Twitch while it's consistently failing, so callers fail fast instead of
+/// stacking timeouts, and probes for recovery every `OPEN_FOR`.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    state: Mutex<BreakerState>
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(BreakerState::Closed { failures: 0 })
+        }
+    }
+}
+
+impl CircuitBreaker {
+    /// Whether a call may go out now. Moves an expired open circuit to half-open and lets the
+    /// caller through as its probe.
+    pub fn allow(&self) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let allowed = match *state {
+            BreakerState::Closed { .. } => true,
+            BreakerState::Open { until } if Instant::now() >= until => {
+                tracing::info!("probing Twitch after the circuit was open for {OPEN_FOR:?}");
+                *state = BreakerState::HalfOpen { since: Instant::now() };
+                true
+            },
+            BreakerState::HalfOpen { since } if since.elapsed() >= OPEN_FOR => {
+                *state = BreakerState::HalfOpen { since: Instant::now() };
+                true
+            },
+            BreakerState::Open { .. } | BreakerState::HalfOpen { .. } => false
+        };
+        drop(state);
+
+        allowed
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if !matches!(*state, BreakerState::Closed { .. }) {
+            tracing::info!("Twitch recovered, closing the circuit");
+        }
+        *state = BreakerState::Closed { failures: 0 };
+        drop(state);
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let failures = match *state {
+            BreakerState::Closed { failures } => failures + 1,
+            BreakerState::Open { .. } | BreakerState::HalfOpen { .. } => FAILURE_THRESHOLD
+        };
+
+        *state = if failures >= FAILURE_THRESHOLD {
+            tracing::error!("Twitch is failing consistently, opening the circuit for {OPEN_FOR:?}");
+            BreakerState::Open { until: Instant::now() + OPEN_FOR }
+        } else {
+            BreakerState::Closed { failures }
+        };
+        drop(state);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        matches!(*self.state.lock().unwrap_or_else(PoisonError::into_inner), BreakerState::Closed { .. })
+    }
+}
@@ -0,0 +1,164 @@
+use alloc::collections::BTreeMap;
+use core::fmt;
+use core::hash::Hash as _;
+use core::hash::Hasher as _;
+use core::time::Duration;
+use redis::aio::ConnectionManager;
+use std::hash::DefaultHasher;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use twitch_api::client::Request;
+use twitch_api::client::Response;
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    /// Points left, as last reported less what's gone out since.
+    remaining: u64,
+    limit: u64,
+    /// Unix seconds when Twitch has the bucket full again.
+    reset: u64
+}
+
+/// A token bucket in Redis refilling at `per_minute`, spending one token per Helix call. Redis's
+/// clock is the one that counts, so replicas' clocks needn't agree.
+const TAKE: &str = r"
+local capacity = tonumber(ARGV[1])
+local per_ms = capacity / 60000
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'at')
+local tokens = math.min(capacity, (tonumber(state[1]) or capacity) + (now - (tonumber(state[2]) or now)) * per_ms)
+local wait = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+else
+    wait = math.ceil((1 - tokens) / per_ms)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'at', now)
+redis.call('PEXPIRE', KEYS[1], 120000)
+return wait
+";
+
+/// The client id's budget, shared with the other replicas through Redis.
+struct Shared {
+    connection: ConnectionManager,
+    key: String,
+    per_minute: u64
+}
+
+impl fmt::Debug for Shared {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Shared").field("key", &self.key).field("per_minute", &self.per_minute).finish_non_exhaustive()
+    }
+}
+
+impl Shared {
+    /// How long to wait before asking again, `None` once a token is taken. While Redis can't be
+    /// reached calls go ahead, held back only by the local buckets.
+    async fn take(&self) -> Option<Duration> {
+        let result: Result<u64, redis::RedisError> = redis::cmd("EVAL").arg(TAKE).arg(1).arg(&self.key).arg(self.per_minute).query_async(&mut self.connection.clone()).await;
+        match result {
+            Ok(0) => None,
+            Ok(wait_ms) => Some(Duration::from_millis(wait_ms)),
+            Err(e) => {
+                tracing::warn!("failed to take from the shared Helix rate limit, going ahead: {e}");
+                None
+            }
+        }
+    }
+}
+
+/// Helix's rate-limit buckets, one per token, as reported in each response's `Ratelimit-*`
+/// headers. Requests wait for their bucket to refill rather than go out and come back 429.
+/// Replicas sharing a client id also draw from one budget in Redis, when there's one.
+#[derive(Debug, Default)]
+pub struct RateLimits {
+    buckets: Mutex<BTreeMap<u64, Bucket>>,
+    shared: Option<Shared>
+}
+
+impl RateLimits {
+    pub async fn shared(redis_url: &str, client_id: &str, per_minute: u64) -> anyhow::Result<Self> {
+        let connection = ConnectionManager::new(redis::Client::open(redis_url)?).await?;
+
+        Ok(Self {
+            buckets: Mutex::default(),
+            shared: Some(Shared { connection, key: format!("control:helix_rate_limit:{client_id}"), per_minute })
+        })
+    }
+
+    /// Takes a point from the request's bucket, and from the shared budget, waiting for them to
+    /// refill while they're empty.
+    pub async fn acquire(&self, request: &Request) {
+        let Some(key) = key(request) else {
+            return;
+        };
+
+        while let Some(wait) = self.take(key) {
+            tracing::debug!("rate limit bucket empty, holding {} {} for {wait:?}", request.method(), request.uri().path());
+            tokio::time::sleep(wait).await;
+        }
+
+        let Some(shared) = &self.shared else {
+            return;
+        };
+        while let Some(wait) = shared.take().await {
+            tracing::debug!("shared rate limit empty, holding {} {} for {wait:?}", request.method(), request.uri().path());
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// `None` once a point is taken, otherwise how long until the bucket is full again.
+    fn take(&self, key: u64) -> Option<Duration> {
+        let now = now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        let bucket = buckets.get_mut(&key)?;
+
+        // Helix buckets refill over a minute, so one refilled without a response saying so is
+        // taken as full until a minute from now
+        if bucket.reset <= now {
+            bucket.remaining = bucket.limit;
+            bucket.reset = now + 60;
+        }
+        let wait = if bucket.remaining == 0 {
+            Some(Duration::from_secs(bucket.reset.saturating_sub(now)).max(Duration::from_millis(100)))
+        } else {
+            bucket.remaining -= 1;
+            None
+        };
+        drop(buckets);
+
+        wait
+    }
+
+    /// Takes Twitch's word for the bucket the request drew from.
+    pub fn update(&self, request: &Request, response: &Response) {
+        let Some(key) = key(request) else {
+            return;
+        };
+        let header = |name: &str| response.headers().get(name)?.to_str().ok()?.parse().ok();
+        let (Some(limit), Some(remaining), Some(reset)) = (header("ratelimit-limit"), header("ratelimit-remaining"), header("ratelimit-reset")) else {
+            return;
+        };
+
+        self.buckets.lock().unwrap_or_else(PoisonError::into_inner).insert(key, Bucket { remaining, limit, reset });
+    }
+}
+
+/// Buckets are per token, so Helix requests are told apart by a hash of theirs; anything else
+/// (e.g. to id.twitch.tv) isn't limited this way.
+fn key(request: &Request) -> Option<u64> {
+    if !request.uri().path().starts_with("/helix/") {
+        return None;
+    }
+
+    let mut hasher = DefaultHasher::new();
+    request.headers().get("authorization")?.as_bytes().hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default()
+}
@@ -0,0 +1,127 @@
+use alloc::sync::Arc;
+use axum::http::StatusCode;
+use crate::helix::BaseUrls;
+use crate::helix::breaker::CircuitBreaker;
+use crate::helix::bucket::RateLimits;
+use core::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use twitch_api::client::BoxedFuture;
+use twitch_api::client::Bytes;
+use twitch_api::client::Request;
+use twitch_api::client::Response;
+use twitch_api::HttpClient;
+
+const BASE_DELAY: Duration = Duration::from_millis(250);
+const MAX_DELAY: Duration = Duration::from_secs(10);
+/// How long a 429 may ask us to wait before we give up and surface it instead.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+const MAX_ATTEMPTS: u32 = 4;
+
+/// Retries every Twitch call that hit a network error, a 5xx, or a 429 (waiting for
+/// `Ratelimit-Reset`), so one blip doesn't fail a whole request or startup. Non-idempotent calls,
+/// e.g. sending a chat message, may have gone through before a 5xx or a timeout, so they're only
+/// retried on a 429 or when they never connected. While `breaker` is
+/// open, calls fail fast with a synthesized 503 instead of going out, and calls wait out an
+/// empty bucket in `rate_limits` before going out at all. Calls go to `base_urls` where
+/// they're overridden.
+#[derive(Clone, Debug)]
+pub struct RetryClient {
+    inner: reqwest::Client,
+    breaker: Arc<CircuitBreaker>,
+    rate_limits: Arc<RateLimits>,
+    base_urls: BaseUrls
+}
+
+impl RetryClient {
+    pub const fn new(inner: reqwest::Client, breaker: Arc<CircuitBreaker>, rate_limits: Arc<RateLimits>, base_urls: BaseUrls) -> Self {
+        Self { inner, breaker, rate_limits, base_urls }
+    }
+
+    /// `http::Request` isn't `Clone`, but ours always carry an in-memory body. The copy is the one
+    /// that goes out, so it's the one moved onto `base_urls`.
+    fn duplicate(&self, request: &Request) -> Request {
+        let mut copy = Request::new(request.body().clone());
+        *copy.method_mut() = request.method().clone();
+        *copy.uri_mut() = self.base_urls.rewrite(&request.uri().to_string()).and_then(|uri| uri.parse().ok()).unwrap_or_else(|| request.uri().clone());
+        *copy.version_mut() = request.version();
+        *copy.headers_mut() = request.headers().clone();
+        copy
+    }
+}
+
+impl HttpClient for RetryClient {
+    type Error = reqwest::Error;
+
+    fn req(&self, request: Request) -> BoxedFuture<'_, Result<Response, Self::Error>> {
+        Box::pin(async move {
+            let mut attempt = 1;
+            let idempotent = request.method().is_idempotent();
+
+            loop {
+                if !self.breaker.allow() {
+                    return Ok(circuit_open());
+                }
+
+                self.rate_limits.acquire(&request).await;
+                let result = self.inner.req(self.duplicate(&request)).await;
+                if let Ok(response) = &result {
+                    self.rate_limits.update(&request, response);
+                }
+
+                // a 429 still means Twitch is up, so only outages count against the breaker
+                let retry = match &result {
+                    Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                        self.breaker.record_success();
+                        Some(rate_limit_reset(response).unwrap_or_else(|| backoff(attempt)))
+                    },
+                    Ok(response) if response.status().is_server_error() => {
+                        self.breaker.record_failure();
+                        idempotent.then(|| backoff(attempt))
+                    },
+                    Err(e) if !e.is_builder() => {
+                        self.breaker.record_failure();
+                        (idempotent || e.is_connect()).then(|| backoff(attempt))
+                    },
+                    _ => {
+                        self.breaker.record_success();
+                        return result;
+                    }
+                };
+
+                let Some(delay) = retry.filter(|&delay| attempt < MAX_ATTEMPTS && delay <= MAX_RATE_LIMIT_WAIT) else {
+                    return result;
+                };
+
+                match &result {
+                    Ok(response) => tracing::warn!("{} {} answered {}, retrying in {delay:?}", request.method(), request.uri().path(), response.status()),
+                    Err(e) => tracing::warn!("{} {} failed, retrying in {delay:?}: {e}", request.method(), request.uri().path())
+                }
+
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+        })
+    }
+}
+
+/// What Helix itself would answer while it's down, so callers treat it as any other transient error.
+fn circuit_open() -> Response {
+    let mut response = Response::new(Bytes::from_static(br#"{"error":"Service Unavailable","status":503,"message":"circuit open, Twitch has been failing"}"#));
+    *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+    response
+}
+
+/// Time until the bucket refills, from the unix timestamp in `Ratelimit-Reset`.
+fn rate_limit_reset(response: &Response) -> Option<Duration> {
+    let reset: u64 = response.headers().get("ratelimit-reset")?.to_str().ok()?.parse().ok()?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+
+    Some(Duration::from_secs(reset).saturating_sub(now) + Duration::from_millis(100))
+}
+
+/// Doubles from `BASE_DELAY` up to `MAX_DELAY`, with up to 10% jitter.
+fn backoff(attempt: u32) -> Duration {
+    let delay = BASE_DELAY.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1))).min(MAX_DELAY);
+    delay + delay.mul_f64(rand::random::<f64>() / 10.0)
+}
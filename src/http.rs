@@ -0,0 +1,1236 @@
+use alloc::collections::BTreeMap;
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
+use axum::extract::ConnectInfo;
+use axum::extract::MatchedPath;
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::Request;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::middleware;
+use axum::middleware::Next;
+use axum::http::header::RETRY_AFTER;
+use axum::response::IntoResponse as _;
+use axum::response::Response;
+use axum::Json;
+use axum::body::Body;
+use axum::Router;
+use axum::routing::delete;
+use axum::routing::get;
+use axum::routing::patch;
+use axum::routing::post;
+use axum::routing::put;
+use axum_extra::TypedHeader;
+use crate::Broadcaster;
+use crate::BroadcasterState;
+use crate::ControlState;
+use crate::add_broadcaster;
+use crate::delete_subscriptions;
+use crate::revoke_broadcaster;
+use crate::callback;
+use crate::channels;
+use crate::chat;
+use crate::conduit;
+use crate::config;
+use crate::events;
+use crate::keys;
+use crate::metrics;
+use crate::moderation;
+use crate::monitor;
+use crate::oauth;
+use crate::polls;
+use crate::reconcile;
+use crate::store;
+use crate::streams;
+use crate::subscription;
+use crate::watch;
+use crate::whispers;
+use crate::audit::Actor;
+use crate::audit::AuditEntry;
+use crate::budget::CostBudget;
+use crate::events::ControlEvent;
+use crate::conduit::Lane;
+use crate::keys::KeyInfo;
+use crate::keys::KeyScope;
+use crate::monitor::ConduitHealth;
+use crate::retry::RetryEntry;
+use crate::scheduler::AssignError;
+use crate::scheduler::Scheduler;
+use crate::scheduler::ShardAssignment;
+use crate::store::Export;
+use crate::subscription::SubscriptionKind;
+use crate::tokens::TokenInfo;
+use crate::tls::Peer;
+use crate::watch::Notice;
+use crate::workers::Worker;
+use crate::workers::WorkerState;
+use core::slice;
+use core::time::Duration;
+use headers::Authorization;
+use headers::authorization::Bearer;
+use serde::Deserialize;
+use serde::Serialize;
+use std::time::Instant;
+use std::time::SystemTime;
+use tracing::Instrument as _;
+use twitch_api::eventsub::Conduit;
+use twitch_api::eventsub::Shard;
+use twitch_api::eventsub::ShardError;
+use twitch_api::eventsub::ShardResponse;
+use twitch_api::eventsub::ShardStatus;
+use twitch_api::eventsub::Status;
+use twitch_api::eventsub::TransportResponse;
+use twitch_api::twitch_oauth2::AccessToken;
+use twitch_api::twitch_oauth2::ClientSecret;
+use twitch_api::twitch_oauth2::RefreshToken;
+use twitch_api::twitch_oauth2::url::Url;
+use twitch_api::twitch_oauth2::UserToken;
+use twitch_api::types::EventSubId;
+use twitch_api::types::UserId;
+
+/// Matches axum's default body limit, which the `Json` extractors behind the audit log enforce anyway.
+const MAX_AUDITED_BODY: usize = 2 * 1024 * 1024;
+
+#[derive(Deserialize)]
+struct AddBroadcaster {
+    login: String,
+    subscriptions: Option<Vec<SubscriptionKind>>,
+    profile: Option<String>
+}
+
+#[derive(Deserialize)]
+struct AssignRequest {
+    session_id: String,
+    worker_id: Option<String>,
+    shard: Option<usize>,
+    /// One of `conduit.pools`, to be given a shard from it.
+    pool: Option<String>,
+    /// One of `[conduits]`, for a shard of that conduit rather than one the worker's capabilities
+    /// pick, or the default conduit.
+    conduit: Option<String>
+}
+
+#[derive(Serialize)]
+struct AssignResponse {
+    shard: usize,
+    conduit_id: String,
+    client_id: String,
+    client_secret: String,
+    bot_user_id: UserId,
+    shards: Vec<ShardResponse>,
+    errors: Vec<ShardError>,
+    /// For webhook shards, to verify the signatures on what Twitch sends the callback.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    webhook_secret: Option<String>
+}
+
+/// Assigns a shard to a webhook callback, for consumers that can't hold a websocket.
+#[derive(Deserialize)]
+struct WebhookAssignRequest {
+    callback: String,
+    worker_id: Option<String>,
+    shard: Option<usize>,
+    pool: Option<String>,
+    conduit: Option<String>
+}
+
+#[derive(Serialize)]
+struct BudgetStatus {
+    #[serde(flatten)]
+    budget: CostBudget,
+    remaining: Option<usize>
+}
+
+#[derive(Serialize)]
+struct SubscriptionsOverview {
+    total: usize,
+    total_cost: usize,
+    max_total_cost: usize,
+    broadcasters: Vec<BroadcasterSubscriptions>
+}
+
+/// Subscriptions grouped by broadcaster, then type; `broadcaster_id` is absent for ones not
+/// about any broadcaster, `login` for broadcasters not in the registry.
+#[derive(Serialize)]
+struct BroadcasterSubscriptions {
+    broadcaster_id: Option<String>,
+    login: Option<String>,
+    types: BTreeMap<&'static str, Vec<SubscriptionSummary>>
+}
+
+#[derive(Serialize)]
+struct SubscriptionSummary {
+    id: EventSubId,
+    version: String,
+    status: Status,
+    cost: usize,
+    conduit_id: Option<String>
+}
+
+#[derive(Deserialize)]
+struct AuthorizationRevoked {
+    user_id: UserId
+}
+
+#[derive(Deserialize)]
+struct AddToken {
+    access_token: String,
+    refresh_token: Option<String>
+}
+
+#[derive(Deserialize)]
+struct CreateKey {
+    name: String,
+    scope: KeyScope
+}
+
+#[derive(Serialize)]
+struct CreatedKey {
+    #[serde(flatten)]
+    info: KeyInfo,
+    /// Only ever shown here; the store keeps a hash.
+    key: String
+}
+
+#[derive(Serialize)]
+struct WorkerLease {
+    worker_id: String,
+    lease_ttl_secs: u64
+}
+
+#[derive(Deserialize)]
+struct RegisterQuery {
+    #[serde(default)]
+    standby: bool,
+    /// Comma separated.
+    #[serde(default)]
+    capabilities: String
+}
+
+/// Tells a standby worker it's been given a shard, to assign a session to with its worker id.
+#[derive(Serialize)]
+struct HeartbeatResponse {
+    conduit: String,
+    shard: usize
+}
+
+#[derive(Serialize)]
+struct DrainStatus {
+    /// Whether the worker can close its websocket without events being dropped.
+    safe: bool,
+    /// Shards still waiting on a standby worker.
+    pending: Vec<PendingShard>
+}
+
+#[derive(Serialize)]
+struct PendingShard {
+    conduit: String,
+    shard: usize
+}
+
+#[derive(Serialize)]
+struct WorkerStatus {
+    worker_id: String,
+    state: WorkerState,
+    standby: bool,
+    capabilities: BTreeSet<String>,
+    conduit: Option<String>,
+    shard: Option<usize>,
+    last_heartbeat_secs_ago: u64
+}
+
+#[derive(Serialize)]
+struct ConduitStatus {
+    conduit_id: String,
+    shard_count: usize,
+    #[serde(flatten)]
+    health: ConduitHealth
+}
+
+/// A shard as Twitch has it, next to who we assigned it to.
+#[derive(Default, Serialize)]
+struct ShardView {
+    id: usize,
+    /// Absent if Twitch doesn't list the shard.
+    status: Option<ShardStatus>,
+    transport: Option<TransportResponse>,
+    worker_id: Option<String>,
+    session_id: Option<String>,
+    assigned_at: Option<u64>,
+    /// Held for a standby worker that hasn't connected yet.
+    reserved: bool,
+    /// Whether Twitch's transport is the session we assigned, if either side has one.
+    in_sync: bool
+}
+
+#[derive(Serialize)]
+struct Readiness {
+    ready: bool,
+    app_token_valid: bool,
+    bot_token_valid: bool,
+    conduit_ok: bool,
+    twitch_reachable: bool,
+    circuit_closed: bool,
+    /// Informational; followers are ready to serve reads.
+    leader: bool
+}
+
+/// Routes are grouped by the scope they need; see `KeyScope`.
+pub fn router(control_state: Arc<ControlState<'static>>) -> Router {
+    let public = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics));
+
+    // unauthenticated, but the invites and authorizations they use up are the leader's
+    let onboard = Router::new()
+        .route("/oauth/authorize", get(oauth::authorize))
+        .route("/oauth/callback", get(oauth::callback))
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&control_state), require_leader));
+
+    // signed by Twitch rather than bearer-authenticated, and not ours to throttle or audit
+    let callback = Router::new()
+        .route("/eventsub/callback", post(callback::receive))
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&control_state), require_leader));
+
+    let read = Router::new()
+        .route("/conduit/status", get(conduit_status))
+        .route("/conduit/shards", get(conduit_shards))
+        .route("/broadcasters", get(broadcasters_list))
+        .route("/channels/{broadcaster}/stream", get(streams::status))
+        .route("/subscriptions", get(subscriptions_list))
+        .route("/subscriptions/budget", get(subscriptions_budget))
+        .route("/subscriptions/retries", get(subscriptions_retries))
+        .route("/tokens", get(tokens_list))
+        .route("/workers", get(workers_list))
+        .route("/events", get(events::stream))
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&control_state), require_read));
+
+    let assign = Router::new()
+        .route("/session/assign", post(session_assign))
+        .route("/session/webhook", post(webhook_assign))
+        .route("/authorization/revoked", post(authorization_revoked))
+        .route("/workers/register", post(workers_register))
+        .route("/workers/{id}/heartbeat", post(workers_heartbeat))
+        .route("/workers/{id}/drain", post(workers_drain))
+        .route("/assignments/watch", get(watch::stream))
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&control_state), require_leader))
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&control_state), require_assign));
+
+    let act = Router::new()
+        .route("/chat/{broadcaster}/message", post(chat::send_message))
+        .route("/chat/{broadcaster}/announcement", post(chat::send_announcement))
+        .route("/chat/{broadcaster}/messages/{message_id}", delete(chat::delete_message))
+        .route("/chat/{broadcaster}/shoutout/{target}", post(chat::shoutout))
+        .route("/chat/{broadcaster}/settings", patch(chat::update_settings))
+        .route("/chat/{broadcaster}/chatters", get(chat::chatters))
+        .route("/whispers/{user}", post(whispers::send))
+        .route("/channels/{broadcaster}", patch(channels::update))
+        .route("/channels/{broadcaster}/rewards", get(channels::rewards_list).post(channels::rewards_create))
+        .route("/channels/{broadcaster}/rewards/{id}", patch(channels::rewards_update).delete(channels::rewards_delete))
+        .route("/channels/{broadcaster}/clips", post(channels::clip))
+        .route("/channels/{broadcaster}/raid", delete(channels::raid_cancel))
+        .route("/channels/{broadcaster}/raid/{target}", post(channels::raid))
+        .route("/channels/{broadcaster}/vips/{user}", put(channels::add_vip).delete(channels::remove_vip))
+        .route("/channels/{broadcaster}/moderators/{user}", put(channels::add_moderator).delete(channels::remove_moderator))
+        .route("/channels/{broadcaster}/polls", post(polls::create_poll))
+        .route("/channels/{broadcaster}/polls/{id}/end", post(polls::end_poll))
+        .route("/channels/{broadcaster}/predictions", post(polls::create_prediction))
+        .route("/channels/{broadcaster}/predictions/{id}/lock", post(polls::lock_prediction))
+        .route("/channels/{broadcaster}/predictions/{id}/resolve", post(polls::resolve_prediction))
+        .route("/channels/{broadcaster}/predictions/{id}/cancel", post(polls::cancel_prediction))
+        .route("/moderation/{broadcaster}/ban", post(moderation::ban))
+        .route("/moderation/{broadcaster}/ban/{user_id}", delete(moderation::unban))
+        .route("/moderation/{broadcaster}/warnings", post(moderation::warn))
+        .route("/moderation/{broadcaster}/shield_mode", get(moderation::shield_mode).put(moderation::update_shield_mode))
+        .route("/moderation/{broadcaster}/automod", get(moderation::automod).put(moderation::update_automod))
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&control_state), require_leader))
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&control_state), require_act));
+
+    let admin = Router::new()
+        .route("/broadcasters", post(broadcasters_add))
+        .route("/broadcasters/{login}", delete(broadcasters_remove))
+        .route("/state/export", get(state_export))
+        .route("/state/import", post(state_import))
+        .route("/tokens", post(tokens_add))
+        .route("/oauth/device", post(oauth::device))
+        .route("/oauth/invites", post(oauth::invite))
+        .route("/conduit", patch(conduit_resize))
+        .route("/conduit/migrate", post(conduit_migrate))
+        .route("/keys", get(keys_list).post(keys_create))
+        .route("/keys/{id}", delete(keys_revoke))
+        .route("/keys/{id}/rotate", post(keys_rotate))
+        .route("/audit", get(audit_list))
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&control_state), require_leader))
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&control_state), require_admin))
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&control_state), require_allowed));
+
+    public
+        .merge(onboard)
+        .merge(read)
+        .merge(assign)
+        .merge(act)
+        .merge(admin)
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&control_state), audit))
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&control_state), rate_limit))
+        .merge(callback)
+        .route_layer(middleware::from_fn(trace_request))
+        .with_state(control_state)
+}
+
+/// One span per control request, named by route rather than path so ids don't blow up cardinality.
+async fn trace_request(
+    matched: Option<MatchedPath>,
+    request: Request,
+    next: Next
+) -> Response {
+    let route = matched.as_ref().map_or_else(|| request.uri().path(), MatchedPath::as_str).to_owned();
+    let span = tracing::info_span!("control", method = %request.method(), route, status = tracing::field::Empty);
+
+    let response = next.run(request).instrument(span.clone()).await;
+    span.record("status", response.status().as_u16());
+
+    response
+}
+
+/// Rejects requests whose bearer isn't allowed `required`. The configured control token acts as
+/// an admin key, so there's always a way to manage the others. With a client CA configured,
+/// worker routes also need a verified client certificate.
+async fn authorize(
+    control_state: &ControlState<'_>,
+    peer: Peer,
+    bearer: &Bearer,
+    required: KeyScope,
+    request: Request,
+    next: Next
+) -> Result<Response, StatusCode> {
+    if required == KeyScope::Assign && !control_state.config.tls.client_ca_path.is_empty() && !peer.client_verified {
+        control_state.metrics.inc("control_auth_failures_total", &[]);
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let (scope, actor) = if control_state.secrets.is_control_token(bearer.token()).await {
+        (KeyScope::Admin, "control-token".to_owned())
+    } else if let Some(key) = control_state.keys.authenticate(bearer.token()).await {
+        (key.scope, format!("key:{}", key.id))
+    } else {
+        control_state.metrics.inc("control_auth_failures_total", &[]);
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+
+    let mut response = if scope.allows(required) { next.run(request).await } else { StatusCode::FORBIDDEN.into_response() };
+    response.extensions_mut().insert(Actor(actor));
+
+    Ok(response)
+}
+
+/// Records every mutating request, with its body's digest and the actor the auth middleware
+/// identified, in the audit log.
+async fn audit(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    matched: Option<MatchedPath>,
+    request: Request,
+    next: Next
+) -> Response {
+    if request.method().is_safe() {
+        return next.run(request).await;
+    }
+
+    let action = format!("{} {}", request.method(), matched.as_ref().map_or_else(|| request.uri().path(), MatchedPath::as_str));
+    let path = request.uri().path().to_owned();
+
+    let (parts, body) = request.into_parts();
+    let body = match axum::body::to_bytes(body, MAX_AUDITED_BODY).await {
+        Ok(body) => body,
+        Err(_err) => return StatusCode::PAYLOAD_TOO_LARGE.into_response()
+    };
+    let digest = (!body.is_empty()).then(|| keys::digest(&body));
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    let actor = response.extensions().get::<Actor>().map_or_else(|| "anonymous".to_owned(), |actor| actor.0.clone());
+    control_state.audit.record(actor, action, path, digest, response.status().as_u16()).await;
+
+    response
+}
+
+/// Throttles mutating requests per bearer token and per IP, so one misbehaving client can't
+/// flood shard or subscription updates.
+async fn rate_limit(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    ConnectInfo(peer): ConnectInfo<Peer>,
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    request: Request,
+    next: Next
+) -> Response {
+    if request.method().is_safe() {
+        return next.run(request).await;
+    }
+
+    let mut clients = vec![format!("ip:{}", peer.addr.ip())];
+    if let Some(TypedHeader(Authorization(bearer))) = bearer {
+        // hashed, so the limiter doesn't hold on to secrets
+        clients.push(format!("token:{}", keys::digest(bearer.token().as_bytes())));
+    }
+
+    match control_state.rate_limiter.check(&clients) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            control_state.metrics.inc("control_rate_limited_total", &[]);
+            (StatusCode::TOO_MANY_REQUESTS, [(RETRY_AFTER, wait.as_secs().saturating_add(1).to_string())]).into_response()
+        }
+    }
+}
+
+/// Keeps admin routes to `control.admin_allowlist`, checked before the bearer is.
+async fn require_allowed(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    ConnectInfo(peer): ConnectInfo<Peer>,
+    request: Request,
+    next: Next
+) -> Response {
+    let allowlist = &control_state.config.control.admin_allowlist;
+    if allowlist.is_empty() || allowlist.iter().any(|cidr| cidr.contains(peer.addr.ip())) {
+        return next.run(request).await;
+    }
+
+    tracing::warn!("rejected admin request from {}, not in the allowlist", peer.addr.ip());
+    (StatusCode::FORBIDDEN, "admin routes aren't allowed from this address").into_response()
+}
+
+/// Keeps routes that change state to the leader; followers only serve reads.
+async fn require_leader(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    request: Request,
+    next: Next
+) -> Response {
+    if control_state.leader.is_leader() {
+        return next.run(request).await;
+    }
+
+    (StatusCode::SERVICE_UNAVAILABLE, "not the leader").into_response()
+}
+
+async fn require_read(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    ConnectInfo(peer): ConnectInfo<Peer>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    request: Request,
+    next: Next
+) -> Result<Response, StatusCode> {
+    authorize(&control_state, peer, &bearer, KeyScope::Read, request, next).await
+}
+
+async fn require_assign(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    ConnectInfo(peer): ConnectInfo<Peer>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    request: Request,
+    next: Next
+) -> Result<Response, StatusCode> {
+    authorize(&control_state, peer, &bearer, KeyScope::Assign, request, next).await
+}
+
+async fn require_act(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    ConnectInfo(peer): ConnectInfo<Peer>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    request: Request,
+    next: Next
+) -> Result<Response, StatusCode> {
+    authorize(&control_state, peer, &bearer, KeyScope::Act, request, next).await
+}
+
+async fn require_admin(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    ConnectInfo(peer): ConnectInfo<Peer>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    request: Request,
+    next: Next
+) -> Result<Response, StatusCode> {
+    authorize(&control_state, peer, &bearer, KeyScope::Admin, request, next).await
+}
+
+/// Frees every shard Twitch no longer considers enabled, e.g. because its websocket went away.
+async fn reclaim_shards(control_state: &ControlState<'_>, lane: &Lane, scheduler: &mut Scheduler) -> anyhow::Result<()> {
+    let conduit_id = lane.conduit.read().await.id.clone();
+    let shards: Vec<ShardResponse> = control_state.metrics.helix("get_conduit_shards", control_state.twitch.shards(&conduit_id, &control_state.app_token.get().await)).await?;
+
+    // webhook shards are pending until Twitch has verified their callback
+    for shard in shards {
+        if monitor::is_lost(&shard.status)
+            && let Some(assignment) = shard.id.as_str().parse().ok().and_then(|id| scheduler.release(id))
+        {
+            tracing::info!("reclaimed shard {} from session {} ({:?})", shard.id, assignment.session_id, shard.status);
+        }
+    }
+
+    Ok(())
+}
+
+/// Twitch websocket session ids are short url-safe base64-ish tokens.
+fn is_valid_session_id(session_id: &str) -> bool {
+    (1..=256).contains(&session_id.len())
+        && session_id.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'=' | b'+' | b'/'))
+}
+
+/// The first conduit needing a capability the worker declared that has room, or else the default.
+async fn lane_for<'a>(control_state: &'a ControlState<'_>, worker: &Worker, pool: Option<&[usize]>) -> &'a Lane {
+    for lane in control_state.conduits.iter() {
+        let capability = control_state.config.capability(&lane.name);
+        if !capability.is_empty() && worker.capabilities.contains(capability) && lane.scheduler.lock().await.has_free(pool) {
+            return lane;
+        }
+    }
+
+    &control_state.conduits.default
+}
+
+/// `webhook_secret` makes it a webhook shard, with the request's session id as the callback.
+async fn assign_shard(control_state: &ControlState<'_>, request: AssignRequest, webhook_secret: Option<String>) -> Result<(StatusCode, Json<AssignResponse>), StatusCode> {
+    let pool = match request.pool.as_deref() {
+        Some(pool) => Some(control_state.config.conduit.pools.get(pool).map(Vec::as_slice).ok_or(StatusCode::UNPROCESSABLE_ENTITY)?),
+        None => None
+    };
+
+    let worker = match request.worker_id.as_deref() {
+        Some(worker_id) => control_state.workers.lock().await.get(worker_id).cloned(),
+        None => None
+    };
+    let lane = match (request.conduit.as_deref(), &worker) {
+        (None, Some(worker)) => lane_for(control_state, worker, pool).await,
+        (conduit, _) => control_state.conduits.get(conduit).ok_or(StatusCode::NOT_FOUND)?
+    };
+    if let Some(worker) = &worker
+        && !worker.can_run(control_state.config.capability(&lane.name))
+    {
+        control_state.metrics.inc("control_shard_assignments_total", &[("outcome", "incapable")]);
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let mut scheduler = lane.scheduler.lock().await;
+
+    if request.shard.is_none() && !scheduler.has_free(pool) {
+        reclaim_shards(control_state, lane, &mut scheduler).await.map_err(|e| {
+            tracing::error!("{e:?}");
+            StatusCode::BAD_GATEWAY
+        })?;
+    }
+
+    let shard_id = match scheduler.assign(&request.session_id, request.worker_id.as_deref(), request.shard, pool, webhook_secret.clone()) {
+        Ok(shard_id) => shard_id,
+        Err(e) => {
+            let (outcome, status) = match e {
+                AssignError::NoFreeShard => ("no_free_shard", StatusCode::SERVICE_UNAVAILABLE),
+                AssignError::OutOfRange => ("out_of_range", StatusCode::UNPROCESSABLE_ENTITY),
+                AssignError::OutsidePool => ("outside_pool", StatusCode::UNPROCESSABLE_ENTITY),
+                AssignError::Conflict => ("conflict", StatusCode::CONFLICT)
+            };
+            control_state.metrics.inc("control_shard_assignments_total", &[("outcome", outcome)]);
+            return Err(status);
+        }
+    };
+
+    let conduit_id = lane.conduit.read().await.id.clone();
+    let transport = scheduler.assignment(shard_id).map(ShardAssignment::transport).ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let shard = Shard::new(shard_id.to_string(), transport);
+    let response = control_state.metrics.helix("update_conduit_shards", control_state.twitch.update_shards(&conduit_id, slice::from_ref(&shard), &control_state.app_token.get().await)).await.map_err(|e| {
+        tracing::error!("{e:?}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    });
+
+    let ok = response.as_ref().is_ok_and(|response| response.errors.is_empty());
+    if ok {
+        control_state.events.publish(ControlEvent::ShardAssigned { conduit: lane.name.clone(), shard: shard_id, worker_id: request.worker_id.clone(), session_id: request.session_id.clone() });
+    } else {
+        scheduler.release(shard_id);
+    }
+    drop(scheduler);
+
+    control_state.metrics.inc("control_shard_assignments_total", &[("outcome", if ok { "ok" } else { "error" })]);
+
+    let response = response?;
+    if !response.errors.is_empty() {
+        tracing::error!("{:?}", response.errors);
+    }
+
+    Ok((if ok { StatusCode::OK } else { StatusCode::BAD_GATEWAY }, Json(AssignResponse {
+        shard: shard_id,
+        conduit_id: conduit_id.to_string(),
+        client_id: control_state.config.twitch.client_id.clone(),
+        client_secret: control_state.secrets.client_secret().await,
+        bot_user_id: control_state.my_user.id.clone(),
+        shards: response.shards,
+        errors: response.errors,
+        webhook_secret
+    })))
+}
+
+async fn session_assign(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Json(request): Json<AssignRequest>
+) -> Result<(StatusCode, Json<AssignResponse>), StatusCode> {
+    if !is_valid_session_id(&request.session_id) {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    if let Some(worker_id) = &request.worker_id
+        && !control_state.workers.lock().await.is_active(worker_id)
+    {
+        return Err(StatusCode::GONE);
+    }
+
+    let (status, response) = assign_shard(&control_state, request, None).await?;
+    if status.is_success() {
+        tracing::info!("assigned shard {}", response.shard);
+    }
+
+    Ok((status, response))
+}
+
+/// Like `session_assign`, but with a fresh secret each time; Twitch verifies the callback with
+/// a challenge before the shard is enabled.
+async fn webhook_assign(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Json(request): Json<WebhookAssignRequest>
+) -> Result<(StatusCode, Json<AssignResponse>), StatusCode> {
+    // Twitch only delivers to https on the default port
+    let callback = Url::parse(&request.callback).map_err(|_err| StatusCode::UNPROCESSABLE_ENTITY)?;
+    if callback.scheme() != "https" || callback.port().is_some() {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    if let Some(worker_id) = &request.worker_id
+        && !control_state.workers.lock().await.is_active(worker_id)
+    {
+        return Err(StatusCode::GONE);
+    }
+
+    let request = AssignRequest {
+        session_id: request.callback,
+        worker_id: request.worker_id,
+        shard: request.shard,
+        pool: request.pool,
+        conduit: request.conduit
+    };
+    let (status, response) = assign_shard(&control_state, request, Some(keys::secret())).await?;
+    if status.is_success() {
+        tracing::info!("assigned shard {} to a webhook", response.shard);
+    }
+
+    Ok((status, response))
+}
+
+async fn broadcasters_list(
+    State(control_state): State<Arc<ControlState<'_>>>
+) -> Result<Json<Vec<Broadcaster>>, StatusCode> {
+    Ok(Json(control_state.broadcasters.read().await.values().cloned().collect()))
+}
+
+async fn broadcasters_add(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Json(body): Json<AddBroadcaster>
+) -> Result<Json<Broadcaster>, StatusCode> {
+    let login = body.login.trim().to_lowercase();
+    if control_state.broadcasters.read().await.get(&login).is_some_and(|broadcaster| broadcaster.state == BroadcasterState::Active) {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let subscription_types = control_state.config.subscription_types(body.subscriptions.as_deref(), body.profile.as_deref()).map_err(|e| {
+        tracing::warn!("{e:?}");
+        StatusCode::UNPROCESSABLE_ENTITY
+    })?;
+
+    add_broadcaster(&control_state, &login, subscription_types, body.profile.as_deref()).await.map(Json).map_err(|e| {
+        tracing::error!("{e:?}");
+        StatusCode::BAD_GATEWAY
+    })
+}
+
+async fn broadcasters_remove(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(login): Path<String>
+) -> Result<StatusCode, StatusCode> {
+    let login = login.to_lowercase();
+    let guard = control_state.subscription_lock.lock().await;
+    let broadcaster = control_state.broadcasters.read().await.get(&login).cloned().ok_or(StatusCode::NOT_FOUND)?;
+
+    if !delete_subscriptions(&control_state, broadcaster.subscriptions.values()).await {
+        return Err(StatusCode::BAD_GATEWAY);
+    }
+
+    control_state.broadcasters.write().await.remove(&login);
+    control_state.retries.lock().await.forget(&login);
+    drop(guard);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn subscriptions_list(
+    State(control_state): State<Arc<ControlState<'_>>>
+) -> Result<Json<SubscriptionsOverview>, StatusCode> {
+    let listing = subscription::list(&control_state).await.map_err(|e| {
+        tracing::error!("{e:?}");
+        StatusCode::BAD_GATEWAY
+    })?;
+
+    let logins: BTreeMap<String, String> = control_state.broadcasters.read().await.values().map(|broadcaster| (broadcaster.user_id.to_string(), broadcaster.login.clone())).collect();
+
+    let mut grouped: BTreeMap<Option<String>, BTreeMap<&'static str, Vec<SubscriptionSummary>>> = BTreeMap::new();
+    for subscription in listing.subscriptions {
+        let broadcaster_id = subscription::broadcaster_of(&subscription.condition).map(str::to_owned);
+        grouped.entry(broadcaster_id).or_default().entry(subscription.type_.to_str()).or_default().push(SubscriptionSummary {
+            conduit_id: match &subscription.transport {
+                TransportResponse::Conduit(transport) => Some(transport.conduit_id.clone()),
+                _ => None
+            },
+            id: subscription.id,
+            version: subscription.version,
+            status: subscription.status,
+            cost: subscription.cost
+        });
+    }
+
+    Ok(Json(SubscriptionsOverview {
+        total: listing.total,
+        total_cost: listing.total_cost,
+        max_total_cost: listing.max_total_cost,
+        broadcasters: grouped.into_iter().map(|(broadcaster_id, types)| BroadcasterSubscriptions {
+            login: broadcaster_id.as_ref().and_then(|id| logins.get(id).cloned()),
+            broadcaster_id,
+            types
+        }).collect()
+    }))
+}
+
+async fn subscriptions_budget(
+    State(control_state): State<Arc<ControlState<'_>>>
+) -> Result<Json<BudgetStatus>, StatusCode> {
+    let budget = *control_state.budget.read().await;
+    Ok(Json(BudgetStatus {
+        budget,
+        remaining: budget.remaining()
+    }))
+}
+
+async fn subscriptions_retries(
+    State(control_state): State<Arc<ControlState<'_>>>
+) -> Result<Json<Vec<RetryEntry>>, StatusCode> {
+    Ok(Json(control_state.retries.lock().await.iter().cloned().collect()))
+}
+
+/// Workers forward the user.authorization.revoke notifications they receive on the conduit here.
+async fn authorization_revoked(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Json(body): Json<AuthorizationRevoked>
+) -> Result<StatusCode, StatusCode> {
+    let guard = control_state.subscription_lock.lock().await;
+    let Some((_, subscriptions)) = revoke_broadcaster(&control_state, &body.user_id).await else {
+        return Ok(StatusCode::NO_CONTENT);
+    };
+
+    let deleted = delete_subscriptions(&control_state, subscriptions.values()).await;
+    drop(guard);
+
+    if deleted { Ok(StatusCode::NO_CONTENT) } else { Err(StatusCode::BAD_GATEWAY) }
+}
+
+async fn state_export(
+    State(control_state): State<Arc<ControlState<'_>>>
+) -> Result<Json<Export>, StatusCode> {
+    Ok(Json(store::export(&control_state).await))
+}
+
+async fn state_import(
+    State(control_state): State<Arc<ControlState<'static>>>,
+    Json(export): Json<Export>
+) -> Result<StatusCode, StatusCode> {
+    tracing::info!("importing {} broadcasters and {} assignments", export.broadcasters.len(), export.assignments.len());
+    store::import(&control_state, export).await;
+
+    tokio::spawn(async move {
+        if let Err(e) = reconcile::reconcile(&control_state).await {
+            tracing::error!("reconciliation after import failed: {e:?}");
+        }
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn tokens_list(
+    State(control_state): State<Arc<ControlState<'_>>>
+) -> Result<Json<Vec<TokenInfo>>, StatusCode> {
+    Ok(Json(control_state.tokens.list().await))
+}
+
+async fn tokens_add(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Json(body): Json<AddToken>
+) -> Result<Json<TokenInfo>, StatusCode> {
+    let token = UserToken::from_existing(
+        &control_state.client,
+        AccessToken::new(body.access_token),
+        body.refresh_token.map(RefreshToken::new),
+        ClientSecret::new(control_state.secrets.client_secret().await)
+    ).await.map_err(|e| {
+        tracing::warn!("rejected user token: {e:?}");
+        StatusCode::UNPROCESSABLE_ENTITY
+    })?;
+
+    let info = control_state.tokens.insert(token).await;
+    tracing::info!("stored user token for {} ({}) with scopes {:?}", info.login, info.user_id, info.scopes);
+
+    Ok(Json(info))
+}
+
+#[derive(Deserialize)]
+struct AuditQuery {
+    actor: Option<String>,
+    limit: Option<usize>
+}
+
+async fn audit_list(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Query(query): Query<AuditQuery>
+) -> Json<Vec<AuditEntry>> {
+    Json(control_state.audit.recent(query.actor.as_deref(), query.limit.unwrap_or(100)).await)
+}
+
+async fn keys_list(
+    State(control_state): State<Arc<ControlState<'_>>>
+) -> Result<Json<Vec<KeyInfo>>, StatusCode> {
+    Ok(Json(control_state.keys.list().await))
+}
+
+async fn keys_create(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Json(body): Json<CreateKey>
+) -> Result<(StatusCode, Json<CreatedKey>), StatusCode> {
+    let (info, key) = control_state.keys.create(body.name, body.scope).await;
+    tracing::info!("created {:?} api key {} ({})", info.scope, info.name, info.id);
+
+    Ok((StatusCode::CREATED, Json(CreatedKey { info, key })))
+}
+
+#[derive(Deserialize)]
+struct RotateQuery {
+    grace_secs: Option<u64>
+}
+
+async fn keys_rotate(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(id): Path<String>,
+    Query(query): Query<RotateQuery>
+) -> Result<Json<CreatedKey>, StatusCode> {
+    let grace = Duration::from_secs(query.grace_secs.unwrap_or(control_state.config.control.key_rotation_grace_secs));
+    let (info, key) = control_state.keys.rotate(&id, grace).await.ok_or(StatusCode::NOT_FOUND)?;
+    tracing::info!("rotated api key {} ({}), the old secret works for another {}s", info.name, info.id, grace.as_secs());
+
+    Ok(Json(CreatedKey { info, key }))
+}
+
+async fn keys_revoke(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(id): Path<String>
+) -> Result<StatusCode, StatusCode> {
+    if !control_state.keys.revoke(&id).await {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    tracing::info!("revoked api key {id}");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn workers_list(
+    State(control_state): State<Arc<ControlState<'_>>>
+) -> Result<Json<Vec<WorkerStatus>>, StatusCode> {
+    let workers = control_state.workers.lock().await;
+    let now = Instant::now();
+
+    let mut statuses: Vec<WorkerStatus> = workers.iter().map(|worker| WorkerStatus {
+        worker_id: worker.id.clone(),
+        state: worker.state,
+        standby: worker.standby,
+        capabilities: worker.capabilities.clone(),
+        conduit: None,
+        shard: None,
+        last_heartbeat_secs_ago: now.duration_since(worker.last_heartbeat).as_secs()
+    }).collect();
+    drop(workers);
+
+    for lane in control_state.conduits.iter() {
+        let scheduler = lane.scheduler.lock().await;
+        for status in statuses.iter_mut().filter(|status| status.shard.is_none()) {
+            status.shard = scheduler.shard_of_worker(&status.worker_id);
+            status.conduit = status.shard.map(|_| lane.name.clone());
+        }
+        drop(scheduler);
+    }
+
+    Ok(Json(statuses))
+}
+
+async fn workers_register(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Query(query): Query<RegisterQuery>
+) -> Result<Json<WorkerLease>, StatusCode> {
+    let mut workers = control_state.workers.lock().await;
+    let worker = workers.register(query.standby, config::split_list(&query.capabilities).map(str::to_owned).collect());
+    tracing::info!("registered {}worker {} for {:?}", if worker.standby { "standby " } else { "" }, worker.id, worker.capabilities);
+    control_state.events.publish(ControlEvent::WorkerRegistered { worker_id: worker.id.clone(), standby: worker.standby });
+
+    Ok(Json(WorkerLease {
+        worker_id: worker.id,
+        lease_ttl_secs: workers.ttl().as_secs()
+    }))
+}
+
+async fn workers_heartbeat(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(id): Path<String>
+) -> Result<Response, StatusCode> {
+    let heartbeat = control_state.workers.lock().await.heartbeat(&id);
+    match heartbeat {
+        Some(true) => {},
+        Some(false) => return Err(StatusCode::GONE),
+        None => return Err(StatusCode::NOT_FOUND)
+    }
+
+    for lane in control_state.conduits.iter() {
+        let reserved = lane.scheduler.lock().await.reserved_for(&id);
+        if let Some(shard) = reserved {
+            return Ok(Json(HeartbeatResponse { conduit: lane.name.clone(), shard }).into_response());
+        }
+    }
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Picks out one of `[conduits]` by name, or the default conduit without one.
+#[derive(Deserialize)]
+struct ConduitQuery {
+    conduit: Option<String>
+}
+
+/// Hands a worker's shards to standby workers ahead of it shutting down, for rolling deploys.
+/// Call it until it answers `safe`: by then every standby has assigned its session, so Twitch
+/// delivers to them. Shards stay with the worker while there's no standby free to take them.
+async fn workers_drain(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(id): Path<String>
+) -> Result<(StatusCode, Json<DrainStatus>), StatusCode> {
+    let mut workers = control_state.workers.lock().await;
+    let previously = match workers.get(&id) {
+        None => return Err(StatusCode::NOT_FOUND),
+        Some(worker) if worker.state == WorkerState::Expired => return Err(StatusCode::GONE),
+        Some(worker) if worker.state == WorkerState::Draining => worker.handed_over.clone(),
+        // so a worker being drained by an operator hears about it over its watch
+        Some(_) => {
+            control_state.watch.notify(&id, Notice::Drain);
+            Vec::new()
+        }
+    };
+
+    let mut handed_over = Vec::new();
+    let mut pending = Vec::new();
+    for lane in control_state.conduits.iter() {
+        let standbys: Vec<&str> = workers.standbys(control_state.config.capability(&lane.name)).filter(|standby| *standby != id).collect();
+        let mut scheduler = lane.scheduler.lock().await;
+
+        let held: Vec<usize> = scheduler.shards_of_worker(&id).collect();
+        for shard in held {
+            match scheduler.hand_over(shard, &standbys) {
+                Some(standby) => {
+                    tracing::info!("handing shard {shard} of the {} conduit over from draining worker {id} to {standby}", lane.name);
+                    control_state.watch.notify(&standby, Notice::Assign { conduit: lane.name.clone(), shard });
+                    handed_over.push((lane.name.clone(), shard));
+                },
+                None => pending.push(PendingShard { conduit: lane.name.clone(), shard })
+            }
+        }
+
+        // a handed-over shard is done with once its standby assigns a session to it
+        for (_, shard) in previously.iter().chain(&handed_over).filter(|(conduit, _)| *conduit == lane.name) {
+            if scheduler.assignment(*shard).is_some_and(ShardAssignment::is_reserved) {
+                pending.push(PendingShard { conduit: lane.name.clone(), shard: *shard });
+            }
+        }
+        drop(scheduler);
+    }
+
+    workers.drain(&id, handed_over);
+    drop(workers);
+
+    let safe = pending.is_empty();
+    Ok((if safe { StatusCode::OK } else { StatusCode::ACCEPTED }, Json(DrainStatus { safe, pending })))
+}
+
+async fn conduit_status(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Query(query): Query<ConduitQuery>
+) -> Result<Json<ConduitStatus>, StatusCode> {
+    let lane = control_state.conduits.get(query.conduit.as_deref()).ok_or(StatusCode::NOT_FOUND)?;
+    let conduit = lane.conduit.read().await.clone();
+    Ok(Json(ConduitStatus {
+        conduit_id: conduit.id.to_string(),
+        shard_count: conduit.shard_count,
+        health: lane.health.read().await.clone()
+    }))
+}
+
+/// Twitch's live view of the shards overlaid with the scheduler's, in one place for working out
+/// why a shard isn't delivering.
+async fn conduit_shards(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Query(query): Query<ConduitQuery>
+) -> Result<Json<Vec<ShardView>>, StatusCode> {
+    let lane = control_state.conduits.get(query.conduit.as_deref()).ok_or(StatusCode::NOT_FOUND)?;
+    let conduit_id = lane.conduit.read().await.id.clone();
+    let shards: Vec<ShardResponse> = control_state.metrics.helix("get_conduit_shards", control_state.twitch.shards(&conduit_id, &control_state.app_token.get().await)).await.map_err(|e| {
+        tracing::error!("{e:?}");
+        StatusCode::BAD_GATEWAY
+    })?;
+
+    let mut views: BTreeMap<usize, ShardView> = shards.into_iter().filter_map(|shard| Some((shard.id.as_str().parse().ok()?, shard))).map(|(id, shard)| (id, ShardView {
+        id,
+        status: Some(shard.status),
+        transport: Some(shard.transport),
+        ..ShardView::default()
+    })).collect();
+
+    let scheduler = lane.scheduler.lock().await;
+    for (id, assignment) in scheduler.assignments() {
+        let view = views.entry(id).or_insert_with(|| ShardView { id, ..ShardView::default() });
+        view.worker_id.clone_from(&assignment.worker_id);
+        view.assigned_at = assignment.assigned_at;
+        view.reserved = assignment.is_reserved();
+        if !view.reserved {
+            view.session_id = Some(assignment.session_id.clone());
+        }
+    }
+    drop(scheduler);
+
+    for view in views.values_mut() {
+        let live = view.transport.as_ref().and_then(|transport| match transport {
+            TransportResponse::Websocket(transport) => Some(transport.session_id.as_str()),
+            TransportResponse::Webhook(transport) => Some(transport.callback.as_str()),
+            _ => None
+        }).filter(|live| !live.is_empty());
+        view.in_sync = live == view.session_id.as_deref();
+    }
+
+    Ok(Json(views.into_values().collect()))
+}
+
+#[derive(Deserialize)]
+struct ResizeConduit {
+    shard_count: usize
+}
+
+async fn conduit_resize(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Query(query): Query<ConduitQuery>,
+    Json(body): Json<ResizeConduit>
+) -> Result<Json<Conduit>, StatusCode> {
+    if body.shard_count == 0 {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let lane = control_state.conduits.get(query.conduit.as_deref()).ok_or(StatusCode::NOT_FOUND)?;
+    conduit::resize(&control_state, lane, body.shard_count).await.map(Json).map_err(|e| {
+        tracing::error!("{e:?}");
+        StatusCode::BAD_GATEWAY
+    })
+}
+
+async fn conduit_migrate(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Query(query): Query<ConduitQuery>
+) -> Result<Json<Conduit>, StatusCode> {
+    let lane = control_state.conduits.get(query.conduit.as_deref()).ok_or(StatusCode::NOT_FOUND)?;
+    conduit::migrate(&control_state, lane).await.map(Json).map_err(|e| {
+        tracing::error!("failed to migrate the conduit: {e:?}");
+        StatusCode::BAD_GATEWAY
+    })
+}
+
+async fn healthz() -> StatusCode {
+    StatusCode::OK
+}
+
+async fn readyz(
+    State(control_state): State<Arc<ControlState<'_>>>
+) -> (StatusCode, Json<Readiness>) {
+    // the health check runs on an interval, so only trust it while it's fresh
+    let stale_after = control_state.config.conduit.health_check_interval_secs.saturating_mul(3);
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default();
+
+    let mut conduit_ok = true;
+    let mut twitch_reachable = true;
+    for lane in control_state.conduits.iter() {
+        let health = lane.health.read().await;
+        let fresh = health.checked_at.is_some_and(|checked_at| now.saturating_sub(checked_at) <= stale_after);
+        conduit_ok &= fresh && health.error.is_none();
+        twitch_reachable &= fresh && health.twitch_reachable;
+        drop(health);
+    }
+
+    let app_token_valid = control_state.app_token.is_valid().await;
+    let bot_token_valid = control_state.tokens.is_valid(&control_state.my_user.id).await.unwrap_or(true);
+    let circuit_closed = control_state.breaker.is_closed();
+    let ready = app_token_valid && bot_token_valid && conduit_ok && twitch_reachable && circuit_closed;
+
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+
+    (status, Json(Readiness {
+        ready,
+        app_token_valid,
+        bot_token_valid,
+        conduit_ok,
+        twitch_reachable,
+        circuit_closed,
+        leader: control_state.leader.is_leader()
+    }))
+}
+
+async fn metrics(
+    State(control_state): State<Arc<ControlState<'_>>>
+) -> Result<String, StatusCode> {
+    let mut out = String::new();
+    control_state.metrics.render(&mut out).map_err(|_err| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut subscriptions: BTreeMap<&str, usize> = BTreeMap::new();
+    for broadcaster in control_state.broadcasters.read().await.values() {
+        for kind in broadcaster.subscriptions.keys() {
+            *subscriptions.entry(kind.name()).or_default() += 1;
+        }
+    }
+
+    let mut shards: BTreeMap<(&str, String), usize> = BTreeMap::new();
+    for lane in control_state.conduits.iter() {
+        for shard in &lane.health.read().await.shards {
+            let status = serde_json::to_value(&shard.status).ok().and_then(|status| status.as_str().map(str::to_owned)).unwrap_or_default();
+            *shards.entry((lane.name.as_str(), status)).or_default() += 1;
+        }
+    }
+
+    let mut workers: BTreeMap<&str, usize> = BTreeMap::new();
+    for worker in control_state.workers.lock().await.iter() {
+        *workers.entry(worker.state.name()).or_default() += 1;
+    }
+
+    let subscriptions: Vec<_> = subscriptions.into_iter().map(|(kind, count)| (vec![("type", kind)], count)).collect();
+    let shards: Vec<_> = shards.iter().map(|((conduit, status), &count)| (vec![("conduit", *conduit), ("status", status.as_str())], count)).collect();
+    let workers: Vec<_> = workers.into_iter().map(|(state, count)| (vec![("state", state)], count)).collect();
+    let leader = [(vec![("identity", control_state.leader.identity())], usize::from(control_state.leader.is_leader()))];
+
+    let mut render_gauges = || -> core::fmt::Result {
+        metrics::render_gauge(&mut out, "control_subscriptions", "Active EventSub subscriptions, by type.", &subscriptions)?;
+        metrics::render_gauge(&mut out, "control_conduit_shards", "Conduit shards as of the last health check, by conduit and status.", &shards)?;
+        metrics::render_gauge(&mut out, "control_workers", "Registered workers, by lease state.", &workers)?;
+        metrics::render_gauge(&mut out, "control_leader", "Whether this replica is the leader.", &leader)
+    };
+    render_gauges().map_err(|_err| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(out)
+}
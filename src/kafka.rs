@@ -0,0 +1,115 @@
+use crate::ControlState;
+use crate::config::KafkaConfig;
+use crate::events;
+use alloc::sync::Arc;
+use core::time::Duration;
+use kafka::producer::Producer;
+use kafka::producer::Record;
+use kafka::producer::RequiredAcks;
+use serde::Serialize;
+use tokio::time::Instant;
+
+const BASE_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Topic and JSON payload.
+type Batch = Vec<(String, Vec<u8>)>;
+
+/// Produces control events and audit entries to their topics in batches. The producer blocks,
+/// so it runs off the async threads.
+pub async fn run(control_state: Arc<ControlState<'static>>) -> ! {
+    let config = &control_state.config.kafka;
+    let mut events = control_state.events.subscribe();
+    let mut audit = control_state.audit.subscribe();
+    let linger = Duration::from_millis(config.linger_ms);
+
+    let mut producer = Some(connect(config).await);
+    let mut batch = Batch::new();
+    let mut deadline = None;
+    loop {
+        tokio::select! {
+            stamped = events::next(&mut events, "Kafka"), if !config.events_topic.is_empty() => push(&mut batch, &config.events_topic, &stamped),
+            entry = events::next(&mut audit, "Kafka"), if !config.audit_topic.is_empty() => push(&mut batch, &config.audit_topic, &entry),
+            () = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {}
+        }
+
+        if batch.is_empty() {
+            continue;
+        }
+        let deadline_at = *deadline.get_or_insert_with(|| Instant::now() + linger);
+        if batch.len() < config.batch_size && Instant::now() < deadline_at {
+            continue;
+        }
+
+        deadline = None;
+        producer = match producer.take() {
+            Some(producer) => send(&control_state, producer, core::mem::take(&mut batch)).await,
+            None => None
+        };
+        if producer.is_none() {
+            producer = Some(connect(config).await);
+        }
+    }
+}
+
+fn push<T: Serialize>(batch: &mut Batch, topic: &str, item: &T) {
+    match serde_json::to_vec(item) {
+        Ok(payload) => batch.push((topic.to_owned(), payload)),
+        Err(e) => tracing::error!("failed to serialize a record for {topic}: {e}")
+    }
+}
+
+/// Keeps retrying with backoff, as the brokers may come up after us.
+async fn connect(config: &KafkaConfig) -> Producer {
+    let mut delay = BASE_DELAY;
+    loop {
+        let brokers = config.brokers.clone();
+        let result = tokio::task::spawn_blocking(move || Producer::from_hosts(brokers)
+            .with_client_id("firin-bot-control-plane".to_owned())
+            .with_required_acks(RequiredAcks::One)
+            .with_ack_timeout(Duration::from_secs(5))
+            .create()
+        ).await;
+
+        match result {
+            Ok(Ok(producer)) => return producer,
+            Ok(Err(e)) => tracing::error!("failed to connect to Kafka, retrying in {delay:?}: {e}"),
+            Err(e) => tracing::error!("failed to connect to Kafka, retrying in {delay:?}: {e}")
+        }
+
+        tokio::time::sleep(delay).await;
+        delay = delay.saturating_mul(2).min(MAX_DELAY);
+    }
+}
+
+/// Sends a batch, giving the producer back unless it was lost with a panic. A failed batch is
+/// dropped rather than retried, so a Kafka outage can't back the control plane up.
+async fn send(control_state: &ControlState<'_>, producer: Producer, batch: Batch) -> Option<Producer> {
+    let records = u64::try_from(batch.len()).unwrap_or(u64::MAX);
+    let result = tokio::task::spawn_blocking(move || {
+        let mut producer = producer;
+        let records: Vec<Record<'_, (), &[u8]>> = batch.iter().map(|(topic, payload)| Record::from_value(topic.as_str(), payload.as_slice())).collect();
+        let result = producer.send_all(&records);
+        (producer, result.map(|confirms| confirms.iter().flat_map(|confirm| &confirm.partition_confirms).filter(|partition| partition.offset.is_err()).count()))
+    }).await;
+
+    let (producer, outcome) = match result {
+        Ok((producer, Ok(0))) => (Some(producer), "ok"),
+        Ok((producer, Ok(failed))) => {
+            tracing::warn!("Kafka rejected a batch of {records} records on {failed} partitions");
+            (Some(producer), "partial")
+        },
+        Ok((producer, Err(e))) => {
+            tracing::warn!("failed to send a batch of {records} records to Kafka: {e}");
+            (Some(producer), "error")
+        },
+        Err(e) => {
+            tracing::error!("the Kafka producer panicked, reconnecting: {e}");
+            (None, "error")
+        }
+    };
+
+    control_state.metrics.inc("control_kafka_batches_total", &[("outcome", outcome)]);
+    control_state.metrics.add("control_event_publishes_total", &[("sink", "kafka"), ("outcome", outcome)], records);
+    producer
+}
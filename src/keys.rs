@@ -0,0 +1,188 @@
+use alloc::collections::BTreeMap;
+use crate::secrets::ExternalKey;
+use core::fmt::Write as _;
+use core::time::Duration;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest as _;
+use sha2::Sha256;
+use std::time::SystemTime;
+use tokio::sync::RwLock;
+
+const PREFIX: &str = "fbk_";
+
+/// What a key may do. Worker and read-only keys are kept apart, so a leaked worker key can't
+/// enumerate state; admin keys may do anything.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyScope {
+    /// Listing state.
+    Read,
+    /// What workers need: registration, shard assignment, heartbeats and forwarding revocations.
+    Assign,
+    /// Acting on Twitch with the tokens the control plane holds: chat, moderation and channels.
+    Act,
+    /// Changing broadcasters, tokens, state and keys.
+    Admin
+}
+
+impl KeyScope {
+    pub fn allows(self, required: Self) -> bool {
+        self == Self::Admin || self == required
+    }
+}
+
+/// A key as stored: only the hash of its secret, never the secret itself.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub scope: KeyScope,
+    pub hash: String,
+    pub created_at: u64,
+    /// The secret replaced by the last rotation, still accepted until it expires.
+    #[serde(default)]
+    pub previous: Option<RetiredSecret>
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RetiredSecret {
+    pub hash: String,
+    pub expires_at: u64
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct KeyInfo {
+    pub id: String,
+    pub name: String,
+    pub scope: KeyScope,
+    pub created_at: u64,
+    /// When the pre-rotation secret stops working, while it still does.
+    pub previous_expires_at: Option<u64>
+}
+
+impl From<&ApiKey> for KeyInfo {
+    fn from(key: &ApiKey) -> Self {
+        Self {
+            id: key.id.clone(),
+            name: key.name.clone(),
+            scope: key.scope,
+            created_at: key.created_at,
+            previous_expires_at: key.previous.as_ref().map(|previous| previous.expires_at).filter(|expires_at| *expires_at > now())
+        }
+    }
+}
+
+/// API keys for the control endpoints, keyed by id. Tokens look like `fbk_<id>_<secret>`, except
+/// for ones from the secrets backend, which can be anything and are looked up by hash.
+#[derive(Debug, Default)]
+pub struct KeyStore {
+    keys: RwLock<BTreeMap<String, ApiKey>>,
+    external: RwLock<BTreeMap<String, KeyInfo>>
+}
+
+impl KeyStore {
+    /// Returns the new key's info and its token, which can't be recovered later.
+    pub async fn create(&self, name: String, scope: KeyScope) -> (KeyInfo, String) {
+        let id = hex(&rand::random::<[u8; 8]>());
+        let secret = secret();
+
+        let key = ApiKey {
+            id: id.clone(),
+            name,
+            scope,
+            hash: hash(&secret),
+            created_at: now(),
+            previous: None
+        };
+        let info = KeyInfo::from(&key);
+        self.keys.write().await.insert(id.clone(), key);
+
+        (info, format!("{PREFIX}{id}_{secret}"))
+    }
+
+    /// Gives the key a new secret, accepting the old one for `grace` too so clients can move over
+    /// without a coordinated restart. Returns the new token like `create`.
+    pub async fn rotate(&self, id: &str, grace: Duration) -> Option<(KeyInfo, String)> {
+        let secret = secret();
+
+        let mut keys = self.keys.write().await;
+        let key = keys.get_mut(id)?;
+        key.previous = Some(RetiredSecret {
+            hash: core::mem::replace(&mut key.hash, hash(&secret)),
+            expires_at: now().saturating_add(grace.as_secs())
+        });
+        let info = KeyInfo::from(&*key);
+        drop(keys);
+
+        Some((info, format!("{PREFIX}{id}_{secret}")))
+    }
+
+    pub async fn revoke(&self, id: &str) -> bool {
+        self.keys.write().await.remove(id).is_some()
+    }
+
+    pub async fn list(&self) -> Vec<KeyInfo> {
+        let mut keys: Vec<KeyInfo> = self.keys.read().await.values().map(KeyInfo::from).collect();
+        keys.extend(self.external.read().await.values().cloned());
+        keys
+    }
+
+    /// Replaces the keys provisioned in the secrets backend. They aren't persisted.
+    pub async fn set_external(&self, keys: Vec<ExternalKey>) {
+        *self.external.write().await = keys.into_iter().map(|key| (hash(&key.key), KeyInfo {
+            id: format!("external:{}", key.name),
+            name: key.name,
+            scope: key.scope,
+            created_at: 0,
+            previous_expires_at: None
+        })).collect();
+    }
+
+    pub async fn all(&self) -> Vec<ApiKey> {
+        self.keys.read().await.values().cloned().collect()
+    }
+
+    /// Replaces the managed keys, so ones revoked since are gone.
+    pub async fn restore(&self, keys: Vec<ApiKey>) {
+        *self.keys.write().await = keys.into_iter().map(|key| (key.id.clone(), key)).collect();
+    }
+
+    /// The key `token` belongs to, if it's one of ours.
+    pub async fn authenticate(&self, token: &str) -> Option<KeyInfo> {
+        if let Some(key) = self.external.read().await.get(&hash(token)) {
+            return Some(key.clone());
+        }
+
+        let (id, secret) = token.strip_prefix(PREFIX)?.split_once('_')?;
+        let hash = hash(secret);
+        self.keys.read().await.get(id).filter(|key| {
+            key.hash == hash || key.previous.as_ref().is_some_and(|previous| previous.hash == hash && previous.expires_at > now())
+        }).map(KeyInfo::from)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default()
+}
+
+/// A random 64 character hex secret.
+pub fn secret() -> String {
+    hex(&rand::random::<[u8; 32]>())
+}
+
+fn hash(secret: &str) -> String {
+    digest(secret.as_bytes())
+}
+
+/// Hex SHA-256 of `bytes`.
+pub fn digest(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut out, byte| {
+        write!(out, "{byte:02x}").ok();
+        out
+    })
+}
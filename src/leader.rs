@@ -0,0 +1,219 @@
+mod kubernetes;
+mod postgres;
+mod redis;
+
+use crate::ControlState;
+use crate::config::Config;
+use crate::config::LeaderConfig;
+use crate::conduit::DEFAULT;
+use crate::events::ControlEvent;
+use crate::store::Snapshot;
+use crate::store::StateStore;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::pin::Pin;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
+use core::time::Duration;
+use tokio::sync::watch;
+use tokio::time::MissedTickBehavior;
+
+pub type ElectionFuture<'a, T> = Pin<Box<dyn Future<Output = anyhow::Result<T>> + Send + 'a>>;
+
+/// A lease the replicas contend for, held until it goes `lease_ttl_secs` without a renewal.
+pub trait Elector: Send + Sync {
+    /// Takes the lease if it's free, or renews it if it's ours; whether it's ours after.
+    fn acquire(&self) -> ElectionFuture<'_, bool>;
+    /// Frees the lease, so another replica needn't wait out its ttl.
+    fn release(&self) -> ElectionFuture<'_, ()>;
+}
+
+/// Whether this replica leads. Without an elector it's the only one, and always does.
+pub struct Leadership {
+    identity: String,
+    elector: Option<Box<dyn Elector>>,
+    renew_interval: Duration,
+    /// Whether the lease is ours, kept up by [`elect`].
+    elected: watch::Sender<bool>,
+    /// Whether this replica acts on it, which it only does once it's caught up with the store.
+    leading: AtomicBool,
+    resigned: AtomicBool
+}
+
+impl Leadership {
+    /// Picks the backend from `leader.url`'s scheme.
+    pub async fn open(config: &LeaderConfig) -> anyhow::Result<Self> {
+        let identity = if config.identity.is_empty() {
+            std::env::var("HOSTNAME").unwrap_or_else(|_err| crate::keys::hex(&rand::random::<[u8; 8]>()))
+        } else {
+            config.identity.clone()
+        };
+        let ttl = Duration::from_secs(config.lease_ttl_secs);
+
+        let elector: Option<Box<dyn Elector>> = if config.url.is_empty() {
+            None
+        } else if config.url.starts_with("postgres:") || config.url.starts_with("postgresql:") {
+            Some(Box::new(postgres::PostgresElector::connect(&config.url, &config.name).await?))
+        } else if config.url.starts_with("redis:") || config.url.starts_with("rediss:") {
+            Some(Box::new(redis::RedisElector::connect(&config.url, &config.name, &identity, ttl).await?))
+        } else if config.url.starts_with("kubernetes:") {
+            Some(Box::new(kubernetes::KubernetesElector::connect(&config.name, &identity, ttl).await?))
+        } else {
+            return Err(anyhow::anyhow!("unsupported leader url {}, expected postgres:, redis: or kubernetes:", config.url));
+        };
+
+        Ok(Self {
+            elected: watch::Sender::new(elector.is_none()),
+            leading: AtomicBool::new(elector.is_none()),
+            resigned: AtomicBool::new(false),
+            renew_interval: Duration::from_secs(config.renew_interval_secs),
+            identity,
+            elector
+        })
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.leading.load(Ordering::Relaxed)
+    }
+
+    pub const fn is_contested(&self) -> bool {
+        self.elector.is_some()
+    }
+
+    pub fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    /// Steps down on shutdown, so a follower can take over right away.
+    pub async fn release(&self) {
+        let Some(elector) = &self.elector else {
+            return;
+        };
+        self.resigned.store(true, Ordering::Relaxed);
+        self.leading.store(false, Ordering::Relaxed);
+        if !self.elected.send_replace(false) {
+            return;
+        }
+
+        match elector.release().await {
+            Ok(()) => tracing::info!("gave up leadership"),
+            Err(e) => tracing::warn!("failed to give up leadership, the next leader waits out the lease: {e:?}")
+        }
+    }
+}
+
+/// Takes or renews the lease every `leader.renew_interval_secs`, from before bootstrap on. A
+/// renewal that fails, or takes longer than an interval, steps down rather than risk two leaders.
+pub async fn elect(leader: Arc<Leadership>) -> ! {
+    let Some(elector) = &leader.elector else {
+        core::future::pending().await
+    };
+
+    let mut interval = tokio::time::interval(leader.renew_interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        if leader.resigned.load(Ordering::Relaxed) {
+            core::future::pending::<()>().await;
+        }
+
+        let elected = match tokio::time::timeout(leader.renew_interval, elector.acquire()).await {
+            Ok(Ok(elected)) => elected,
+            Ok(Err(e)) => {
+                tracing::warn!("leader election failed: {e:?}");
+                false
+            },
+            Err(_elapsed) => {
+                tracing::warn!("leader election timed out");
+                false
+            }
+        };
+        leader.elected.send_if_modified(|was| core::mem::replace(was, elected) != elected);
+    }
+}
+
+/// The snapshot to bootstrap from. A follower waits for the leader to have stored the conduits
+/// it'd otherwise create, unless it's elected first.
+pub async fn settle(leader: &Leadership, config: &Config, store: &dyn StateStore, mut snapshot: Snapshot) -> anyhow::Result<Snapshot> {
+    let mut elected = leader.elected.subscribe();
+    loop {
+        if *elected.borrow_and_update() {
+            break;
+        }
+        let known = |name: &str, pinned: &str| !pinned.is_empty() || snapshot.conduit_ids.contains_key(name);
+        if known(DEFAULT, &config.conduit.id) && config.conduits.iter().all(|(name, routed)| known(name, &routed.id)) {
+            tracing::info!("{} is following", leader.identity);
+            return Ok(snapshot);
+        }
+
+        tracing::info!("waiting for a leader to create the conduits");
+        tokio::select! {
+            changed = elected.changed() => changed?,
+            () = tokio::time::sleep(leader.renew_interval) => {}
+        }
+        snapshot = store.load().await?;
+    }
+
+    tracing::info!("{} is the leader", leader.identity);
+    leader.leading.store(true, Ordering::Relaxed);
+    if leader.is_contested() {
+        // what another leader saved while we waited
+        snapshot = store.load().await?;
+    }
+    Ok(snapshot)
+}
+
+/// Acts on the lease changing hands once the control plane is up. While following, it reloads
+/// the store as often as the leader saves it, so reads and keys keep up with the leader's.
+pub async fn run(control_state: Arc<ControlState<'static>>) -> ! {
+    let mut elected = control_state.leader.elected.subscribe();
+    let refresh_every = Duration::from_secs(control_state.config.store.flush_interval_secs);
+    loop {
+        let leading = control_state.leader.is_leader();
+        let changed = tokio::select! {
+            // the guard wait_for returns isn't Send, so it's dropped before the select is done
+            changed = async { elected.wait_for(|&elected| elected != leading).await.is_ok() } => changed,
+            () = tokio::time::sleep(refresh_every), if !leading => {
+                if let Err(e) = crate::store::reload(&control_state).await {
+                    tracing::warn!("failed to reload the leader's state: {e:?}");
+                }
+                continue;
+            }
+        };
+        if !changed {
+            core::future::pending::<()>().await;
+        }
+
+        let identity = control_state.leader.identity.clone();
+        if leading {
+            control_state.leader.leading.store(false, Ordering::Relaxed);
+
+            tracing::warn!("{identity} is no longer the leader");
+            control_state.metrics.inc("control_leader_transitions_total", &[("to", "follower")]);
+            control_state.events.publish(ControlEvent::LeadershipLost { identity });
+            continue;
+        }
+
+        // restored before leading, so reconciliation doesn't act on a stale registry
+        // replacing this replica's, so nothing the previous leader removed comes back
+        if let Err(e) = crate::store::reload(&control_state).await {
+            tracing::error!("failed to load the previous leader's state, trying again: {e:?}");
+            tokio::time::sleep(control_state.leader.renew_interval).await;
+            continue;
+        }
+        control_state.leader.leading.store(true, Ordering::Relaxed);
+
+        tracing::info!("{identity} is now the leader");
+        control_state.metrics.inc("control_leader_transitions_total", &[("to", "leader")]);
+        control_state.events.publish(ControlEvent::LeadershipAcquired { identity });
+
+        if !control_state.config.control.public_url.is_empty() {
+            let control_state = Arc::clone(&control_state);
+            tokio::spawn(async move {
+                if let Err(e) = crate::callback::subscribe(&control_state).await {
+                    tracing::error!("failed to subscribe to conduit.shard.disabled: {e:?}");
+                }
+            });
+        }
+    }
+}
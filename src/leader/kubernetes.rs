@@ -0,0 +1,172 @@
+use super::ElectionFuture;
+use super::Elector;
+use crate::calendar;
+use alloc::boxed::Box;
+use anyhow::Context as _;
+use core::time::Duration;
+use reqwest::StatusCode;
+use serde_json::Value;
+use serde_json::json;
+use std::time::Instant;
+use std::time::SystemTime;
+use tokio::sync::Mutex;
+
+const SERVICE_ACCOUNT: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+/// A coordination.k8s.io Lease in the pod's namespace, through the API server with the pod's
+/// service account. Its holder needs `get`, `create` and `update` on leases.
+pub struct KubernetesElector {
+    http: reqwest::Client,
+    /// The lease's URL, and the collection's for creating it.
+    url: String,
+    leases_url: String,
+    namespace: String,
+    name: String,
+    identity: String,
+    ttl: Duration,
+    /// Another holder's record as last seen, and when it was first seen. A lease is only taken
+    /// over once its record hasn't changed for a whole ttl by our clock, so the replicas' clocks
+    /// needn't agree.
+    observed: Mutex<Option<(Value, Instant)>>
+}
+
+impl KubernetesElector {
+    pub async fn connect(name: &str, identity: &str, ttl: Duration) -> anyhow::Result<Self> {
+        let host = std::env::var("KUBERNETES_SERVICE_HOST").context("leader election through a Lease needs to run in a pod (KUBERNETES_SERVICE_HOST)")?;
+        let port = std::env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_err| "443".to_owned());
+        let namespace = tokio::fs::read_to_string(format!("{SERVICE_ACCOUNT}/namespace")).await.context("failed to read the pod's namespace")?;
+        let ca = tokio::fs::read(format!("{SERVICE_ACCOUNT}/ca.crt")).await.context("failed to read the cluster's CA")?;
+
+        let namespace = namespace.trim().to_owned();
+        let leases_url = format!("https://{host}:{port}/apis/coordination.k8s.io/v1/namespaces/{namespace}/leases");
+        Ok(Self {
+            http: reqwest::Client::builder().add_root_certificate(reqwest::Certificate::from_pem(&ca)?).timeout(ttl).build()?,
+            url: format!("{leases_url}/{name}"),
+            leases_url,
+            namespace,
+            name: name.to_owned(),
+            identity: identity.to_owned(),
+            ttl,
+            observed: Mutex::new(None)
+        })
+    }
+
+    /// Projected service account tokens rotate, so it's read for every request.
+    async fn request(&self, method: reqwest::Method, url: &str, body: Option<&Value>) -> anyhow::Result<(StatusCode, Value)> {
+        let token = tokio::fs::read_to_string(format!("{SERVICE_ACCOUNT}/token")).await.context("failed to read the service account token")?;
+
+        let mut request = self.http.request(method, url).bearer_auth(token.trim());
+        if let Some(body) = body {
+            request = request.header("content-type", "application/json").body(body.to_string());
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        let bytes = response.bytes().await?;
+        Ok((status, serde_json::from_slice(&bytes).unwrap_or(Value::Null)))
+    }
+
+    fn lease(&self, resource_version: Option<&str>, spec: Value) -> Value {
+        json!({
+            "apiVersion": "coordination.k8s.io/v1",
+            "kind": "Lease",
+            "metadata": { "name": self.name, "namespace": self.namespace, "resourceVersion": resource_version },
+            "spec": spec
+        })
+    }
+
+    async fn acquire(&self) -> anyhow::Result<bool> {
+        let now = micro_time(SystemTime::now());
+        let ttl_secs = self.ttl.as_secs();
+
+        let (status, lease) = self.request(reqwest::Method::GET, &self.url, None).await?;
+        if status == StatusCode::NOT_FOUND {
+            let spec = json!({ "holderIdentity": self.identity, "leaseDurationSeconds": ttl_secs, "acquireTime": now, "renewTime": now, "leaseTransitions": 0 });
+            let (status, body) = self.request(reqwest::Method::POST, &self.leases_url, Some(&self.lease(None, spec))).await?;
+            return match status {
+                StatusCode::CREATED => Ok(true),
+                StatusCode::CONFLICT => Ok(false),
+                _ => Err(anyhow::anyhow!("failed to create lease {}: {status} {body}", self.name))
+            };
+        }
+        if !status.is_success() {
+            return Err(anyhow::anyhow!("failed to get lease {}: {status} {lease}", self.name));
+        }
+
+        let holder = lease.pointer("/spec/holderIdentity").and_then(Value::as_str).unwrap_or_default();
+        let ours = holder == self.identity;
+        if !ours && !holder.is_empty() {
+            let record = json!([holder, lease.pointer("/spec/renewTime")]);
+            let lease_secs = lease.pointer("/spec/leaseDurationSeconds").and_then(Value::as_u64).unwrap_or(ttl_secs);
+
+            let mut observed = self.observed.lock().await;
+            let seen_at = match &*observed {
+                Some((seen, seen_at)) if *seen == record => *seen_at,
+                _ => observed.insert((record, Instant::now())).1
+            };
+            drop(observed);
+            if seen_at.elapsed() < Duration::from_secs(lease_secs) {
+                return Ok(false);
+            }
+        }
+
+        let transitions = lease.pointer("/spec/leaseTransitions").and_then(Value::as_u64).unwrap_or_default();
+        let spec = json!({
+            "holderIdentity": self.identity,
+            "leaseDurationSeconds": ttl_secs,
+            "acquireTime": if ours { lease.pointer("/spec/acquireTime").cloned().unwrap_or_else(|| json!(now)) } else { json!(now) },
+            "renewTime": now,
+            "leaseTransitions": if ours { transitions } else { transitions.saturating_add(1) }
+        });
+        let resource_version = lease.pointer("/metadata/resourceVersion").and_then(Value::as_str);
+        let (status, body) = self.request(reqwest::Method::PUT, &self.url, Some(&self.lease(resource_version, spec))).await?;
+        match status {
+            StatusCode::OK => Ok(true),
+            // another replica updated it first
+            StatusCode::CONFLICT => Ok(false),
+            _ => Err(anyhow::anyhow!("failed to update lease {}: {status} {body}", self.name))
+        }
+    }
+
+    async fn release(&self) -> anyhow::Result<()> {
+        let (status, lease) = self.request(reqwest::Method::GET, &self.url, None).await?;
+        if !status.is_success() || lease.pointer("/spec/holderIdentity").and_then(Value::as_str) != Some(self.identity.as_str()) {
+            return Ok(());
+        }
+
+        let now = micro_time(SystemTime::now());
+        let spec = json!({
+            "holderIdentity": "",
+            "leaseDurationSeconds": 1,
+            "acquireTime": lease.pointer("/spec/acquireTime"),
+            "renewTime": now,
+            "leaseTransitions": lease.pointer("/spec/leaseTransitions")
+        });
+        let resource_version = lease.pointer("/metadata/resourceVersion").and_then(Value::as_str);
+        let (status, body) = self.request(reqwest::Method::PUT, &self.url, Some(&self.lease(resource_version, spec))).await?;
+        if !status.is_success() {
+            return Err(anyhow::anyhow!("failed to release lease {}: {status} {body}", self.name));
+        }
+        Ok(())
+    }
+}
+
+impl Elector for KubernetesElector {
+    fn acquire(&self) -> ElectionFuture<'_, bool> {
+        Box::pin(self.acquire())
+    }
+
+    fn release(&self) -> ElectionFuture<'_, ()> {
+        Box::pin(self.release())
+    }
+}
+
+/// `at` as a Kubernetes MicroTime, e.g. 2024-05-01T12:00:00.000000Z.
+fn micro_time(at: SystemTime) -> String {
+    let since = at.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs();
+    let (year, month, day) = calendar::civil_from_days(i64::try_from(secs / 86_400).unwrap_or_default());
+    let time = secs % 86_400;
+
+    format!("{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:06}Z", time / 3_600, time % 3_600 / 60, time % 60, since.subsec_micros())
+}
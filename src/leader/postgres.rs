@@ -0,0 +1,81 @@
+use super::ElectionFuture;
+use super::Elector;
+use alloc::boxed::Box;
+use core::str::FromStr as _;
+use sha2::Digest as _;
+use sha2::Sha256;
+use sqlx::Connection as _;
+use sqlx::postgres::PgConnectOptions;
+use sqlx::postgres::PgConnection;
+use tokio::sync::Mutex;
+
+/// A session-level advisory lock, keyed by the election's name. Postgres drops it with the
+/// session, so it's held on a connection of its own rather than one from a pool.
+pub struct PostgresElector {
+    options: PgConnectOptions,
+    key: i64,
+    /// The session, and whether it holds the lock.
+    session: Mutex<Option<(PgConnection, bool)>>
+}
+
+impl PostgresElector {
+    pub async fn connect(url: &str, name: &str) -> anyhow::Result<Self> {
+        let options = PgConnectOptions::from_str(url)?;
+        let connection = PgConnection::connect_with(&options).await?;
+        let digest = Sha256::digest(name.as_bytes());
+        let key = i64::from_be_bytes(*digest.first_chunk::<8>().ok_or_else(|| anyhow::anyhow!("sha256 digests are 32 bytes"))?);
+
+        Ok(Self {
+            options,
+            key,
+            session: Mutex::new(Some((connection, false)))
+        })
+    }
+
+    async fn acquire(&self) -> anyhow::Result<bool> {
+        let mut session = self.session.lock().await;
+        let (connection, held) = match &mut *session {
+            Some(session) => session,
+            None => session.insert((PgConnection::connect_with(&self.options).await?, false))
+        };
+
+        // a held lock stays held as long as the session is alive
+        let result = if *held {
+            sqlx::query_scalar::<_, i32>("SELECT 1").fetch_one(&mut *connection).await.map(|_one| true)
+        } else {
+            sqlx::query_scalar::<_, bool>("SELECT pg_try_advisory_lock($1)").bind(self.key).fetch_one(&mut *connection).await
+        };
+
+        match result {
+            Ok(leading) => {
+                *held = leading;
+                drop(session);
+                Ok(leading)
+            },
+            Err(e) => {
+                // the lock may have gone with the session, so start over with a new one
+                *session = None;
+                drop(session);
+                Err(e.into())
+            }
+        }
+    }
+
+    async fn release(&self) -> anyhow::Result<()> {
+        let session = self.session.lock().await.take();
+        if let Some((connection, _held)) = session {
+            connection.close().await?;
+        }
+        Ok(())
+    }
+}
+
+impl Elector for PostgresElector {
+    fn acquire(&self) -> ElectionFuture<'_, bool> {
+        Box::pin(self.acquire())
+    }
+
+    fn release(&self) -> ElectionFuture<'_, ()> {
+        Box::pin(self.release())
+    }
+}
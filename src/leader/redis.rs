@@ -0,0 +1,66 @@
+use super::ElectionFuture;
+use super::Elector;
+use alloc::boxed::Box;
+use core::time::Duration;
+use redis::aio::ConnectionManager;
+
+/// Takes the key if it's free, or extends it if it's ours; 1 if it's ours after.
+const ACQUIRE: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    redis.call('PEXPIRE', KEYS[1], ARGV[2])
+    return 1
+end
+if redis.call('SET', KEYS[1], ARGV[1], 'NX', 'PX', ARGV[2]) then
+    return 1
+end
+return 0
+";
+
+/// Deletes the key, but only if it's still ours.
+const RELEASE: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+";
+
+/// A key holding the leader's identity, expiring after the lease ttl unless renewed.
+pub struct RedisElector {
+    connection: ConnectionManager,
+    key: String,
+    identity: String,
+    ttl_ms: u128
+}
+
+impl RedisElector {
+    pub async fn connect(url: &str, name: &str, identity: &str, ttl: Duration) -> anyhow::Result<Self> {
+        let connection = ConnectionManager::new(redis::Client::open(url)?).await?;
+
+        Ok(Self {
+            connection,
+            key: format!("control:leader:{name}"),
+            identity: identity.to_owned(),
+            ttl_ms: ttl.as_millis()
+        })
+    }
+
+    async fn acquire(&self) -> anyhow::Result<bool> {
+        let acquired: u64 = redis::cmd("EVAL").arg(ACQUIRE).arg(1).arg(&self.key).arg(&self.identity).arg(self.ttl_ms.to_string()).query_async(&mut self.connection.clone()).await?;
+        Ok(acquired == 1)
+    }
+
+    async fn release(&self) -> anyhow::Result<()> {
+        let _deleted: u64 = redis::cmd("EVAL").arg(RELEASE).arg(1).arg(&self.key).arg(&self.identity).query_async(&mut self.connection.clone()).await?;
+        Ok(())
+    }
+}
+
+impl Elector for RedisElector {
+    fn acquire(&self) -> ElectionFuture<'_, bool> {
+        Box::pin(self.acquire())
+    }
+
+    fn release(&self) -> ElectionFuture<'_, ()> {
+        Box::pin(self.release())
+    }
+}
@@ -0,0 +1,524 @@
+extern crate alloc;
+
+mod allowlist;
+mod audit;
+mod bootstrap;
+mod budget;
+mod calendar;
+mod callback;
+mod channels;
+mod chat;
+mod conduit;
+pub mod config;
+mod discord;
+mod events;
+mod helix;
+mod http;
+mod kafka;
+mod keys;
+mod leader;
+mod metrics;
+#[cfg(any(test, feature = "mock"))]
+mod mock;
+mod moderation;
+mod monitor;
+mod nats;
+mod oauth;
+mod polls;
+mod proxy;
+mod ratelimit;
+mod reconcile;
+mod retry;
+mod scheduler;
+mod secrets;
+mod shutdown;
+mod store;
+mod streams;
+mod subscription;
+mod telemetry;
+#[cfg(test)]
+mod tests;
+mod tls;
+mod tokens;
+pub mod twitch;
+mod users;
+mod watch;
+mod webhooks;
+mod whispers;
+mod workers;
+
+use alloc::collections::BTreeMap;
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
+use anyhow::Context as _;
+use anyhow::anyhow;
+use crate::audit::AuditLog;
+use crate::bootstrap::Bootstrap;
+use crate::budget::CostBudget;
+use crate::config::Config;
+use crate::events::ControlEvent;
+use crate::conduit::Conduits;
+use crate::conduit::Lane;
+use crate::helix::BaseUrls;
+use crate::helix::breaker::CircuitBreaker;
+use crate::helix::bucket::RateLimits;
+use crate::helix::retry::RetryClient;
+use crate::keys::KeyStore;
+use crate::leader::Leadership;
+use crate::metrics::Metrics;
+use crate::oauth::PendingAuthorizations;
+use crate::ratelimit::RateLimiter;
+use crate::retry::RetryQueue;
+use crate::scheduler::Scheduler;
+use crate::secrets::RuntimeSecrets;
+use crate::store::Snapshot;
+use crate::store::StateStore;
+use crate::subscription::SubscriptionKind;
+use crate::tokens::AppToken;
+use crate::tls::Peer;
+use crate::tls::TlsListener;
+use crate::tokens::TokenStore;
+use crate::twitch::TwitchControl;
+use crate::watch::Notice;
+use crate::workers::WorkerRegistry;
+use core::slice;
+use core::time::Duration;
+use serde::Deserialize;
+use serde::Serialize;
+use std::sync::OnceLock;
+use std::time::Instant;
+use tokio::sync::Mutex;
+use tokio::sync::RwLock;
+use twitch_api::helix::users::User;
+use twitch_api::TwitchClient;
+use twitch_api::types::EventSubId;
+use twitch_api::types::UserId;
+
+struct ControlState<'a> {
+    config: Config,
+    metrics: Metrics,
+    client: TwitchClient<'a, RetryClient>,
+    twitch: Box<dyn TwitchControl>,
+    breaker: Arc<CircuitBreaker>,
+    app_token: AppToken,
+    my_user: User,
+    conduits: Conduits,
+    subscription_lock: Mutex<()>,
+    budget: RwLock<CostBudget>,
+    retries: Mutex<RetryQueue>,
+    store: Box<dyn StateStore>,
+    tokens: TokenStore,
+    keys: KeyStore,
+    rate_limiter: RateLimiter,
+    audit: AuditLog,
+    secrets: RuntimeSecrets,
+    oauth: PendingAuthorizations,
+    callback: callback::Receiver,
+    shoutouts: chat::Shoutouts,
+    chatters: chat::Chatters,
+    streams: streams::Streams,
+    users: users::UserCache,
+    whispers: whispers::Whispers,
+    watch: watch::Watch,
+    events: events::Events,
+    leader: Arc<Leadership>,
+    workers: Mutex<WorkerRegistry>,
+    broadcasters: RwLock<BTreeMap<String, Broadcaster>>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum BroadcasterState {
+    Active,
+    /// Authorization was revoked; kept around without subscriptions until re-added.
+    Revoked
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+struct Broadcaster {
+    login: String,
+    user_id: UserId,
+    state: BroadcasterState,
+    profile: Option<String>,
+    subscription_types: BTreeSet<SubscriptionKind>,
+    subscriptions: BTreeMap<SubscriptionKind, EventSubId>,
+    /// Whether the bot moderates the channel, as of the last check that could tell.
+    #[serde(default)]
+    bot_moderator: Option<bool>
+}
+
+/// Runs the control plane from the environment and config file until it's told to shut down.
+pub async fn run() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+    let tracer = telemetry::init()?;
+
+    let result = serve(Config::load()?).await;
+    telemetry::shutdown(tracer);
+
+    result
+}
+
+/// Serves the control plane for `config` until a shutdown signal, for binaries that load their
+/// config and set up tracing themselves.
+pub async fn serve(mut config: Config) -> anyhow::Result<()> {
+    let secrets = if config.secrets.url.is_empty() {
+        None
+    } else {
+        let secrets = secrets::fetch(&config.secrets).await.context("failed to fetch secrets")?;
+        secrets::apply(&mut config, &secrets)?;
+        Some(secrets)
+    };
+
+    #[cfg(feature = "mock")]
+    if config.twitch.mock {
+        let (addr, _twitch) = mock::serve().await.context("failed to start the mock Twitch")?;
+        tracing::warn!("calling the mock Twitch at {addr} rather than Twitch");
+        config.twitch.helix_url = format!("http://{addr}/helix");
+        config.twitch.auth_url = format!("http://{addr}/oauth2");
+    }
+
+    let breaker = Arc::new(CircuitBreaker::default());
+    let rate_limits = if config.store.redis_url.is_empty() || config.store.helix_rate_limit_per_minute == 0 {
+        Arc::new(RateLimits::default())
+    } else {
+        Arc::new(RateLimits::shared(&config.store.redis_url, &config.twitch.client_id, config.store.helix_rate_limit_per_minute).await.context("failed to connect to Redis for the shared rate limit")?)
+    };
+    let http_client = helix::http_client(&config.http).context("failed to build the HTTP client")?;
+    let client = TwitchClient::with_client(RetryClient::new(http_client, Arc::clone(&breaker), rate_limits, BaseUrls::from(&config.twitch)));
+    let twitch: Box<dyn TwitchControl> = Box::new(client.helix.clone());
+    let metrics = Metrics::default();
+
+    // serve right away, degraded until Twitch bootstrap succeeds
+
+    let app = Arc::new(OnceLock::new());
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", config.control.port)).await?;
+
+    let service = bootstrap::router(Arc::clone(&app)).into_make_service_with_connect_info::<Peer>();
+
+    let (stop, mut stopped) = tokio::sync::watch::channel(false);
+    let stopping = async move {
+        stopped.wait_for(|&stopped| stopped).await.ok();
+    };
+
+    let server = match tls::acceptor(&config.tls).context("invalid TLS configuration")? {
+        Some(acceptor) => {
+            let (reloaded, acceptor) = tokio::sync::watch::channel(acceptor);
+            if config.tls.reload_interval_secs > 0 {
+                tokio::spawn(tls::reload(config.tls.clone(), reloaded));
+            }
+            tokio::spawn(axum::serve(TlsListener::new(listener, acceptor), service).with_graceful_shutdown(stopping).into_future())
+        },
+        None => tokio::spawn(axum::serve(listener, service).with_graceful_shutdown(stopping).into_future())
+    };
+
+    let store = store::open(&config).await.context("failed to open store")?;
+    let snapshot = store.load().await.context("failed to load stored state")?;
+
+    // elected before bootstrap, which may create conduits only the leader should
+    let leader = Arc::new(Leadership::open(&config.leader).await.context("failed to set up leader election")?);
+    tokio::spawn(leader::elect(Arc::clone(&leader)));
+    let snapshot = tokio::select! {
+        snapshot = leader::settle(&leader, &config, &*store, snapshot) => snapshot.context("failed to wait for a leader")?,
+        () = shutdown::signal() => {
+            stop.send_replace(true);
+            return Ok(());
+        }
+    };
+
+    let bootstrap = tokio::select! {
+        bootstrap = bootstrap::run(&config, &client, &*twitch, &metrics, leader.is_leader(), &snapshot.conduit_ids) => bootstrap,
+        () = shutdown::signal() => {
+            stop.send_replace(true);
+            return Ok(());
+        }
+    };
+
+    let control_state = control_state(config, client, twitch, breaker, metrics, leader, bootstrap, store, snapshot).await?;
+    if let Some(secrets) = secrets {
+        control_state.keys.set_external(secrets.api_keys).await;
+    }
+
+    tokio::spawn(store::run(Arc::clone(&control_state)));
+    tokio::spawn(reconcile::run(Arc::clone(&control_state)));
+    tokio::spawn(retry::run(Arc::clone(&control_state)));
+    tokio::spawn(expire_leases(Arc::clone(&control_state)));
+    tokio::spawn(monitor::run(Arc::clone(&control_state)));
+    tokio::spawn(tokens::run(Arc::clone(&control_state)));
+    tokio::spawn(tokens::validate(Arc::clone(&control_state)));
+    tokio::spawn(moderation::run(Arc::clone(&control_state)));
+    if !control_state.config.secrets.url.is_empty() && control_state.config.secrets.refresh_interval_secs > 0 {
+        tokio::spawn(secrets::run(Arc::clone(&control_state)));
+    }
+
+    if !control_state.config.nats.url.is_empty() {
+        let client = nats::connect(&control_state.config.nats).await.context("failed to connect to NATS")?;
+        tokio::spawn(nats::run(Arc::clone(&control_state), client));
+    }
+
+    if control_state.config.streams.poll_interval_secs > 0 {
+        tokio::spawn(streams::run(Arc::clone(&control_state)));
+    }
+
+    if control_state.config.discord.is_enabled() {
+        tokio::spawn(discord::run(Arc::clone(&control_state)));
+    }
+    for webhook in &control_state.config.webhooks {
+        tokio::spawn(webhooks::run(Arc::clone(&control_state), webhook.clone()));
+    }
+    if !control_state.config.kafka.brokers.is_empty() {
+        tokio::spawn(kafka::run(Arc::clone(&control_state)));
+    }
+
+    app.set(http::router(Arc::clone(&control_state))).map_err(|_router| anyhow!("router already set"))?;
+
+    // only once the router is up, as Twitch verifies the callback straight away
+    if control_state.leader.is_leader() && !control_state.config.control.public_url.is_empty() {
+        let control_state = Arc::clone(&control_state);
+        tokio::spawn(async move {
+            if let Err(e) = callback::subscribe(&control_state).await {
+                tracing::error!("failed to subscribe to conduit.shard.disabled: {e:?}");
+            }
+        });
+    }
+    tokio::spawn(leader::run(Arc::clone(&control_state)));
+
+    shutdown::signal().await;
+    shutdown::drain(&control_state, stop, server, Duration::from_secs(control_state.config.control.shutdown_grace_secs)).await;
+
+    Ok(())
+}
+
+/// The state the control plane serves from, seeded from config and restored from `snapshot`.
+#[expect(clippy::too_many_arguments, reason = "the parts are built before bootstrap, which needs some of them")]
+async fn control_state(
+    config: Config,
+    client: TwitchClient<'static, RetryClient>,
+    twitch: Box<dyn TwitchControl>,
+    breaker: Arc<CircuitBreaker>,
+    metrics: Metrics,
+    leader: Arc<Leadership>,
+    bootstrap: Bootstrap,
+    store: Box<dyn StateStore>,
+    snapshot: Snapshot
+) -> anyhow::Result<Arc<ControlState<'static>>> {
+    let Bootstrap { app_token, conduit, routed, my_user, bot_token } = bootstrap;
+    let audit = AuditLog::open(&config.audit).await.context("failed to open audit log")?;
+
+    let control_state = Arc::new(ControlState {
+        workers: Mutex::new(WorkerRegistry::new(Duration::from_secs(config.workers.lease_ttl_secs))),
+        rate_limiter: RateLimiter::new(config.control.rate_limit_per_minute, config.control.rate_limit_burst),
+        secrets: RuntimeSecrets::new(&config),
+        oauth: PendingAuthorizations::default(),
+        callback: callback::Receiver::default(),
+        shoutouts: chat::Shoutouts::default(),
+        chatters: chat::Chatters::default(),
+        streams: streams::Streams::default(),
+        users: users::UserCache::default(),
+        whispers: whispers::Whispers::default(),
+        watch: watch::Watch::default(),
+        events: events::Events::default(),
+        leader,
+        config,
+        metrics,
+        client,
+        twitch,
+        breaker,
+        app_token: AppToken::new(app_token),
+        my_user,
+        conduits: Conduits {
+            default: Lane::new(conduit::DEFAULT.to_owned(), conduit),
+            routed: routed.into_iter().map(|(name, conduit)| (name.clone(), Lane::new(name, conduit))).collect()
+        },
+        subscription_lock: Mutex::new(()),
+        budget: RwLock::new(CostBudget::default()),
+        retries: Mutex::new(RetryQueue::default()),
+        store,
+        tokens: TokenStore::default(),
+        keys: KeyStore::default(),
+        audit,
+        broadcasters: RwLock::new(BTreeMap::new())
+    });
+
+    // users stored from the last run needn't be looked up again
+    control_state.users.seed(snapshot.broadcasters.iter().map(|broadcaster| (broadcaster.login.clone(), broadcaster.user_id.clone())).collect()).await;
+
+    // seed broadcasters from config, the rest are managed at runtime; the
+    // reconciler creates their subscriptions on its first pass
+
+    let logins: Vec<String> = control_state.config.broadcasters.iter().map(|entry| entry.login.clone()).collect();
+    let resolved = users::resolve_many(&control_state, &logins).await;
+    tracing::info!("resolved {} of {} configured broadcasters", resolved.len(), logins.len());
+
+    for entry in &control_state.config.broadcasters {
+        let subscription_types = control_state.config.subscription_types(entry.subscriptions.as_deref(), entry.profile.as_deref())?;
+        match resolve_broadcaster(&control_state, &entry.login, subscription_types, entry.profile.as_deref()).await {
+            Ok(broadcaster) => {
+                tracing::info!("registered {} ({})", broadcaster.login, broadcaster.user_id);
+                control_state.broadcasters.write().await.insert(broadcaster.login.clone(), broadcaster);
+            },
+            Err(e) => {
+                tracing::error!("{e:?}");
+            }
+        }
+    }
+
+    if let Some(token) = bot_token {
+        let info = control_state.tokens.insert(token).await;
+        tracing::info!("stored bot token with scopes {:?}", info.scopes);
+    }
+
+    // the bot's own subscriptions ride on a registry entry for the bot, merged with its
+    // channel's entry if it's also listed as a broadcaster
+
+    let bot_subscriptions = &control_state.config.twitch.bot_subscriptions;
+    if !bot_subscriptions.is_empty() {
+        let mut broadcasters = control_state.broadcasters.write().await;
+        let login = control_state.my_user.login.to_string();
+        broadcasters.entry(login.clone()).or_insert_with(|| Broadcaster {
+            login,
+            user_id: control_state.my_user.id.clone(),
+            state: BroadcasterState::Active,
+            profile: None,
+            subscription_types: BTreeSet::new(),
+            subscriptions: BTreeMap::new(),
+            bot_moderator: Some(true)
+        }).subscription_types.extend(bot_subscriptions.iter().copied());
+        drop(broadcasters);
+    }
+
+    tracing::info!("restoring {} broadcasters, {} assignments, {} workers and {} retries from the store", snapshot.broadcasters.len(), snapshot.assignments.len(), snapshot.workers.len(), snapshot.retries.len());
+    store::restore(&control_state, snapshot).await;
+    subscription::verify_scopes(&control_state).await?;
+
+    Ok(control_state)
+}
+
+async fn expire_leases(control_state: Arc<ControlState<'static>>) -> ! {
+    let mut interval = tokio::time::interval(control_state.workers.lock().await.ttl() / 2);
+    loop {
+        interval.tick().await;
+        if !control_state.leader.is_leader() {
+            continue;
+        }
+
+        let mut workers = control_state.workers.lock().await;
+        let expired = workers.expire(Instant::now());
+        if expired.is_empty() {
+            continue;
+        }
+        for worker_id in &expired {
+            control_state.events.publish(ControlEvent::WorkerExpired { worker_id: worker_id.clone() });
+        }
+
+        for lane in control_state.conduits.iter() {
+            let mut scheduler = lane.scheduler.lock().await;
+            let mut vacated = Vec::new();
+            for worker_id in &expired {
+                let shards = scheduler.release_worker(worker_id);
+                if !shards.is_empty() {
+                    tracing::warn!("lease of worker {worker_id} expired, unassigned shards {shards:?} of the {} conduit", lane.name);
+                }
+                for &shard in &shards {
+                    control_state.events.publish(ControlEvent::ShardUnassigned { conduit: lane.name.clone(), shard, worker_id: Some(worker_id.clone()), reason: "lease_expired" });
+                }
+                vacated.extend(shards);
+            }
+
+            fail_over(&control_state, &workers, lane, &mut scheduler, &vacated);
+            drop(scheduler);
+        }
+        drop(workers);
+    }
+}
+
+/// Reserves shards freed by a dead worker or session for standby workers, which pick them up
+/// from their next heartbeat.
+fn fail_over(control_state: &ControlState<'_>, workers: &WorkerRegistry, lane: &Lane, scheduler: &mut Scheduler, vacated: &[usize]) {
+    let standbys: Vec<&str> = workers.standbys(control_state.config.capability(&lane.name)).collect();
+    for (shard, worker_id) in scheduler.fail_over(vacated, &standbys) {
+        tracing::info!("failing shard {shard} of the {} conduit over to standby worker {worker_id}", lane.name);
+        control_state.watch.notify(&worker_id, Notice::Assign { conduit: lane.name.clone(), shard });
+        control_state.metrics.inc("control_shard_failovers_total", &[]);
+    }
+}
+
+/// Looks up the user behind `login`; `subscription_types` falls back to the configured defaults.
+/// Whether `login` is among the broadcasters config seeds, the bot's own entry included.
+fn is_seeded(control_state: &ControlState<'_>, login: &str) -> bool {
+    let config = &control_state.config;
+    config.broadcasters.iter().any(|entry| entry.login.eq_ignore_ascii_case(login))
+        || (!config.twitch.bot_subscriptions.is_empty() && control_state.my_user.login.as_str() == login)
+}
+
+async fn resolve_broadcaster(control_state: &ControlState<'_>, login: &str, subscription_types: &[SubscriptionKind], profile: Option<&str>) -> anyhow::Result<Broadcaster> {
+    let user_id = users::resolve(control_state, login).await?.ok_or_else(|| anyhow!("no such user {login}"))?;
+
+    Ok(Broadcaster {
+        login: login.to_lowercase(),
+        user_id,
+        state: BroadcasterState::Active,
+        profile: profile.map(str::to_owned),
+        subscription_types: subscription_types.iter().copied().collect(),
+        subscriptions: BTreeMap::new(),
+        bot_moderator: None
+    })
+}
+
+async fn add_broadcaster(control_state: &ControlState<'_>, login: &str, subscription_types: &[SubscriptionKind], profile: Option<&str>) -> anyhow::Result<Broadcaster> {
+    let mut broadcaster = resolve_broadcaster(control_state, login, subscription_types, profile).await?;
+    broadcaster.bot_moderator = moderation::bot_moderates(control_state, slice::from_ref(&broadcaster.user_id)).await.get(&broadcaster.user_id).copied();
+    if broadcaster.bot_moderator == Some(false) {
+        moderation::flag_unmodded(control_state, &broadcaster.login, &broadcaster.user_id);
+    }
+
+    let guard = control_state.subscription_lock.lock().await;
+    for kind in broadcaster.subscription_types.clone() {
+        match subscription::create(control_state, kind, &broadcaster.user_id).await {
+            Ok(id) => {
+                broadcaster.subscriptions.insert(kind, id);
+            },
+            Err(e) => {
+                delete_subscriptions(control_state, broadcaster.subscriptions.values()).await;
+                return Err(e.context(format!("failed to create {kind} subscription for {login}")));
+            }
+        }
+    }
+
+    control_state.broadcasters.write().await.insert(broadcaster.login.clone(), broadcaster.clone());
+    drop(guard);
+
+    Ok(broadcaster)
+}
+
+async fn delete_subscriptions(control_state: &ControlState<'_>, ids: impl Iterator<Item = &EventSubId>) -> bool {
+    let mut ok = true;
+    for id in ids {
+        if let Err(e) = control_state.metrics.helix("delete_eventsub_subscription", control_state.twitch.delete_subscription(id, &control_state.app_token.get().await)).await {
+            tracing::error!("{e:?}");
+            ok = false;
+        }
+    }
+    ok
+}
+
+/// Marks the broadcaster with `user_id` revoked and forgets its token, returning the
+/// subscriptions it had for the caller to delete. Callers hold `subscription_lock`.
+async fn revoke_broadcaster(control_state: &ControlState<'_>, user_id: &UserId) -> Option<(String, BTreeMap<SubscriptionKind, EventSubId>)> {
+    control_state.tokens.remove(user_id).await;
+
+    let mut broadcasters = control_state.broadcasters.write().await;
+    let broadcaster = broadcasters.values_mut().find(|broadcaster| broadcaster.user_id == *user_id && broadcaster.state == BroadcasterState::Active)?;
+    broadcaster.state = BroadcasterState::Revoked;
+    let revoked = (broadcaster.login.clone(), core::mem::take(&mut broadcaster.subscriptions));
+    drop(broadcasters);
+
+    control_state.retries.lock().await.forget(&revoked.0);
+
+    tracing::warn!("{} revoked authorization, disabled their subscriptions", revoked.0);
+    control_state.events.publish(ControlEvent::BroadcasterRevoked {
+        login: revoked.0.clone(),
+        broadcaster_id: user_id.to_string(),
+        types: revoked.1.keys().map(|kind| kind.name()).collect()
+    });
+    Some(revoked)
+}
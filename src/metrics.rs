@@ -0,0 +1,141 @@
+use alloc::collections::BTreeMap;
+use core::fmt;
+use core::fmt::Write as _;
+use core::future::Future;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::time::Instant;
+use tracing::Instrument as _;
+
+const BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+const DESCRIPTIONS: &[(&str, &str, &str)] = &[
+    ("control_helix_requests_total",            "counter",   "Helix requests made, by endpoint and outcome."),
+    ("control_helix_request_duration_seconds",  "histogram", "Helix request latency, by endpoint."),
+    ("control_subscription_creations_total",    "counter",   "EventSub subscription creation attempts, by type and outcome."),
+    ("control_shard_assignments_total",         "counter",   "Shard assignment attempts, by outcome."),
+    ("control_auth_failures_total",             "counter",   "Requests rejected for a bad or missing control token."),
+    ("control_rate_limited_total",              "counter",   "Mutating control requests rejected by the rate limiter."),
+    ("control_conduit_recreations_total",       "counter",   "Times the conduit was found deleted and recreated, by outcome."),
+    ("control_shard_failovers_total",           "counter",   "Shards vacated by a dead worker or session and reserved for a standby worker."),
+    ("control_callback_deliveries_total",       "counter",   "Deliveries to /eventsub/callback, by outcome."),
+    ("control_event_publishes_total",           "counter",   "Control events published to external sinks, by sink and outcome."),
+    ("control_kafka_batches_total",             "counter",   "Batches of events and audit entries sent to Kafka, by outcome.")
+];
+
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64
+}
+
+/// Process-local counters and histograms rendered in the Prometheus text format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    counters: Mutex<BTreeMap<(&'static str, String), u64>>,
+    histograms: Mutex<BTreeMap<(&'static str, String), Histogram>>
+}
+
+impl Metrics {
+    pub fn inc(&self, name: &'static str, labels: &[(&str, &str)]) {
+        self.add(name, labels, 1);
+    }
+
+    pub fn add(&self, name: &'static str, labels: &[(&str, &str)], value: u64) {
+        let mut counters = self.counters.lock().unwrap_or_else(PoisonError::into_inner);
+        *counters.entry((name, render_labels(labels))).or_default() += value;
+    }
+
+    pub fn observe(&self, name: &'static str, labels: &[(&str, &str)], value: f64) {
+        let mut histograms = self.histograms.lock().unwrap_or_else(PoisonError::into_inner);
+        let histogram = histograms.entry((name, render_labels(labels))).or_insert_with(|| Histogram {
+            buckets: vec![0; BUCKETS.len()],
+            ..Histogram::default()
+        });
+
+        for (count, bound) in histogram.buckets.iter_mut().zip(BUCKETS) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        histogram.sum += value;
+        histogram.count += 1;
+        drop(histograms);
+    }
+
+    /// Times a Helix call, counts it by outcome, and traces it in its own span.
+    pub async fn helix<T, E, F: Future<Output = Result<T, E>>>(&self, endpoint: &'static str, request: F) -> Result<T, E> {
+        let span = tracing::info_span!("helix", endpoint, outcome = tracing::field::Empty);
+        let start = Instant::now();
+        let result = request.instrument(span.clone()).await;
+
+        span.record("outcome", if result.is_ok() { "ok" } else { "error" });
+
+        self.observe("control_helix_request_duration_seconds", &[("endpoint", endpoint)], start.elapsed().as_secs_f64());
+        self.inc("control_helix_requests_total", &[("endpoint", endpoint), ("outcome", if result.is_ok() { "ok" } else { "error" })]);
+
+        result
+    }
+
+    pub fn render(&self, out: &mut String) -> fmt::Result {
+        let counters = self.counters.lock().unwrap_or_else(PoisonError::into_inner);
+        let histograms = self.histograms.lock().unwrap_or_else(PoisonError::into_inner);
+
+        for &(name, kind, help) in DESCRIPTIONS {
+            writeln!(out, "# HELP {name} {help}")?;
+            writeln!(out, "# TYPE {name} {kind}")?;
+
+            for ((_, labels), value) in counters.range((name, String::new())..).take_while(|((series, _), _)| *series == name) {
+                writeln!(out, "{name}{} {value}", braced(labels))?;
+            }
+
+            for ((_, labels), histogram) in histograms.range((name, String::new())..).take_while(|((series, _), _)| *series == name) {
+                for (count, bound) in histogram.buckets.iter().zip(BUCKETS) {
+                    writeln!(out, "{name}_bucket{} {count}", braced(&join_labels(labels, &format!("le=\"{bound}\""))))?;
+                }
+                writeln!(out, "{name}_bucket{} {}", braced(&join_labels(labels, "le=\"+Inf\"")), histogram.count)?;
+                writeln!(out, "{name}_sum{} {}", braced(labels), histogram.sum)?;
+                writeln!(out, "{name}_count{} {}", braced(labels), histogram.count)?;
+            }
+        }
+        drop(counters);
+        drop(histograms);
+
+        Ok(())
+    }
+}
+
+/// Renders a gauge computed at scrape time rather than tracked incrementally.
+pub fn render_gauge(out: &mut String, name: &str, help: &str, series: &[(Vec<(&str, &str)>, usize)]) -> fmt::Result {
+    writeln!(out, "# HELP {name} {help}")?;
+    writeln!(out, "# TYPE {name} gauge")?;
+    for (labels, value) in series {
+        writeln!(out, "{name}{} {value}", braced(&render_labels(labels)))?;
+    }
+
+    Ok(())
+}
+
+fn render_labels(labels: &[(&str, &str)]) -> String {
+    labels.iter()
+        .map(|(key, value)| format!("{key}=\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn join_labels(labels: &str, extra: &str) -> String {
+    if labels.is_empty() {
+        extra.to_owned()
+    } else {
+        format!("{labels},{extra}")
+    }
+}
+
+fn braced(labels: &str) -> String {
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{labels}}}")
+    }
+}
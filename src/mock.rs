@@ -0,0 +1,451 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use axum::Json;
+use axum::Router;
+use axum::extract::RawQuery;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::response::IntoResponse as _;
+use axum::response::Response;
+use axum::routing::get;
+use axum::routing::post;
+use core::net::SocketAddr;
+use serde::Deserialize;
+use serde_json::Value;
+use serde_json::json;
+use tokio::sync::Mutex;
+use twitch_api::twitch_oauth2::url::form_urlencoded;
+
+/// What every app token the mock hands out is.
+pub const APP_TOKEN: &str = "mock-app-token";
+/// The mock's clock is stopped; everything happened at once.
+const CREATED_AT: &str = "2024-01-01T00:00:00Z";
+const MAX_TOTAL_COST: usize = 10_000;
+
+/// A stand-in for the parts of Twitch the control plane calls: app tokens, users, conduits and
+/// their shards, and EventSub subscriptions, kept in memory. Every login looked up exists.
+#[derive(Default)]
+pub struct Twitch {
+    state: Mutex<MockState>
+}
+
+#[derive(Default)]
+struct MockState {
+    client_id: String,
+    /// Ids by login.
+    users: BTreeMap<String, String>,
+    conduits: BTreeMap<String, MockConduit>,
+    subscriptions: BTreeMap<String, Value>,
+    next_id: u64
+}
+
+#[derive(Default)]
+struct MockConduit {
+    shard_count: usize,
+    /// As Get Conduit Shards describes them, for shards that have been given a transport.
+    shards: BTreeMap<usize, Value>
+}
+
+impl MockState {
+    fn next_id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{prefix}-{}", self.next_id)
+    }
+
+    fn user_id(&mut self, login: &str) -> String {
+        if let Some(id) = self.users.get(login) {
+            return id.clone();
+        }
+
+        self.next_id += 1;
+        let id = (1000 + self.next_id).to_string();
+        self.users.insert(login.to_owned(), id.clone());
+        id
+    }
+
+    fn totals(&self) -> (usize, usize) {
+        let total_cost = self.subscriptions.values().filter_map(|subscription| at(subscription, "/cost").as_u64()).sum::<u64>();
+        (self.subscriptions.len(), usize::try_from(total_cost).unwrap_or(usize::MAX))
+    }
+}
+
+#[cfg(test)]
+impl Twitch {
+    pub async fn user_id(&self, login: &str) -> String {
+        self.state.lock().await.user_id(login)
+    }
+
+    /// Ids and shard counts.
+    pub async fn conduits(&self) -> Vec<(String, usize)> {
+        self.state.lock().await.conduits.iter().map(|(id, conduit)| (id.clone(), conduit.shard_count)).collect()
+    }
+
+    pub async fn shard(&self, conduit_id: &str, shard: usize) -> Option<Value> {
+        self.state.lock().await.conduits.get(conduit_id)?.shards.get(&shard).cloned()
+    }
+
+    /// The shard's websocket going away, as Twitch would report it.
+    pub async fn disconnect(&self, conduit_id: &str, shard: usize) {
+        let mut state = self.state.lock().await;
+        if let Some(shard) = state.conduits.get_mut(conduit_id).and_then(|conduit| conduit.shards.get_mut(&shard)) {
+            if let Some(status) = shard.pointer_mut("/status") {
+                *status = json!("websocket_disconnected");
+            }
+            if let Some(transport) = shard.pointer_mut("/transport").and_then(Value::as_object_mut) {
+                transport.insert("disconnected_at".to_owned(), json!(CREATED_AT));
+            }
+        }
+        drop(state);
+    }
+
+    pub async fn subscriptions(&self) -> Vec<Value> {
+        self.state.lock().await.subscriptions.values().cloned().collect()
+    }
+
+    /// Subscriptions dropped out from under the control plane, e.g. deleted from another client.
+    pub async fn delete_subscriptions(&self) {
+        self.state.lock().await.subscriptions.clear();
+    }
+}
+
+/// Serves the mock on an ephemeral local port, with Helix under `/helix` and OAuth under `/oauth2`.
+pub async fn serve() -> anyhow::Result<(SocketAddr, Arc<Twitch>)> {
+    let twitch = Arc::new(Twitch::default());
+    let router = Router::new()
+        .route("/oauth2/token", post(token))
+        .route("/oauth2/validate", get(validate))
+        .route("/helix/users", get(users))
+        .route("/helix/eventsub/conduits", get(conduits_list).post(conduits_create).patch(conduits_update).delete(conduits_delete))
+        .route("/helix/eventsub/conduits/shards", get(shards_list).patch(shards_update))
+        .route("/helix/eventsub/subscriptions", get(subscriptions_list).post(subscriptions_create).delete(subscriptions_delete))
+        .with_state(Arc::clone(&twitch));
+
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0)).await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(axum::serve(listener, router).into_future());
+
+    Ok((addr, twitch))
+}
+
+/// Helix's error body.
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": status.canonical_reason(), "status": status.as_u16(), "message": message }))).into_response()
+}
+
+/// Conduits and subscriptions are app token only.
+fn is_app_token(headers: &HeaderMap) -> bool {
+    headers.get("authorization").and_then(|value| value.to_str().ok()).and_then(|value| value.strip_prefix("Bearer ")) == Some(APP_TOKEN)
+}
+
+fn unauthorized() -> Response {
+    error(StatusCode::UNAUTHORIZED, "invalid access token")
+}
+
+/// What's at `pointer` in `value`, null if nothing is.
+fn at<'a>(value: &'a Value, pointer: &str) -> &'a Value {
+    static NULL: Value = Value::Null;
+    value.pointer(pointer).unwrap_or(&NULL)
+}
+
+fn query_pairs(query: Option<&str>) -> Vec<(String, String)> {
+    form_urlencoded::parse(query.unwrap_or_default().as_bytes()).into_owned().collect()
+}
+
+fn query_value<'a>(pairs: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    pairs.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_str())
+}
+
+/// Client credentials only, which is all the control plane asks the mock for. `twitch_oauth2`
+/// sends the parameters in the query rather than a form body.
+async fn token(State(twitch): State<Arc<Twitch>>, RawQuery(query): RawQuery) -> Response {
+    let form = query_pairs(query.as_deref());
+    if query_value(&form, "grant_type") != Some("client_credentials") {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "status": 400, "message": "unsupported grant type" }))).into_response();
+    }
+
+    twitch.state.lock().await.client_id = query_value(&form, "client_id").unwrap_or_default().to_owned();
+    Json(json!({ "access_token": APP_TOKEN, "expires_in": 3600, "token_type": "bearer" })).into_response()
+}
+
+async fn validate(State(twitch): State<Arc<Twitch>>, headers: HeaderMap) -> Response {
+    let token = headers.get("authorization").and_then(|value| value.to_str().ok()).and_then(|value| value.strip_prefix("OAuth "));
+    if token != Some(APP_TOKEN) {
+        return (StatusCode::UNAUTHORIZED, Json(json!({ "status": 401, "message": "invalid access token" }))).into_response();
+    }
+
+    let client_id = twitch.state.lock().await.client_id.clone();
+    Json(json!({ "client_id": client_id, "scopes": [], "expires_in": 3600 })).into_response()
+}
+
+async fn users(State(twitch): State<Arc<Twitch>>, RawQuery(query): RawQuery) -> Response {
+    let pairs = query_pairs(query.as_deref());
+    let mut state = twitch.state.lock().await;
+
+    let mut found = Vec::new();
+    for (key, value) in &pairs {
+        let login = match key.as_str() {
+            "login" => Some(value.to_lowercase()),
+            "id" => state.users.iter().find(|(_, id)| *id == value).map(|(login, _)| login.clone()),
+            _ => None
+        };
+        if let Some(login) = login {
+            let id = state.user_id(&login);
+            found.push(json!({
+                "id": id,
+                "login": login,
+                "display_name": login,
+                "type": "",
+                "broadcaster_type": "",
+                "description": "",
+                "profile_image_url": "",
+                "offline_image_url": "",
+                "created_at": CREATED_AT
+            }));
+        }
+    }
+    drop(state);
+
+    Json(json!({ "data": found })).into_response()
+}
+
+fn conduit_json(id: &str, conduit: &MockConduit) -> Value {
+    json!({ "id": id, "shard_count": conduit.shard_count })
+}
+
+async fn conduits_list(State(twitch): State<Arc<Twitch>>, headers: HeaderMap) -> Response {
+    if !is_app_token(&headers) {
+        return unauthorized();
+    }
+
+    let data: Vec<Value> = twitch.state.lock().await.conduits.iter().map(|(id, conduit)| conduit_json(id, conduit)).collect();
+    Json(json!({ "data": data })).into_response()
+}
+
+#[derive(Deserialize)]
+struct CreateConduit {
+    shard_count: usize
+}
+
+async fn conduits_create(State(twitch): State<Arc<Twitch>>, headers: HeaderMap, Json(body): Json<CreateConduit>) -> Response {
+    if !is_app_token(&headers) {
+        return unauthorized();
+    }
+
+    let mut state = twitch.state.lock().await;
+    let id = state.next_id("mock-conduit");
+    let conduit = MockConduit { shard_count: body.shard_count, shards: BTreeMap::new() };
+    let data = conduit_json(&id, &conduit);
+    state.conduits.insert(id, conduit);
+    drop(state);
+
+    Json(json!({ "data": [data] })).into_response()
+}
+
+#[derive(Deserialize)]
+struct UpdateConduit {
+    id: String,
+    shard_count: usize
+}
+
+async fn conduits_update(State(twitch): State<Arc<Twitch>>, headers: HeaderMap, Json(body): Json<UpdateConduit>) -> Response {
+    if !is_app_token(&headers) {
+        return unauthorized();
+    }
+
+    let mut state = twitch.state.lock().await;
+    let Some(conduit) = state.conduits.get_mut(&body.id) else {
+        return error(StatusCode::NOT_FOUND, "conduit not found");
+    };
+    conduit.shard_count = body.shard_count;
+    conduit.shards.retain(|&shard, _| shard < body.shard_count);
+    let data = conduit_json(&body.id, conduit);
+    drop(state);
+
+    Json(json!({ "data": [data] })).into_response()
+}
+
+async fn conduits_delete(State(twitch): State<Arc<Twitch>>, headers: HeaderMap, RawQuery(query): RawQuery) -> Response {
+    if !is_app_token(&headers) {
+        return unauthorized();
+    }
+
+    let pairs = query_pairs(query.as_deref());
+    let id = query_value(&pairs, "id").unwrap_or_default();
+    let mut state = twitch.state.lock().await;
+    if state.conduits.remove(id).is_none() {
+        return error(StatusCode::NOT_FOUND, "conduit not found");
+    }
+    state.subscriptions.retain(|_, subscription| *at(subscription, "/transport/conduit_id") != *id);
+    drop(state);
+
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn shards_list(State(twitch): State<Arc<Twitch>>, headers: HeaderMap, RawQuery(query): RawQuery) -> Response {
+    if !is_app_token(&headers) {
+        return unauthorized();
+    }
+
+    let pairs = query_pairs(query.as_deref());
+    let state = twitch.state.lock().await;
+    let Some(conduit) = query_value(&pairs, "conduit_id").and_then(|id| state.conduits.get(id)) else {
+        return error(StatusCode::NOT_FOUND, "conduit not found");
+    };
+    let status = query_value(&pairs, "status");
+    let data: Vec<Value> = conduit.shards.values().filter(|shard| status.is_none_or(|status| *at(shard, "/status") == *status)).cloned().collect();
+    drop(state);
+
+    Json(json!({ "data": data, "pagination": {} })).into_response()
+}
+
+#[derive(Deserialize)]
+struct UpdateShards {
+    conduit_id: String,
+    shards: Vec<ShardUpdate>
+}
+
+#[derive(Deserialize)]
+struct ShardUpdate {
+    id: String,
+    transport: ShardTransport
+}
+
+#[derive(Deserialize)]
+struct ShardTransport {
+    method: String,
+    session_id: Option<String>,
+    callback: Option<String>
+}
+
+async fn shards_update(State(twitch): State<Arc<Twitch>>, headers: HeaderMap, Json(body): Json<UpdateShards>) -> Response {
+    if !is_app_token(&headers) {
+        return unauthorized();
+    }
+
+    let mut state = twitch.state.lock().await;
+    let Some(conduit) = state.conduits.get_mut(&body.conduit_id) else {
+        return error(StatusCode::NOT_FOUND, "conduit not found");
+    };
+
+    let (mut data, mut errors) = (Vec::new(), Vec::new());
+    for update in body.shards {
+        let shard = match update.id.parse::<usize>() {
+            Ok(shard) if shard < conduit.shard_count => shard,
+            _ => {
+                errors.push(json!({ "id": update.id, "message": "shard id out of range", "code": "invalid_parameter" }));
+                continue;
+            }
+        };
+
+        let described = match (update.transport.method.as_str(), update.transport.session_id, update.transport.callback) {
+            ("websocket", Some(session_id), _) => json!({
+                "id": update.id,
+                "status": "enabled",
+                "transport": { "method": "websocket", "session_id": session_id, "connected_at": CREATED_AT }
+            }),
+            ("webhook", _, Some(callback)) => json!({
+                "id": update.id,
+                "status": "webhook_callback_verification_pending",
+                "transport": { "method": "webhook", "callback": callback }
+            }),
+            _ => {
+                errors.push(json!({ "id": update.id, "message": "invalid transport", "code": "invalid_parameter" }));
+                continue;
+            }
+        };
+        conduit.shards.insert(shard, described.clone());
+        data.push(described);
+    }
+    drop(state);
+
+    Json(json!({ "data": data, "errors": errors })).into_response()
+}
+
+/// `limit` is only in Create EventSub Subscription's answer.
+fn listing(state: &MockState, subscriptions: Vec<Value>, limit: Option<usize>) -> Value {
+    let (total, total_cost) = state.totals();
+    json!({ "data": subscriptions, "total": total, "total_cost": total_cost, "max_total_cost": MAX_TOTAL_COST, "limit": limit, "pagination": {} })
+}
+
+async fn subscriptions_list(State(twitch): State<Arc<Twitch>>, headers: HeaderMap, RawQuery(query): RawQuery) -> Response {
+    if !is_app_token(&headers) {
+        return unauthorized();
+    }
+
+    let pairs = query_pairs(query.as_deref());
+    let (type_, status, user_id) = (query_value(&pairs, "type"), query_value(&pairs, "status"), query_value(&pairs, "user_id"));
+    let state = twitch.state.lock().await;
+    let found = state.subscriptions.values()
+        .filter(|subscription| type_.is_none_or(|type_| *at(subscription, "/type") == *type_))
+        .filter(|subscription| status.is_none_or(|status| *at(subscription, "/status") == *status))
+        .filter(|subscription| user_id.is_none_or(|user_id| at(subscription, "/condition").as_object().is_some_and(|condition| condition.values().any(|value| value == user_id))))
+        .cloned()
+        .collect();
+    let body = listing(&state, found, None);
+    drop(state);
+
+    Json(body).into_response()
+}
+
+#[derive(Deserialize)]
+struct CreateSubscription {
+    #[serde(rename = "type")]
+    type_: String,
+    version: String,
+    condition: Value,
+    transport: SubscriptionTransport
+}
+
+#[derive(Deserialize)]
+struct SubscriptionTransport {
+    method: String,
+    conduit_id: Option<String>
+}
+
+async fn subscriptions_create(State(twitch): State<Arc<Twitch>>, headers: HeaderMap, Json(body): Json<CreateSubscription>) -> Response {
+    if !is_app_token(&headers) {
+        return unauthorized();
+    }
+
+    let mut state = twitch.state.lock().await;
+    let conduit_id = match (body.transport.method.as_str(), body.transport.conduit_id) {
+        ("conduit", Some(conduit_id)) if state.conduits.contains_key(&conduit_id) => conduit_id,
+        _ => return error(StatusCode::BAD_REQUEST, "the mock only takes subscriptions on one of its conduits")
+    };
+    let duplicate = state.subscriptions.values().any(|subscription| *at(subscription, "/type") == *body.type_ && *at(subscription, "/version") == *body.version && *at(subscription, "/condition") == body.condition);
+    if duplicate {
+        return error(StatusCode::CONFLICT, "subscription already exists");
+    }
+
+    let id = state.next_id("mock-subscription");
+    let subscription = json!({
+        "id": id,
+        "status": "enabled",
+        "type": body.type_,
+        "version": body.version,
+        "condition": body.condition,
+        "created_at": CREATED_AT,
+        "transport": { "method": "conduit", "conduit_id": conduit_id },
+        "cost": 1
+    });
+    state.subscriptions.insert(id, subscription.clone());
+    let body = listing(&state, vec![subscription], Some(MAX_TOTAL_COST));
+    drop(state);
+
+    (StatusCode::ACCEPTED, Json(body)).into_response()
+}
+
+async fn subscriptions_delete(State(twitch): State<Arc<Twitch>>, headers: HeaderMap, RawQuery(query): RawQuery) -> Response {
+    if !is_app_token(&headers) {
+        return unauthorized();
+    }
+
+    let pairs = query_pairs(query.as_deref());
+    let id = query_value(&pairs, "id").unwrap_or_default();
+    if twitch.state.lock().await.subscriptions.remove(id).is_none() {
+        return error(StatusCode::NOT_FOUND, "subscription not found");
+    }
+
+    StatusCode::NO_CONTENT.into_response()
+}
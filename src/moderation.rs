@@ -0,0 +1,261 @@
+use crate::BroadcasterState;
+use crate::ControlState;
+use crate::events::ControlEvent;
+use crate::proxy;
+use crate::proxy::Rejection;
+use alloc::collections::BTreeMap;
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use core::slice;
+use core::time::Duration;
+use futures_util::TryStreamExt as _;
+use serde::Deserialize;
+use twitch_api::helix::Request as _;
+use twitch_api::helix::moderation::AutoModSettings;
+use twitch_api::helix::moderation::BanUser;
+use twitch_api::helix::moderation::BanUserRequest;
+use twitch_api::helix::moderation::GetAutoModSettingsRequest;
+use twitch_api::helix::moderation::GetModeratedChannelsRequest;
+use twitch_api::helix::moderation::GetModeratorsRequest;
+use twitch_api::helix::moderation::GetShieldModeStatusRequest;
+use twitch_api::helix::moderation::ModeratedChannel;
+use twitch_api::helix::moderation::ShieldModeStatus;
+use twitch_api::helix::moderation::UnbanUserRequest;
+use twitch_api::helix::moderation::UpdateAutoModSettingsBody;
+use twitch_api::helix::moderation::UpdateAutoModSettingsRequest;
+use twitch_api::helix::moderation::WarnChatUser;
+use twitch_api::helix::moderation::WarnChatUserRequest;
+use twitch_api::helix::moderation::update_shield_mode_status::UpdateShieldModeStatusBody;
+use twitch_api::helix::moderation::update_shield_mode_status::UpdateShieldModeStatusRequest;
+use twitch_api::types::UserId;
+
+/// Twitch's longest timeout, two weeks.
+const MAX_TIMEOUT_SECS: u32 = 14 * 24 * 60 * 60;
+/// How often every channel is checked for the bot still being a moderator there.
+const MODERATOR_CHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Deserialize)]
+pub struct Ban {
+    user_id: UserId,
+    #[serde(default)]
+    reason: String,
+    /// Times the user out for this long rather than banning them for good.
+    duration_secs: Option<u32>
+}
+
+/// Bans or times out a user in the broadcaster's chat, as the bot moderating it.
+pub async fn ban(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(broadcaster): Path<String>,
+    Json(body): Json<Ban>
+) -> Result<Json<BanUser>, Rejection> {
+    if body.duration_secs.is_some_and(|secs| !(1..=MAX_TIMEOUT_SECS).contains(&secs)) {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, format!("duration_secs must be between 1 and {MAX_TIMEOUT_SECS}")));
+    }
+
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::bot_token(&control_state, &BanUserRequest::SCOPE).await?;
+
+    let banned = control_state.metrics.helix("ban_user", control_state.client.helix.ban_user(
+        &body.user_id,
+        body.reason.as_str(),
+        body.duration_secs,
+        &broadcaster_id,
+        &control_state.my_user.id,
+        &token
+    )).await.map_err(|e| proxy::rejection(&e))?;
+
+    Ok(Json(banned))
+}
+
+/// Lifts a ban or timeout.
+pub async fn unban(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path((broadcaster, user_id)): Path<(String, UserId)>
+) -> Result<StatusCode, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::bot_token(&control_state, &UnbanUserRequest::SCOPE).await?;
+
+    control_state.metrics.helix("unban_user", control_state.client.helix.unban_user(&user_id, &broadcaster_id, &control_state.my_user.id, &token)).await.map_err(|e| proxy::rejection(&e))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct Warn {
+    user_id: UserId,
+    reason: String
+}
+
+/// Warns a user in the broadcaster's chat. They can't chat again until they acknowledge it.
+pub async fn warn(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(broadcaster): Path<String>,
+    Json(body): Json<Warn>
+) -> Result<Json<WarnChatUser>, Rejection> {
+    if body.reason.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "reason is required".to_owned()));
+    }
+
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::bot_token(&control_state, &WarnChatUserRequest::SCOPE).await?;
+
+    let warning = control_state.metrics.helix("warn_chat_user", control_state.client.helix.warn_chat_user(
+        &body.user_id,
+        body.reason.as_str(),
+        &broadcaster_id,
+        &control_state.my_user.id,
+        &token
+    )).await.map_err(|e| proxy::rejection(&e))?;
+
+    Ok(Json(warning))
+}
+
+pub async fn shield_mode(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(broadcaster): Path<String>
+) -> Result<Json<ShieldModeStatus>, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::bot_token(&control_state, &GetShieldModeStatusRequest::SCOPE).await?;
+
+    let request = GetShieldModeStatusRequest::new(&broadcaster_id, &control_state.my_user.id);
+    let status = control_state.metrics.helix("get_shield_mode_status", control_state.client.helix.req_get(request, &token)).await.map_err(|e| proxy::rejection(&e))?.data;
+
+    Ok(Json(status))
+}
+
+/// Turns Shield Mode on or off, e.g. in the middle of a hate raid.
+pub async fn update_shield_mode(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(broadcaster): Path<String>,
+    Json(body): Json<UpdateShieldModeStatusBody<'static>>
+) -> Result<Json<ShieldModeStatus>, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::bot_token(&control_state, &UpdateShieldModeStatusRequest::SCOPE).await?;
+
+    let request = UpdateShieldModeStatusRequest::new(&broadcaster_id, &control_state.my_user.id);
+    let status = control_state.metrics.helix("update_shield_mode_status", control_state.client.helix.req_put(request, body, &token)).await.map_err(|e| proxy::rejection(&e))?.data;
+
+    Ok(Json(status))
+}
+
+pub async fn automod(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(broadcaster): Path<String>
+) -> Result<Json<AutoModSettings>, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::bot_token(&control_state, &GetAutoModSettingsRequest::SCOPE).await?;
+
+    let request = GetAutoModSettingsRequest::new(&broadcaster_id, &control_state.my_user.id);
+    let settings = control_state.metrics.helix("get_automod_settings", control_state.client.helix.req_get(request, &token)).await.map_err(|e| proxy::rejection(&e))?.data;
+
+    Ok(Json(settings))
+}
+
+/// Replaces the AutoMod settings with either an `overall_level` or every individual level, as
+/// Twitch overwrites any left out.
+pub async fn update_automod(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(broadcaster): Path<String>,
+    Json(body): Json<UpdateAutoModSettingsBody>
+) -> Result<Json<AutoModSettings>, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::bot_token(&control_state, &UpdateAutoModSettingsRequest::SCOPE).await?;
+
+    let request = UpdateAutoModSettingsRequest::new(&broadcaster_id, &control_state.my_user.id);
+    let settings = control_state.metrics.helix("update_automod_settings", control_state.client.helix.req_put(request, body, &token)).await.map_err(|e| proxy::rejection(&e))?.data;
+
+    Ok(Json(settings))
+}
+
+/// Whether the bot moderates each channel, asked of the broadcaster's token where it has the
+/// scope and otherwise looked up in the bot's own moderated channels. Channels neither can tell
+/// about are left out.
+pub async fn bot_moderates(control_state: &ControlState<'_>, broadcaster_ids: &[UserId]) -> BTreeMap<UserId, bool> {
+    let bot_id = &control_state.my_user.id;
+    let mut moderated = None;
+    let mut statuses = BTreeMap::new();
+    for broadcaster_id in broadcaster_ids {
+        let status = if broadcaster_id == bot_id {
+            Some(true)
+        } else if let Ok(token) = proxy::user_token(control_state, broadcaster_id, &GetModeratorsRequest::SCOPE).await {
+            let request = GetModeratorsRequest::broadcaster_id(broadcaster_id).user_ids(slice::from_ref(bot_id));
+            match control_state.metrics.helix("get_moderators", control_state.client.helix.req_get(request, &token)).await {
+                Ok(response) => Some(!response.data.is_empty()),
+                Err(e) => {
+                    tracing::warn!("failed to check whether {} moderates {broadcaster_id}: {e:?}", control_state.my_user.login);
+                    None
+                }
+            }
+        } else {
+            if moderated.is_none() {
+                moderated = Some(moderated_channels(control_state).await);
+            }
+            moderated.as_ref().and_then(Option::as_ref).map(|channels| channels.contains(broadcaster_id))
+        };
+
+        if let Some(status) = status {
+            statuses.insert(broadcaster_id.clone(), status);
+        }
+    }
+    statuses
+}
+
+async fn moderated_channels(control_state: &ControlState<'_>) -> Option<BTreeSet<UserId>> {
+    let token = proxy::bot_token(control_state, &GetModeratedChannelsRequest::SCOPE).await.ok()?;
+    let channels: Vec<ModeratedChannel> = match control_state.metrics.helix("get_moderated_channels", control_state.client.helix.get_moderated_channels(&control_state.my_user.id, &token).try_collect()).await {
+        Ok(channels) => channels,
+        Err(e) => {
+            tracing::warn!("failed to list the channels {} moderates: {e:?}", control_state.my_user.login);
+            return None;
+        }
+    };
+
+    Some(channels.into_iter().map(|channel| channel.broadcaster_id).collect())
+}
+
+/// Flags a channel the bot isn't a moderator in, which many subscription types and chat
+/// endpoints need it to be.
+pub fn flag_unmodded(control_state: &ControlState<'_>, login: &str, broadcaster_id: &UserId) {
+    tracing::warn!("{} isn't a moderator in {login}'s channel", control_state.my_user.login);
+    control_state.events.publish(ControlEvent::BotNotModerator { login: login.to_owned(), broadcaster_id: broadcaster_id.to_string() });
+}
+
+/// Rechecks the bot's moderator status in every active channel, flagging those it lost it in.
+/// A channel that can't be checked keeps its last known status.
+pub async fn run(control_state: Arc<ControlState<'static>>) -> ! {
+    let mut interval = tokio::time::interval(MODERATOR_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        if !control_state.leader.is_leader() {
+            continue;
+        }
+
+        let ids: Vec<UserId> = control_state.broadcasters.read().await.values()
+            .filter(|broadcaster| broadcaster.state == BroadcasterState::Active)
+            .map(|broadcaster| broadcaster.user_id.clone())
+            .collect();
+        let statuses = bot_moderates(&control_state, &ids).await;
+
+        let mut unmodded = Vec::new();
+        let mut broadcasters = control_state.broadcasters.write().await;
+        for broadcaster in broadcasters.values_mut() {
+            let Some(&status) = statuses.get(&broadcaster.user_id) else {
+                continue;
+            };
+            if !status && broadcaster.bot_moderator != Some(false) {
+                unmodded.push((broadcaster.login.clone(), broadcaster.user_id.clone()));
+            }
+            broadcaster.bot_moderator = Some(status);
+        }
+        drop(broadcasters);
+
+        for (login, broadcaster_id) in unmodded {
+            flag_unmodded(&control_state, &login, &broadcaster_id);
+        }
+    }
+}
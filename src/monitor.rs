@@ -0,0 +1,154 @@
+use crate::ControlState;
+use crate::conduit::Lane;
+use crate::events::ControlEvent;
+use crate::watch::Notice;
+use alloc::sync::Arc;
+use axum::http::StatusCode;
+use serde::Serialize;
+use std::time::SystemTime;
+use twitch_api::eventsub::ShardResponse;
+use twitch_api::eventsub::ShardStatus;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShardHealth {
+    Healthy,
+    Unhealthy,
+    Idle
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ShardReport {
+    pub id: String,
+    pub status: ShardStatus,
+    pub health: ShardHealth,
+    pub session_id: Option<String>
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ConduitHealth {
+    pub checked_at: Option<u64>,
+    pub healthy: bool,
+    pub twitch_reachable: bool,
+    pub shards: Vec<ShardReport>,
+    pub error: Option<String>
+}
+
+/// Whether Twitch has given up on a shard's transport, rather than it still coming up.
+pub const fn is_lost(status: &ShardStatus) -> bool {
+    !matches!(status, ShardStatus::Enabled | ShardStatus::WebhookCallbackVerificationPending)
+}
+
+pub async fn run(control_state: Arc<ControlState<'static>>) -> ! {
+    let interval_secs = control_state.config.conduit.health_check_interval_secs;
+    let mut interval = tokio::time::interval(core::time::Duration::from_secs(interval_secs));
+    loop {
+        interval.tick().await;
+
+        for lane in control_state.conduits.iter() {
+            let health = check(&control_state, lane).await;
+            let mut current = lane.health.write().await;
+            if control_state.leader.is_leader() {
+                for (shard, from, to) in changes(&current, &health) {
+                    control_state.events.publish(ControlEvent::ShardHealthChanged { conduit: lane.name.clone(), shard, from, to });
+                }
+            }
+            *current = health;
+            drop(current);
+        }
+    }
+}
+
+/// Shards whose health differs between two successful checks.
+fn changes(before: &ConduitHealth, after: &ConduitHealth) -> Vec<(usize, ShardHealth, ShardHealth)> {
+    if before.checked_at.is_none() || before.error.is_some() || after.error.is_some() {
+        return Vec::new();
+    }
+
+    after.shards.iter().filter_map(|report| {
+        let previous = before.shards.iter().find(|previous| previous.id == report.id)?;
+        if previous.health == report.health {
+            return None;
+        }
+        Some((report.id.parse().ok()?, previous.health, report.health))
+    }).collect()
+}
+
+async fn check(control_state: &ControlState<'_>, lane: &Lane) -> ConduitHealth {
+    let checked_at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).ok().map(|since| since.as_secs());
+
+    let conduit_id = lane.conduit.read().await.id.clone();
+    let shards: Vec<ShardResponse> = match control_state.metrics.helix("get_conduit_shards", control_state.twitch.shards(&conduit_id, &control_state.app_token.get().await)).await {
+        Ok(shards) => shards,
+        Err(e) => {
+            tracing::warn!("failed to check conduit shards: {e:?}");
+
+            if crate::helix::status(&e) == Some(StatusCode::NOT_FOUND) && control_state.leader.is_leader() {
+                tracing::error!("{} conduit {conduit_id} no longer exists, recreating it", lane.name);
+                if let Err(e) = crate::conduit::heal(control_state, lane).await {
+                    tracing::error!("failed to recreate the conduit: {e:?}");
+                }
+            }
+
+            return ConduitHealth {
+                checked_at,
+                healthy: false,
+                twitch_reachable: !crate::helix::is_unreachable(&e),
+                shards: Vec::new(),
+                error: Some(e.to_string())
+            };
+        }
+    };
+
+    let workers = control_state.workers.lock().await;
+    let mut scheduler = lane.scheduler.lock().await;
+    let reports: Vec<ShardReport> = shards.into_iter().map(|shard| {
+        let session_id = shard.id.as_str().parse().ok().and_then(|id| scheduler.assignment(id)).filter(|assignment| !assignment.is_reserved()).map(|assignment| assignment.session_id.clone());
+        let health = match (&shard.status, &session_id) {
+            (ShardStatus::Enabled, _) => ShardHealth::Healthy,
+            (_, Some(_)) => ShardHealth::Unhealthy,
+            (_, None) => ShardHealth::Idle
+        };
+
+        if health == ShardHealth::Unhealthy {
+            tracing::warn!("shard {} is unhealthy: {:?}", shard.id, shard.status);
+        }
+
+        ShardReport {
+            id: shard.id.to_string(),
+            status: shard.status,
+            health,
+            session_id
+        }
+    }).collect();
+
+    // websocket shards are gone for good once disconnected, so the leader frees them for a
+    // standby worker; the report above still shows them unhealthy this once
+    let lost: Vec<usize> = reports.iter()
+        .filter(|_report| control_state.leader.is_leader())
+        .filter(|report| report.health == ShardHealth::Unhealthy && is_lost(&report.status))
+        .filter_map(|report| report.id.parse().ok())
+        .filter(|&shard| scheduler.assignment(shard).is_some_and(|assignment| assignment.webhook_secret.is_none()))
+        .collect();
+    for &shard in &lost {
+        if let Some(assignment) = scheduler.release(shard) {
+            tracing::warn!("shard {shard} lost session {}, unassigned it", assignment.session_id);
+            control_state.events.publish(ControlEvent::ShardUnassigned { conduit: lane.name.clone(), shard, worker_id: assignment.worker_id.clone(), reason: "session_lost" });
+            if let Some(worker_id) = &assignment.worker_id {
+                control_state.watch.notify(worker_id, Notice::Reconnect { conduit: lane.name.clone(), shard });
+            }
+        }
+    }
+    crate::fail_over(control_state, &workers, lane, &mut scheduler, &lost);
+    drop(scheduler);
+    drop(workers);
+
+    let shards = reports;
+    ConduitHealth {
+        checked_at,
+        healthy: shards.iter().all(|shard| shard.health != ShardHealth::Unhealthy),
+        twitch_reachable: true,
+        shards,
+        error: None
+    }
+}
@@ -0,0 +1,36 @@
+use crate::ControlState;
+use crate::config::NatsConfig;
+use crate::events;
+use alloc::sync::Arc;
+
+/// Connects in the background, reconnecting whenever the connection drops; events published
+/// while disconnected are buffered by the client.
+pub async fn connect(config: &NatsConfig) -> anyhow::Result<async_nats::Client> {
+    Ok(async_nats::ConnectOptions::new().name("firin-bot-control-plane").retry_on_initial_connect().connect(config.url.as_str()).await?)
+}
+
+/// Publishes every control event as JSON, for the rest of the bot to react to without polling.
+pub async fn run(control_state: Arc<ControlState<'static>>, client: async_nats::Client) -> ! {
+    let config = &control_state.config.nats;
+    let mut events = control_state.events.subscribe();
+    loop {
+        let stamped = events::next(&mut events, "NATS").await;
+
+        let name = stamped.event.name();
+        let subject = config.subjects.get(name).cloned().unwrap_or_else(|| format!("{}.{name}", config.subject_prefix));
+        let payload = match serde_json::to_vec(&stamped) {
+            Ok(payload) => payload,
+            Err(e) => {
+                tracing::error!("failed to serialize {name} event: {e}");
+                continue;
+            }
+        };
+
+        if let Err(e) = client.publish(subject, payload.into()).await {
+            tracing::warn!("failed to publish {name} event to NATS: {e}");
+            control_state.metrics.inc("control_event_publishes_total", &[("sink", "nats"), ("outcome", "error")]);
+        } else {
+            control_state.metrics.inc("control_event_publishes_total", &[("sink", "nats"), ("outcome", "ok")]);
+        }
+    }
+}
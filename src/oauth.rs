@@ -0,0 +1,276 @@
+use crate::ControlState;
+use crate::helix::BaseUrls;
+use crate::subscription::Authorizer;
+use crate::subscription::SubscriptionKind;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::Redirect;
+use axum::Json;
+use core::time::Duration;
+use serde::Deserialize;
+use serde::Serialize;
+use std::time::SystemTime;
+use tokio::sync::Mutex;
+use twitch_api::twitch_oauth2::ClientId;
+use twitch_api::twitch_oauth2::ClientSecret;
+use twitch_api::twitch_oauth2::CsrfToken;
+use twitch_api::twitch_oauth2::DeviceUserTokenBuilder;
+use twitch_api::twitch_oauth2::Scope;
+use twitch_api::twitch_oauth2::UserTokenBuilder;
+use twitch_api::twitch_oauth2::url::Url;
+
+/// How long a broadcaster has to finish authorizing after being sent to Twitch.
+const PENDING_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// How long an invite can be followed once issued.
+const INVITE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// At most this many invites, and as many authorizations, are outstanding at a time.
+const MAX_PENDING: usize = 100;
+
+/// Invites issued and not yet followed, keyed by their token, and authorizations sent to Twitch
+/// and not yet called back, keyed by their CSRF state. Both are persisted, so either step can be
+/// finished on whichever replica leads by then.
+#[derive(Default)]
+pub struct PendingAuthorizations {
+    invites: Mutex<BTreeMap<String, Outstanding>>,
+    pending: Mutex<BTreeMap<String, Outstanding>>
+}
+
+/// A one-time invite or an authorization in flight, fixing the profile the broadcaster is
+/// subscribed with.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Outstanding {
+    profile: Option<String>,
+    /// Unix seconds it was issued or sent to Twitch at.
+    since: u64
+}
+
+impl Outstanding {
+    fn new(profile: Option<String>) -> Self {
+        Self { profile, since: now() }
+    }
+
+    fn is_live(&self, ttl: Duration) -> bool {
+        now().saturating_sub(self.since) < ttl.as_secs()
+    }
+}
+
+impl PendingAuthorizations {
+    /// The invites and authorizations still live, for the snapshot.
+    pub async fn saved(&self) -> (BTreeMap<String, Outstanding>, BTreeMap<String, Outstanding>) {
+        let invites = self.invites.lock().await.iter().filter(|(_, invite)| invite.is_live(INVITE_TTL)).map(|(token, invite)| (token.clone(), invite.clone())).collect();
+        let pending = self.pending.lock().await.iter().filter(|(_, pending)| pending.is_live(PENDING_TTL)).map(|(state, pending)| (state.clone(), pending.clone())).collect();
+        (invites, pending)
+    }
+
+    /// Takes over what the previous leader had outstanding.
+    pub async fn restore(&self, invites: BTreeMap<String, Outstanding>, pending: BTreeMap<String, Outstanding>) {
+        *self.invites.lock().await = invites;
+        *self.pending.lock().await = pending;
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default()
+}
+
+fn redirect_url(control_state: &ControlState<'_>) -> Result<Url, (StatusCode, String)> {
+    let redirect_url = &control_state.config.twitch.redirect_url;
+    if redirect_url.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "oauth isn't configured, set twitch.redirect_url".to_owned()));
+    }
+    Url::parse(redirect_url).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("invalid twitch.redirect_url: {e}")))
+}
+
+#[derive(Deserialize)]
+pub struct InviteRequest {
+    profile: Option<String>
+}
+
+#[derive(Serialize)]
+pub struct IssuedInvite {
+    url: String,
+    expires_in_secs: u64
+}
+
+/// Issues a one-time link for a broadcaster to onboard themselves with `profile`, served next to
+/// `twitch.redirect_url`.
+pub async fn invite(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Json(body): Json<InviteRequest>
+) -> Result<(StatusCode, Json<IssuedInvite>), (StatusCode, String)> {
+    let redirect_url = redirect_url(&control_state)?;
+    control_state.config.subscription_types(None, body.profile.as_deref()).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+
+    let token = crate::keys::secret();
+    let mut url = redirect_url.join("authorize").map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("invalid twitch.redirect_url: {e}")))?;
+    url.query_pairs_mut().append_pair("invite", &token);
+
+    let mut invites = control_state.oauth.invites.lock().await;
+    invites.retain(|_, invite| invite.is_live(INVITE_TTL));
+    if invites.len() >= MAX_PENDING {
+        return Err((StatusCode::SERVICE_UNAVAILABLE, "too many invites outstanding, try again once some are used or expire".to_owned()));
+    }
+    invites.insert(token, Outstanding::new(body.profile));
+    drop(invites);
+
+    Ok((StatusCode::CREATED, Json(IssuedInvite { url: url.into(), expires_in_secs: INVITE_TTL.as_secs() })))
+}
+
+async fn token_builder(control_state: &ControlState<'_>, redirect_url: Url) -> UserTokenBuilder {
+    UserTokenBuilder::new(
+        ClientId::new(control_state.config.twitch.client_id.clone()),
+        ClientSecret::new(control_state.secrets.client_secret().await),
+        redirect_url
+    )
+}
+
+#[derive(Deserialize)]
+pub struct AuthorizeQuery {
+    invite: String
+}
+
+/// Sends the broadcaster to Twitch to grant the scopes their invite's profile needs, using up
+/// the invite.
+pub async fn authorize(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Query(query): Query<AuthorizeQuery>
+) -> Result<Redirect, (StatusCode, String)> {
+    let redirect_url = redirect_url(&control_state)?;
+    let invite = control_state.oauth.invites.lock().await.remove(&query.invite)
+        .filter(|invite| invite.is_live(INVITE_TTL))
+        .ok_or_else(|| (StatusCode::FORBIDDEN, "unknown, used or expired invite".to_owned()))?;
+
+    let subscription_types = control_state.config.subscription_types(None, invite.profile.as_deref()).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+    let mut scopes: Vec<_> = subscription_types.iter().copied()
+        .filter(|kind| kind.authorizer() == Some(Authorizer::Broadcaster))
+        .flat_map(crate::subscription::scopes)
+        .collect();
+    scopes.sort_unstable_by(|a, b| a.as_str().cmp(b.as_str()));
+    scopes.dedup();
+
+    let (url, state) = token_builder(&control_state, redirect_url).await.set_scopes(scopes).generate_url();
+
+    let mut pending = control_state.oauth.pending.lock().await;
+    pending.retain(|_, pending| pending.is_live(PENDING_TTL));
+    if pending.len() >= MAX_PENDING {
+        return Err((StatusCode::SERVICE_UNAVAILABLE, "too many authorizations in flight, try again shortly".to_owned()));
+    }
+    pending.insert(state.secret().to_owned(), Outstanding::new(invite.profile));
+    drop(pending);
+
+    let url = BaseUrls::from(&control_state.config.twitch).rewrite(url.as_str()).unwrap_or_else(|| url.into());
+    Ok(Redirect::to(&url))
+}
+
+#[derive(Deserialize)]
+pub struct CallbackQuery {
+    code: Option<String>,
+    state: String,
+    error: Option<String>,
+    error_description: Option<String>
+}
+
+/// Exchanges the code for the broadcaster's token, stores it, and registers the broadcaster with
+/// the profile they authorized for.
+pub async fn callback(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Query(query): Query<CallbackQuery>
+) -> Result<String, (StatusCode, String)> {
+    let redirect_url = redirect_url(&control_state)?;
+    let pending = control_state.oauth.pending.lock().await.remove(&query.state)
+        .filter(|pending| pending.is_live(PENDING_TTL))
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "unknown or expired authorization, start again".to_owned()))?;
+
+    let code = match (query.code, query.error) {
+        (Some(code), None) => code,
+        (_, error) => return Err((StatusCode::BAD_REQUEST, format!("authorization failed: {}", query.error_description.or(error).unwrap_or_default())))
+    };
+
+    // the authorization may have been started by another leader, so the builder is made anew
+    let mut builder = token_builder(&control_state, redirect_url).await;
+    builder.set_csrf(CsrfToken::new(query.state.clone()));
+    let token = builder.get_user_token(&control_state.client, &query.state, &code).await.map_err(|e| {
+        tracing::warn!("failed to exchange authorization code: {e:?}");
+        (StatusCode::BAD_GATEWAY, "failed to exchange the authorization code with Twitch".to_owned())
+    })?;
+    let login = token.login.to_string();
+    let info = control_state.tokens.insert(token).await;
+    tracing::info!("stored user token for {} ({}) with scopes {:?} from oauth", info.login, info.user_id, info.scopes);
+
+    if control_state.broadcasters.read().await.get(&login).is_some_and(|broadcaster| broadcaster.state == crate::BroadcasterState::Active) {
+        return Ok(format!("{login} is authorized, and was already onboarded"));
+    }
+
+    let subscription_types: Vec<SubscriptionKind> = control_state.config.subscription_types(None, pending.profile.as_deref())
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?
+        .to_vec();
+    crate::add_broadcaster(&control_state, &login, &subscription_types, pending.profile.as_deref()).await.map_err(|e| {
+        tracing::error!("{e:?}");
+        (StatusCode::BAD_GATEWAY, format!("{login} is authorized, but creating their subscriptions failed"))
+    })?;
+
+    Ok(format!("{login} is authorized and onboarded"))
+}
+
+#[derive(Serialize)]
+pub struct DeviceAuthorization {
+    user_code: String,
+    verification_uri: String,
+    expires_in_secs: u64
+}
+
+/// Starts a device code grant for the bot account, for deployments with no public redirect url.
+/// Whoever runs it signs in as the bot at `verification_uri`; the token then shows up under
+/// `/tokens` and is persisted with the rest of the state.
+pub async fn device(
+    State(control_state): State<Arc<ControlState<'static>>>
+) -> Result<Json<DeviceAuthorization>, (StatusCode, String)> {
+    let mut builder = DeviceUserTokenBuilder::new(ClientId::new(control_state.config.twitch.client_id.clone()), bot_scopes(&control_state));
+    builder.set_secret(Some(ClientSecret::new(control_state.secrets.client_secret().await)));
+
+    let response = builder.start(&control_state.client).await.map_err(|e| {
+        tracing::warn!("failed to start device authorization: {e:?}");
+        (StatusCode::BAD_GATEWAY, "failed to start the device authorization with Twitch".to_owned())
+    })?;
+    let authorization = DeviceAuthorization {
+        user_code: response.user_code.clone(),
+        verification_uri: response.verification_uri.clone(),
+        expires_in_secs: response.expires_in
+    };
+
+    tokio::spawn(async move {
+        match builder.wait_for_code(&control_state.client, tokio::time::sleep).await {
+            Ok(token) if token.user_id == control_state.my_user.id => {
+                let info = control_state.tokens.insert(token).await;
+                tracing::info!("stored bot token with scopes {:?} from device authorization", info.scopes);
+            },
+            Ok(token) => tracing::warn!("device authorization was for {}, not the bot {}, discarded it", token.login, control_state.my_user.login),
+            Err(e) => tracing::warn!("device authorization didn't complete: {e:?}")
+        }
+    });
+
+    Ok(Json(authorization))
+}
+
+/// What the bot's own token needs: the scopes of every configured type it authorizes or that
+/// ride on its user (chat reads, for one), rather than on the broadcaster's.
+fn bot_scopes(control_state: &ControlState<'_>) -> Vec<Scope> {
+    let config = &control_state.config;
+    let configured = config.subscriptions.iter()
+        .chain(config.profiles.values().flatten())
+        .chain(config.broadcasters.iter().filter_map(|broadcaster| broadcaster.subscriptions.as_ref()).flatten())
+        .chain(&config.twitch.bot_subscriptions);
+
+    let mut scopes: Vec<Scope> = configured.copied()
+        .filter(|kind| kind.authorizer() != Some(Authorizer::Broadcaster) || config.twitch.bot_subscriptions.contains(kind))
+        .flat_map(crate::subscription::scopes)
+        .collect();
+    scopes.sort_unstable_by(|a, b| a.as_str().cmp(b.as_str()));
+    scopes.dedup();
+    scopes
+}
@@ -0,0 +1,210 @@
+use crate::ControlState;
+use crate::proxy;
+use crate::proxy::Rejection;
+use alloc::sync::Arc;
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+use serde::Serialize;
+use twitch_api::helix::Request as _;
+use twitch_api::helix::polls::CreatePollBody;
+use twitch_api::helix::polls::CreatePollRequest;
+use twitch_api::helix::polls::EndPollBody;
+use twitch_api::helix::polls::EndPollRequest;
+use twitch_api::helix::polls::NewPollChoice;
+use twitch_api::helix::polls::Poll;
+use twitch_api::helix::polls::end_poll::EndPoll;
+use twitch_api::helix::predictions::Prediction;
+use twitch_api::helix::predictions::create_prediction::CreatePredictionBody;
+use twitch_api::helix::predictions::create_prediction::CreatePredictionRequest;
+use twitch_api::helix::predictions::create_prediction::NewPredictionOutcome;
+use twitch_api::helix::predictions::end_prediction::EndPrediction;
+use twitch_api::helix::predictions::end_prediction::EndPredictionBody;
+use twitch_api::helix::predictions::end_prediction::EndPredictionRequest;
+use twitch_api::types::PollChoice;
+use twitch_api::types::PollId;
+use twitch_api::types::PollStatus;
+use twitch_api::types::PredictionId;
+use twitch_api::types::PredictionOutcome;
+use twitch_api::types::PredictionOutcomeId;
+use twitch_api::types::PredictionStatus;
+use twitch_api::types::Timestamp;
+
+#[derive(Serialize)]
+pub struct PollSummary {
+    id: PollId,
+    title: String,
+    choices: Vec<PollChoice>,
+    status: PollStatus,
+    duration_secs: i64,
+    started_at: Timestamp,
+    ended_at: Option<Timestamp>
+}
+
+impl From<Poll> for PollSummary {
+    fn from(poll: Poll) -> Self {
+        Self {
+            id: poll.id,
+            title: poll.title,
+            choices: poll.choices,
+            status: poll.status,
+            duration_secs: poll.duration,
+            started_at: poll.started_at,
+            ended_at: poll.ended_at
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PredictionSummary {
+    id: PredictionId,
+    title: String,
+    outcomes: Vec<PredictionOutcome>,
+    winning_outcome_id: Option<PredictionOutcomeId>,
+    status: PredictionStatus,
+    window_secs: i64,
+    created_at: Timestamp,
+    locked_at: Option<Timestamp>,
+    ended_at: Option<Timestamp>
+}
+
+impl From<Prediction> for PredictionSummary {
+    fn from(prediction: Prediction) -> Self {
+        Self {
+            id: prediction.id,
+            title: prediction.title,
+            outcomes: prediction.outcomes,
+            winning_outcome_id: prediction.winning_outcome_id,
+            status: prediction.status,
+            window_secs: prediction.prediction_window,
+            created_at: prediction.created_at,
+            locked_at: prediction.locked_at,
+            ended_at: prediction.ended_at
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreatePoll {
+    title: String,
+    /// Two to five.
+    choices: Vec<String>,
+    duration_secs: i64,
+    /// Lets viewers buy extra votes at this many channel points each.
+    channel_points_per_vote: Option<i64>
+}
+
+/// Starts a poll in the broadcaster's channel, with their token.
+pub async fn create_poll(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(broadcaster): Path<String>,
+    Json(body): Json<CreatePoll>
+) -> Result<(StatusCode, Json<PollSummary>), Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::user_token(&control_state, &broadcaster_id, &CreatePollRequest::SCOPE).await?;
+
+    let choices: Vec<NewPollChoice<'_>> = body.choices.iter().map(|choice| NewPollChoice::new(choice.as_str())).collect();
+    let mut poll = CreatePollBody::new(&broadcaster_id, body.title, body.duration_secs, choices);
+    if let Some(points) = body.channel_points_per_vote {
+        poll = poll.channel_points_voting_enabled(true).channel_points_per_vote(points);
+    }
+
+    let poll = control_state.metrics.helix("create_poll", control_state.client.helix.req_post(CreatePollRequest::new(), poll, &token)).await.map_err(|e| proxy::rejection(&e))?.data;
+
+    Ok((StatusCode::CREATED, Json(poll.into())))
+}
+
+#[derive(Deserialize)]
+pub struct EndPollQuery {
+    /// Also hides the results from viewers.
+    #[serde(default)]
+    archive: bool
+}
+
+pub async fn end_poll(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path((broadcaster, id)): Path<(String, PollId)>,
+    Query(query): Query<EndPollQuery>
+) -> Result<Json<PollSummary>, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::user_token(&control_state, &broadcaster_id, &EndPollRequest::SCOPE).await?;
+
+    let status = if query.archive { PollStatus::Archived } else { PollStatus::Terminated };
+    let body = EndPollBody::new(&broadcaster_id, &id, status);
+    match control_state.metrics.helix("end_poll", control_state.client.helix.req_patch(EndPollRequest::new(), body, &token)).await.map_err(|e| proxy::rejection(&e))?.data {
+        EndPoll::Success(poll) => Ok(Json(poll.into())),
+        _ => Err((StatusCode::BAD_GATEWAY, "Twitch refused to end the poll".to_owned()))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreatePrediction {
+    title: String,
+    /// Two to ten.
+    outcomes: Vec<String>,
+    /// How long viewers have to predict.
+    window_secs: i64
+}
+
+/// Starts a prediction in the broadcaster's channel, with their token.
+pub async fn create_prediction(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(broadcaster): Path<String>,
+    Json(body): Json<CreatePrediction>
+) -> Result<(StatusCode, Json<PredictionSummary>), Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+    let token = proxy::user_token(&control_state, &broadcaster_id, &CreatePredictionRequest::SCOPE).await?;
+
+    let outcomes: Vec<NewPredictionOutcome<'_>> = body.outcomes.iter().map(|outcome| NewPredictionOutcome::new(outcome.as_str())).collect();
+    let prediction = CreatePredictionBody::new(&broadcaster_id, body.title, &outcomes, body.window_secs);
+    let prediction = control_state.metrics.helix("create_prediction", control_state.client.helix.req_post(CreatePredictionRequest::new(), prediction, &token)).await.map_err(|e| proxy::rejection(&e))?.data;
+
+    Ok((StatusCode::CREATED, Json(prediction.into())))
+}
+
+/// Stops taking predictions, to be resolved or canceled later.
+pub async fn lock_prediction(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path((broadcaster, id)): Path<(String, PredictionId)>
+) -> Result<Json<PredictionSummary>, Rejection> {
+    end_prediction(&control_state, &broadcaster, &id, PredictionStatus::Locked, None).await
+}
+
+#[derive(Deserialize)]
+pub struct ResolvePrediction {
+    winning_outcome_id: PredictionOutcomeId
+}
+
+/// Pays out to whoever predicted `winning_outcome_id`.
+pub async fn resolve_prediction(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path((broadcaster, id)): Path<(String, PredictionId)>,
+    Json(body): Json<ResolvePrediction>
+) -> Result<Json<PredictionSummary>, Rejection> {
+    end_prediction(&control_state, &broadcaster, &id, PredictionStatus::Resolved, Some(body.winning_outcome_id)).await
+}
+
+/// Refunds everyone's channel points.
+pub async fn cancel_prediction(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path((broadcaster, id)): Path<(String, PredictionId)>
+) -> Result<Json<PredictionSummary>, Rejection> {
+    end_prediction(&control_state, &broadcaster, &id, PredictionStatus::Canceled, None).await
+}
+
+async fn end_prediction(control_state: &ControlState<'_>, broadcaster: &str, id: &PredictionId, status: PredictionStatus, winning_outcome_id: Option<PredictionOutcomeId>) -> Result<Json<PredictionSummary>, Rejection> {
+    let broadcaster_id = proxy::user_id(control_state, broadcaster).await?;
+    let token = proxy::user_token(control_state, &broadcaster_id, &EndPredictionRequest::SCOPE).await?;
+
+    let mut body = EndPredictionBody::new(&broadcaster_id, id, status);
+    if let Some(winning_outcome_id) = &winning_outcome_id {
+        body = body.winning_outcome_id(winning_outcome_id);
+    }
+    match control_state.metrics.helix("end_prediction", control_state.client.helix.req_patch(EndPredictionRequest::new(), body, &token)).await.map_err(|e| proxy::rejection(&e))?.data {
+        EndPrediction::Success(prediction) => Ok(Json(prediction.into())),
+        _ => Err((StatusCode::BAD_GATEWAY, "Twitch refused to end the prediction".to_owned()))
+    }
+}
@@ -0,0 +1,52 @@
+use crate::ControlState;
+use axum::http::StatusCode;
+use twitch_api::helix::ClientRequestError;
+use twitch_api::twitch_oauth2::TwitchToken as _;
+use twitch_api::twitch_oauth2::UserToken;
+use twitch_api::twitch_oauth2::Validator;
+use twitch_api::types::UserId;
+
+/// Why a call made on a client's behalf didn't go through, for that client.
+pub type Rejection = (StatusCode, String);
+
+/// The user behind a login in the path: a registered broadcaster, or else whoever Twitch knows
+/// by it.
+pub async fn user_id(control_state: &ControlState<'_>, login: &str) -> Result<UserId, Rejection> {
+    let registered = control_state.broadcasters.read().await.get(login).map(|broadcaster| broadcaster.user_id.clone());
+    if let Some(user_id) = registered {
+        return Ok(user_id);
+    }
+
+    match crate::users::resolve(control_state, login).await {
+        Ok(Some(user_id)) => Ok(user_id),
+        Ok(None) => Err((StatusCode::NOT_FOUND, format!("no such user {login}"))),
+        Err(e) => Err(rejection(&e))
+    }
+}
+
+/// The token stored for `user_id`, as long as it has the scopes `required`.
+pub async fn user_token(control_state: &ControlState<'_>, user_id: &UserId, required: &Validator) -> Result<UserToken, Rejection> {
+    let token = control_state.tokens.get(user_id).await
+        .ok_or_else(|| (StatusCode::FORBIDDEN, format!("no token for user {user_id}, authorize them through /oauth/authorize")))?;
+
+    match required.missing(token.scopes()) {
+        None => Ok(token),
+        Some(missing) => Err((StatusCode::FORBIDDEN, format!("the token for {} is missing scopes {missing}", token.login)))
+    }
+}
+
+pub async fn bot_token(control_state: &ControlState<'_>, required: &Validator) -> Result<UserToken, Rejection> {
+    user_token(control_state, &control_state.my_user.id, required).await
+}
+
+/// Passes on what Twitch said about a request it refused, other than our token being rejected,
+/// which isn't the client's to fix. Anything else is a bad gateway.
+pub fn rejection<RE: core::error::Error + Send + Sync + 'static>(err: &ClientRequestError<RE>) -> Rejection {
+    tracing::warn!("{err:?}");
+
+    match (crate::helix::status(err), crate::helix::message(err)) {
+        (Some(StatusCode::UNAUTHORIZED), message) => (StatusCode::BAD_GATEWAY, format!("Twitch rejected our token: {}", message.unwrap_or_default())),
+        (Some(status), Some(message)) if status.is_client_error() => (status, message.to_owned()),
+        _ => (StatusCode::BAD_GATEWAY, err.to_string())
+    }
+}
@@ -0,0 +1,99 @@
+use alloc::collections::BTreeMap;
+use core::time::Duration;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::time::Instant;
+
+/// At most this many clients are tracked; past it, the least recently seen is dropped.
+const MAX_TRACKED: usize = 10_000;
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    /// Where it sits in `Buckets::recency`.
+    seen: u64
+}
+
+#[derive(Debug)]
+struct Buckets {
+    by_key: BTreeMap<String, Bucket>,
+    /// Keys by when they were last seen, oldest first.
+    recency: BTreeMap<u64, String>,
+    next_seen: u64
+}
+
+/// Token buckets keyed by client, refilling at `per_minute` up to `burst`.
+#[derive(Debug)]
+pub struct RateLimiter {
+    per_minute: u32,
+    burst: u32,
+    buckets: Mutex<Buckets>
+}
+
+impl RateLimiter {
+    pub const fn new(per_minute: u32, burst: u32) -> Self {
+        Self {
+            per_minute,
+            burst,
+            buckets: Mutex::new(Buckets { by_key: BTreeMap::new(), recency: BTreeMap::new(), next_seen: 0 })
+        }
+    }
+
+    /// Takes a token from every bucket in `keys`, or none of them and how long until the first
+    /// one short of a token has one. Keys are checked in order, and buckets are only made for
+    /// clients let through, so a rejected request can't grow the map.
+    pub fn check(&self, keys: &[String]) -> Result<(), Duration> {
+        if self.per_minute == 0 {
+            return Ok(());
+        }
+
+        let rate = f64::from(self.per_minute) / 60.0;
+        let burst = f64::from(self.burst.max(1));
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        for key in keys {
+            let Some(bucket) = buckets.by_key.get_mut(key) else {
+                continue;
+            };
+            bucket.tokens = now.duration_since(bucket.updated_at).as_secs_f64().mul_add(rate, bucket.tokens).min(burst);
+            bucket.updated_at = now;
+
+            if bucket.tokens < 1.0 {
+                let wait = Duration::from_secs_f64((1.0 - bucket.tokens) / rate);
+                drop(buckets);
+                return Err(wait);
+            }
+        }
+
+        for key in keys {
+            buckets.take(key, burst, now);
+        }
+        drop(buckets);
+
+        Ok(())
+    }
+}
+
+impl Buckets {
+    /// Spends a token from `key`'s bucket, starting it full if it's new, and marks it as seen.
+    fn take(&mut self, key: &str, burst: f64, now: Instant) {
+        let seen = self.next_seen;
+        self.next_seen += 1;
+
+        if let Some(bucket) = self.by_key.get_mut(key) {
+            self.recency.remove(&bucket.seen);
+            bucket.tokens -= 1.0;
+            bucket.seen = seen;
+        } else {
+            if self.by_key.len() >= MAX_TRACKED
+                && let Some((_, oldest)) = self.recency.pop_first()
+            {
+                self.by_key.remove(&oldest);
+            }
+            self.by_key.insert(key.to_owned(), Bucket { tokens: burst - 1.0, updated_at: now, seen });
+        }
+        self.recency.insert(seen, key.to_owned());
+    }
+}
@@ -0,0 +1,181 @@
+use crate::BroadcasterState;
+use crate::ControlState;
+use crate::conduit::DEFAULT;
+use crate::subscription;
+use crate::subscription::Description;
+use crate::subscription::SubscriptionKind;
+use crate::subscription::Target;
+use alloc::collections::BTreeMap;
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
+use core::time::Duration;
+use futures_util::StreamExt as _;
+use tokio::time::Instant;
+use twitch_api::eventsub::EventSubSubscription;
+use twitch_api::eventsub::Status;
+use twitch_api::eventsub::TransportResponse;
+
+/// How often creating missing subscriptions reports how far along it is.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+pub async fn run(control_state: Arc<ControlState<'static>>) -> ! {
+    let mut interval = tokio::time::interval(Duration::from_secs(control_state.config.conduit.reconcile_interval_secs));
+    loop {
+        interval.tick().await;
+        if !control_state.leader.is_leader() {
+            continue;
+        }
+
+        if let Err(e) = reconcile(&control_state).await {
+            tracing::error!("subscription reconciliation failed: {e:?}");
+        }
+
+        if let Err(e) = crate::conduit::autoscale(&control_state).await {
+            tracing::error!("conduit autoscaling failed: {e:?}");
+        }
+    }
+}
+
+/// Diffs broadcasters × subscription types against what Twitch has, creating what's missing
+/// and deleting what's dead, unwanted, or pointing at a conduit that no longer exists.
+pub async fn reconcile(control_state: &ControlState<'_>) -> anyhow::Result<()> {
+    let _guard = control_state.subscription_lock.lock().await;
+
+    let existing = subscription::list(control_state).await?.subscriptions;
+
+    let conduits: BTreeSet<String> = control_state.metrics.helix("get_conduits", control_state.twitch.conduits(&control_state.app_token.get().await)).await?
+        .into_iter()
+        .map(|conduit| conduit.id.to_string())
+        .collect();
+
+    // each type belongs on the conduit it's routed to, anywhere else it's orphaned
+    let mut ours = BTreeMap::new();
+    for lane in control_state.conduits.iter() {
+        let id = lane.conduit.read().await.id.to_string();
+        if !conduits.contains(&id) {
+            return Err(anyhow::anyhow!("{} conduit {id} no longer exists, leaving it to the health check to recreate", lane.name));
+        }
+        ours.insert(lane.name.as_str(), id);
+    }
+    let default = ours.get(DEFAULT).cloned().unwrap_or_default();
+    let conduit_for = |kind: SubscriptionKind| ours.get(control_state.config.conduit_for(kind)).map_or(default.as_str(), String::as_str);
+
+    let conduit_of = |subscription: &EventSubSubscription| match &subscription.transport {
+        TransportResponse::Conduit(transport) => Some(transport.conduit_id.clone()),
+        _ => None
+    };
+
+    let find = |description: &Description, statuses: &[Status], conduit: &str| existing.iter().find(|subscription| {
+        statuses.contains(&subscription.status)
+            && conduit_of(subscription).as_deref() == Some(conduit)
+            && description.matches(&subscription.type_, &subscription.version, &subscription.condition)
+    });
+
+    let mut kept = BTreeSet::new();
+    // what was already live is counted rather than taken from `kept`, as a create can answer
+    // with an id already in it
+    let (mut unchanged, mut created, mut failed) = (0usize, 0usize, 0usize);
+
+    let revocation = match find(&subscription::describe_revocation(control_state), &[Status::Enabled], &default) {
+        Some(live) => {
+            unchanged += 1;
+            Ok(live.id.clone())
+        },
+        None => subscription::create_revocation(control_state).await.inspect(|_| created += 1)
+    };
+
+    match revocation {
+        Ok(id) => {
+            kept.insert(id);
+        },
+        Err(e) => {
+            failed += 1;
+            tracing::error!("failed to create user.authorization.revoke subscription: {e:?}");
+        }
+    }
+
+    let broadcasters: Vec<_> = control_state.broadcasters.read().await.values().filter(|broadcaster| broadcaster.state == BroadcasterState::Active).cloned().collect();
+    let mut missing = Vec::new();
+    for broadcaster in broadcasters {
+        let target = Target {
+            broadcaster_id: &broadcaster.user_id,
+            bot_id: &control_state.my_user.id
+        };
+
+        // in case a revocation notification was missed; whatever they had is orphaned below
+        let revoked = broadcaster.subscription_types.iter().any(|&kind| find(&subscription::describe(kind, target), &[Status::AuthorizationRevoked, Status::UserRemoved], conduit_for(kind)).is_some());
+        if revoked {
+            crate::revoke_broadcaster(control_state, &broadcaster.user_id).await;
+            continue;
+        }
+
+        for &kind in &broadcaster.subscription_types {
+            match find(&subscription::describe(kind, target), &[Status::Enabled], conduit_for(kind)) {
+                Some(live) => {
+                    unchanged += 1;
+                    kept.insert(live.id.clone());
+                    if let Some(entry) = control_state.broadcasters.write().await.get_mut(&broadcaster.login) {
+                        entry.subscriptions.insert(kind, live.id.clone());
+                    }
+                },
+                None => missing.push((broadcaster.login.clone(), broadcaster.user_id.clone(), kind))
+            }
+        }
+    }
+
+    // a few at a time, so bootstrapping hundreds of channels doesn't take one round trip each
+    let total = missing.len();
+    let mut creations = futures_util::stream::iter(missing)
+        .map(|(login, user_id, kind)| async move {
+            let result = subscription::create(control_state, kind, &user_id).await;
+            (login, kind, result)
+        })
+        .buffer_unordered(control_state.config.conduit.create_concurrency);
+
+    let mut last_progress = Instant::now();
+    let mut done = 0usize;
+    while let Some((login, kind, result)) = creations.next().await {
+        done += 1;
+        match result {
+            Ok(id) => {
+                created += 1;
+                control_state.retries.lock().await.record_success(&login, kind);
+                kept.insert(id.clone());
+                if let Some(entry) = control_state.broadcasters.write().await.get_mut(&login) {
+                    entry.subscriptions.insert(kind, id);
+                }
+            },
+            Err(e) => {
+                failed += 1;
+                let state = control_state.retries.lock().await.record_failure(&login, kind, &e, Instant::now());
+                tracing::error!("failed to create {kind} subscription for {login} ({state:?}): {e:?}");
+            }
+        }
+
+        if last_progress.elapsed() >= PROGRESS_INTERVAL && done < total {
+            tracing::info!("created {done} of {total} missing subscriptions ({failed} failed)");
+            last_progress = Instant::now();
+        }
+    }
+
+    let mut deleted = 0usize;
+    for subscription in &existing {
+        let Some(conduit_id) = conduit_of(subscription) else {
+            continue;
+        };
+
+        let orphaned = if ours.values().any(|id| *id == conduit_id) { !kept.contains(&subscription.id) } else { !conduits.contains(&conduit_id) };
+        if !orphaned {
+            continue;
+        }
+
+        match control_state.metrics.helix("delete_eventsub_subscription", control_state.twitch.delete_subscription(&subscription.id, &control_state.app_token.get().await)).await {
+            Ok(_) => deleted += 1,
+            Err(e) => tracing::error!("failed to delete orphaned subscription {}: {e:?}", subscription.id)
+        }
+    }
+
+    tracing::info!("reconciled subscriptions: {unchanged} kept, {created} created, {failed} failed, {deleted} deleted");
+
+    Ok(())
+}
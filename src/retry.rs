@@ -0,0 +1,137 @@
+use crate::BroadcasterState;
+use crate::ControlState;
+use crate::subscription;
+use crate::subscription::SubscriptionKind;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::time::Duration;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::time::Instant;
+
+const BASE_DELAY: Duration = Duration::from_secs(5);
+const MAX_DELAY: Duration = Duration::from_secs(600);
+const MAX_ATTEMPTS: u32 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryState {
+    Pending,
+    /// Not transient, or out of attempts; left for an operator to look at.
+    Failed
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RetryEntry {
+    pub login: String,
+    pub kind: SubscriptionKind,
+    pub state: RetryState,
+    pub attempts: u32,
+    pub last_error: String,
+    #[serde(skip, default = "Instant::now")]
+    pub next_attempt: Instant
+}
+
+/// Subscription creations that failed, keyed by broadcaster login and type.
+#[derive(Debug, Default)]
+pub struct RetryQueue {
+    entries: BTreeMap<(String, SubscriptionKind), RetryEntry>
+}
+
+impl RetryQueue {
+    pub fn record_failure(&mut self, login: &str, kind: SubscriptionKind, error: &anyhow::Error, now: Instant) -> RetryState {
+        let entry = self.entries.entry((login.to_owned(), kind)).or_insert_with(|| RetryEntry {
+            login: login.to_owned(),
+            kind,
+            state: RetryState::Pending,
+            attempts: 0,
+            last_error: String::new(),
+            next_attempt: now
+        });
+
+        entry.attempts = entry.attempts.saturating_add(1);
+        entry.last_error = format!("{error:#}");
+        entry.state = if crate::helix::is_transient(error) && entry.attempts < MAX_ATTEMPTS { RetryState::Pending } else { RetryState::Failed };
+        entry.next_attempt = now + backoff(entry.attempts);
+        entry.state
+    }
+
+    pub fn record_success(&mut self, login: &str, kind: SubscriptionKind) {
+        self.entries.remove(&(login.to_owned(), kind));
+    }
+
+    pub fn forget(&mut self, login: &str) {
+        self.entries.retain(|(entry_login, _), _| entry_login != login);
+    }
+
+    pub fn due(&self, now: Instant) -> Vec<(String, SubscriptionKind)> {
+        self.entries.values()
+            .filter(|entry| entry.state == RetryState::Pending && entry.next_attempt <= now)
+            .map(|entry| (entry.login.clone(), entry.kind))
+            .collect()
+    }
+
+    /// Puts back an entry from before a restart, due immediately.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn restore(&mut self, entry: RetryEntry) {
+        self.entries.insert((entry.login.clone(), entry.kind), RetryEntry { next_attempt: Instant::now(), ..entry });
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RetryEntry> {
+        self.entries.values()
+    }
+}
+
+/// Doubles from `BASE_DELAY` up to `MAX_DELAY`, with up to 10% jitter so retries don't line up.
+fn backoff(attempts: u32) -> Duration {
+    let delay = BASE_DELAY.saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1))).min(MAX_DELAY);
+    delay + delay.mul_f64(rand::random::<f64>() / 10.0)
+}
+
+pub async fn run(control_state: Arc<ControlState<'static>>) -> ! {
+    let mut interval = tokio::time::interval(BASE_DELAY);
+    loop {
+        interval.tick().await;
+        if !control_state.leader.is_leader() {
+            continue;
+        }
+
+        let due = control_state.retries.lock().await.due(Instant::now());
+        for (login, kind) in due {
+            retry(&control_state, &login, kind).await;
+        }
+    }
+}
+
+async fn retry(control_state: &ControlState<'_>, login: &str, kind: SubscriptionKind) {
+    let _guard = control_state.subscription_lock.lock().await;
+
+    // the broadcaster may have been removed, or already picked up by reconciliation
+    let broadcaster = control_state.broadcasters.read().await.get(login).cloned().filter(|broadcaster| {
+        broadcaster.state == BroadcasterState::Active
+            && broadcaster.subscription_types.contains(&kind)
+            && !broadcaster.subscriptions.contains_key(&kind)
+    });
+
+    let Some(broadcaster) = broadcaster else {
+        control_state.retries.lock().await.record_success(login, kind);
+        return;
+    };
+
+    match subscription::create(control_state, kind, &broadcaster.user_id).await {
+        Ok(id) => {
+            tracing::info!("created {kind} subscription for {login} on retry");
+            control_state.retries.lock().await.record_success(login, kind);
+            if let Some(entry) = control_state.broadcasters.write().await.get_mut(login) {
+                entry.subscriptions.insert(kind, id);
+            }
+        },
+        Err(e) => {
+            let state = control_state.retries.lock().await.record_failure(login, kind, &e, Instant::now());
+            tracing::error!("retry of {kind} subscription for {login} failed ({state:?}): {e:?}");
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use serde::Deserialize;
+use serde::Serialize;
+use std::time::SystemTime;
+use twitch_api::eventsub::Transport;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ShardAssignment {
+    /// The websocket session, or for webhook shards the callback url.
+    pub session_id: String,
+    pub worker_id: Option<String>,
+    /// Set for webhook shards, the secret Twitch signs their notifications with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_secret: Option<String>,
+    /// Unix seconds, unknown for assignments restored from a store that doesn't keep it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assigned_at: Option<u64>
+}
+
+impl ShardAssignment {
+    /// Whether the shard is only held for a standby worker that hasn't connected to it yet.
+    pub const fn is_reserved(&self) -> bool {
+        self.session_id.is_empty()
+    }
+
+    pub fn transport(&self) -> Transport {
+        self.webhook_secret.as_ref().map_or_else(
+            || Transport::websocket(self.session_id.clone()),
+            |secret| Transport::webhook(&self.session_id, secret.clone())
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AssignError {
+    NoFreeShard,
+    OutOfRange,
+    OutsidePool,
+    Conflict
+}
+
+/// An assignment and the shard it's on.
+type Placed = (usize, ShardAssignment);
+
+/// Tracks which worker session owns which shard of the conduit.
+#[derive(Debug)]
+pub struct Scheduler {
+    shards: Vec<Option<ShardAssignment>>
+}
+
+impl Scheduler {
+    pub fn new(shard_count: usize) -> Self {
+        Self {
+            shards: vec![None; shard_count]
+        }
+    }
+
+    /// Hands out `requested` if it's free or already ours, otherwise the shard already held by
+    /// this worker (or session), or else the lowest free one. A `pool` limits both to its shards.
+    pub fn assign(&mut self, session_id: &str, worker_id: Option<&str>, requested: Option<usize>, pool: Option<&[usize]>, webhook_secret: Option<String>) -> Result<usize, AssignError> {
+        let held = worker_id.and_then(|worker_id| self.shard_of_worker(worker_id)).or_else(|| self.shard_of(session_id));
+        let in_pool = |shard: &usize| pool.is_none_or(|pool| pool.contains(shard));
+
+        let shard = match requested {
+            Some(shard) => {
+                let slot = self.shards.get(shard).ok_or(AssignError::OutOfRange)?;
+                if !in_pool(&shard) {
+                    return Err(AssignError::OutsidePool);
+                }
+
+                let ours = slot.as_ref().is_none_or(|assignment| match (worker_id, &assignment.worker_id) {
+                    (Some(worker_id), Some(owner)) => worker_id == owner,
+                    _ => assignment.session_id == session_id
+                });
+                if !ours {
+                    return Err(AssignError::Conflict);
+                }
+
+                shard
+            },
+            None => held.filter(in_pool).or_else(|| self.free(pool)).ok_or(AssignError::NoFreeShard)?
+        };
+
+        // moving to a different shard gives up the old one
+        if let Some(held) = held.filter(|&held| held != shard) {
+            self.release(held);
+        }
+
+        let slot = self.shards.get_mut(shard).ok_or(AssignError::OutOfRange)?;
+        *slot = Some(ShardAssignment {
+            session_id: session_id.to_owned(),
+            worker_id: worker_id.map(str::to_owned),
+            webhook_secret,
+            assigned_at: now()
+        });
+
+        Ok(shard)
+    }
+
+    /// Holds a free shard for `worker_id` until it assigns itself a session, which it then gets
+    /// this shard for as the one it already holds.
+    pub fn reserve(&mut self, shard: usize, worker_id: &str) -> bool {
+        let Some(slot) = self.shards.get_mut(shard).filter(|slot| slot.is_none()) else {
+            return false;
+        };
+
+        *slot = Some(ShardAssignment {
+            session_id: String::new(),
+            worker_id: Some(worker_id.to_owned()),
+            webhook_secret: None,
+            assigned_at: now()
+        });
+        true
+    }
+
+    /// Reserves each vacated shard for the next standby worker not already holding one. Returns
+    /// which worker each went to.
+    pub fn fail_over(&mut self, shards: &[usize], standbys: &[&str]) -> Vec<(usize, String)> {
+        let standbys: Vec<&str> = standbys.iter().copied().filter(|worker_id| self.shard_of_worker(worker_id).is_none()).collect();
+
+        shards.iter().copied().zip(standbys)
+            .filter(|&(shard, worker_id)| self.reserve(shard, worker_id))
+            .map(|(shard, worker_id)| (shard, worker_id.to_owned()))
+            .collect()
+    }
+
+    /// Reserves `shard` for the first of `standbys` not already holding one, in place of whoever
+    /// has it now. Returns which worker it went to.
+    pub fn hand_over(&mut self, shard: usize, standbys: &[&str]) -> Option<String> {
+        let standby = standbys.iter().copied().find(|worker_id| self.shard_of_worker(worker_id).is_none())?;
+        self.release(shard);
+        self.reserve(shard, standby).then(|| standby.to_owned())
+    }
+
+    pub fn release(&mut self, shard: usize) -> Option<ShardAssignment> {
+        self.shards.get_mut(shard).and_then(Option::take)
+    }
+
+    pub fn release_worker(&mut self, worker_id: &str) -> Vec<usize> {
+        let shards: Vec<usize> = self.shards_of_worker(worker_id).collect();
+        for &shard in &shards {
+            self.release(shard);
+        }
+        shards
+    }
+
+    /// Puts back an assignment from before a restart; `false` if the shard no longer exists.
+    pub fn restore(&mut self, shard: usize, assignment: ShardAssignment) -> bool {
+        let Some(slot) = self.shards.get_mut(shard) else {
+            return false;
+        };
+
+        *slot = Some(assignment);
+        true
+    }
+
+    /// Grows or shrinks to `shard_count`, moving assignments off removed shards onto remaining
+    /// free ones. Returns the moved assignments with their new shard, and the ones there was no
+    /// room for with the shard they had.
+    pub fn resize(&mut self, shard_count: usize) -> (Vec<Placed>, Vec<Placed>) {
+        let kept = shard_count.min(self.shards.len());
+        let removed: Vec<(usize, ShardAssignment)> = self.shards.drain(kept..).enumerate().filter_map(|(offset, slot)| Some((kept + offset, slot?))).collect();
+        self.shards.resize(shard_count, None);
+
+        let (mut moved, mut dropped) = (Vec::new(), Vec::new());
+        for (old, assignment) in removed {
+            match self.shards.iter_mut().enumerate().find(|(_, slot)| slot.is_none()) {
+                Some((shard, slot)) => {
+                    *slot = Some(assignment.clone());
+                    moved.push((shard, assignment));
+                },
+                None => dropped.push((old, assignment))
+            }
+        }
+
+        (moved, dropped)
+    }
+
+    pub fn clear(&mut self) {
+        self.shards.fill(None);
+    }
+
+    pub fn assignments(&self) -> impl Iterator<Item = (usize, &ShardAssignment)> {
+        self.shards.iter().enumerate().filter_map(|(shard, slot)| Some((shard, slot.as_ref()?)))
+    }
+
+    pub fn assignment(&self, shard: usize) -> Option<&ShardAssignment> {
+        self.shards.get(shard).and_then(Option::as_ref)
+    }
+
+    pub fn shard_of(&self, session_id: &str) -> Option<usize> {
+        self.shards.iter().position(|slot| slot.as_ref().is_some_and(|assignment| assignment.session_id == session_id))
+    }
+
+    /// The shard waiting for this standby worker to connect, if any.
+    pub fn reserved_for(&self, worker_id: &str) -> Option<usize> {
+        self.shards_of_worker(worker_id).find(|&shard| self.assignment(shard).is_some_and(ShardAssignment::is_reserved))
+    }
+
+    pub fn shard_of_worker(&self, worker_id: &str) -> Option<usize> {
+        self.shards_of_worker(worker_id).next()
+    }
+
+    pub fn shards_of_worker<'a>(&'a self, worker_id: &'a str) -> impl Iterator<Item = usize> + 'a {
+        self.shards.iter().enumerate()
+            .filter(move |(_, slot)| slot.as_ref().is_some_and(|assignment| assignment.worker_id.as_deref() == Some(worker_id)))
+            .map(|(shard, _)| shard)
+    }
+
+    pub fn has_free(&self, pool: Option<&[usize]>) -> bool {
+        self.free(pool).is_some()
+    }
+
+    fn free(&self, pool: Option<&[usize]>) -> Option<usize> {
+        self.shards.iter().enumerate()
+            .find(|(shard, slot)| slot.is_none() && pool.is_none_or(|pool| pool.contains(shard)))
+            .map(|(shard, _)| shard)
+    }
+}
+
+fn now() -> Option<u64> {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).ok().map(|since| since.as_secs())
+}
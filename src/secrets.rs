@@ -0,0 +1,111 @@
+use alloc::sync::Arc;
+use crate::ControlState;
+use crate::config::Config;
+use crate::config::SecretsConfig;
+use crate::keys::KeyScope;
+use core::time::Duration;
+use serde::Deserialize;
+use tokio::sync::RwLock;
+
+/// What the secrets backend may hold; anything missing falls back to config.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Secrets {
+    pub twitch_client_secret: Option<String>,
+    pub control_token: Option<String>,
+    pub api_keys: Vec<ExternalKey>
+}
+
+/// An API key provisioned in the secrets backend rather than through `/keys`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ExternalKey {
+    pub name: String,
+    pub scope: KeyScope,
+    pub key: String
+}
+
+/// The secrets that can change at runtime, seeded from config and refreshed from the backend.
+#[derive(Debug)]
+pub struct RuntimeSecrets {
+    client_secret: RwLock<String>,
+    control_token: RwLock<String>
+}
+
+impl RuntimeSecrets {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            client_secret: RwLock::new(config.twitch.client_secret.clone()),
+            control_token: RwLock::new(config.control.token.clone())
+        }
+    }
+
+    pub async fn client_secret(&self) -> String {
+        self.client_secret.read().await.clone()
+    }
+
+    pub async fn is_control_token(&self, token: &str) -> bool {
+        *self.control_token.read().await == token
+    }
+
+    async fn update(&self, secrets: &Secrets) {
+        if let Some(client_secret) = &secrets.twitch_client_secret {
+            client_secret.clone_into(&mut *self.client_secret.write().await);
+        }
+        if let Some(control_token) = &secrets.control_token {
+            control_token.clone_into(&mut *self.control_token.write().await);
+        }
+    }
+}
+
+/// Reads the secrets from a KV-over-HTTP backend. Vault's KV v2 (`{"data": {"data": ...}}`) and
+/// v1 (`{"data": ...}`) responses are unwrapped; anything else is taken as the secrets object itself.
+pub async fn fetch(config: &SecretsConfig) -> anyhow::Result<Secrets> {
+    let mut request = reqwest::Client::new().get(&config.url);
+    if !config.token.is_empty() {
+        request = request.bearer_auth(&config.token);
+    }
+
+    let body = request.send().await?.error_for_status()?.bytes().await?;
+    let mut value: serde_json::Value = serde_json::from_slice(&body)?;
+
+    for _ in 0..2 {
+        if let Some(data) = value.get_mut("data").filter(|data| data.is_object()) {
+            value = data.take();
+        }
+    }
+
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Fills in config from fetched secrets, before anything has read it.
+pub fn apply(config: &mut Config, secrets: &Secrets) -> anyhow::Result<()> {
+    if let Some(client_secret) = &secrets.twitch_client_secret {
+        config.twitch.client_secret.clone_from(client_secret);
+    }
+    if let Some(control_token) = &secrets.control_token {
+        config.control.token.clone_from(control_token);
+    }
+
+    if config.twitch.client_secret.is_empty() { return Err(anyhow::anyhow!("missing twitch.client_secret, neither configured nor in the secrets backend")); }
+    if config.control.token.is_empty()        { return Err(anyhow::anyhow!("missing control.token, neither configured nor in the secrets backend")); }
+
+    Ok(())
+}
+
+/// Refetches the secrets on an interval, so rotating them in the backend reaches new worker
+/// assignments, token refreshes and key checks without a restart.
+pub async fn run(control_state: Arc<ControlState<'static>>) -> ! {
+    let mut interval = tokio::time::interval(Duration::from_secs(control_state.config.secrets.refresh_interval_secs));
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+
+        match fetch(&control_state.config.secrets).await {
+            Ok(secrets) => {
+                control_state.secrets.update(&secrets).await;
+                control_state.keys.set_external(secrets.api_keys).await;
+            },
+            Err(e) => tracing::error!("failed to refresh secrets, keeping the current ones: {e:?}")
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use crate::ControlState;
+use core::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// Resolves on the first SIGINT or SIGTERM.
+pub async fn signal() {
+    let interrupt = tokio::signal::ctrl_c();
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            },
+            Err(e) => {
+                tracing::error!("failed to listen for SIGTERM: {e:?}");
+                core::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = core::future::pending::<()>();
+
+    tokio::select! {
+        result = interrupt => if let Err(e) = result { tracing::error!("failed to listen for SIGINT: {e:?}"); },
+        () = terminate => {}
+    }
+}
+
+/// Stops the server accepting requests and waits, up to `grace`, for in-flight ones and any
+/// subscription work holding `subscription_lock` to finish, then flushes state to the store if
+/// it's the leader's to write and hands leadership on.
+pub async fn drain(control_state: &ControlState<'_>, stop: watch::Sender<bool>, server: JoinHandle<std::io::Result<()>>, grace: Duration) {
+    tracing::info!("shutting down, draining for up to {}s", grace.as_secs());
+    stop.send_replace(true);
+
+    let drained = tokio::time::timeout(grace, async {
+        match server.await {
+            Ok(Ok(())) => {},
+            Ok(Err(e)) => tracing::error!("server failed while draining: {e:?}"),
+            Err(e) => tracing::error!("server task failed while draining: {e:?}")
+        }
+
+        drop(control_state.subscription_lock.lock().await);
+    }).await;
+
+    if drained.is_err() {
+        tracing::warn!("grace period elapsed with work still in flight");
+    }
+
+    if control_state.leader.is_leader() {
+        match crate::store::flush(control_state).await {
+            Ok(()) => tracing::info!("flushed state to the store"),
+            Err(e) => tracing::error!("failed to flush state on shutdown: {e:?}")
+        }
+    }
+    control_state.leader.release().await;
+}
@@ -0,0 +1,348 @@
+use crate::Broadcaster;
+use crate::ControlState;
+use crate::audit::AuditEntry;
+use crate::conduit::DEFAULT;
+use crate::config::Config;
+use crate::keys::ApiKey;
+use crate::oauth::Outstanding;
+use crate::retry::RetryEntry;
+use crate::scheduler::ShardAssignment;
+use crate::subscription::SubscriptionKind;
+use crate::tokens::SavedToken;
+use crate::workers::SavedWorker;
+use twitch_api::twitch_oauth2::ClientId;
+use twitch_api::twitch_oauth2::ClientSecret;
+use twitch_api::types::UserId;
+use alloc::collections::BTreeMap;
+use alloc::collections::BTreeSet;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
+use core::time::Duration;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::time::Instant;
+
+mod memory;
+mod redis;
+mod sql;
+
+/// Everything the control plane learns at runtime that should survive a restart.
+#[derive(Default, Deserialize, Serialize)]
+pub struct Snapshot {
+    pub broadcasters: Vec<Broadcaster>,
+    pub assignments: Vec<StoredAssignment>,
+    pub workers: Vec<SavedWorker>,
+    pub retries: Vec<RetryEntry>,
+    pub keys: Vec<ApiKey>,
+    pub audit: Vec<AuditEntry>,
+    pub tokens: Vec<SavedToken>,
+    /// The conduits in use by name, so the next run picks them out of any others the client id has.
+    pub conduit_ids: BTreeMap<String, String>,
+    /// Onboarding invites by token, and authorizations sent to Twitch by CSRF state.
+    pub invites: BTreeMap<String, Outstanding>,
+    pub authorizations: BTreeMap<String, Outstanding>,
+    /// What the callback subscription's deliveries are signed with.
+    pub callback_secret: Option<String>
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct StoredAssignment {
+    /// One of `[conduits]`, or `None` for the default conduit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conduit: Option<String>,
+    pub shard: usize,
+    #[serde(flatten)]
+    pub assignment: ShardAssignment
+}
+
+/// The desired state, for backups and moving between environments. Unlike a `Snapshot` it has
+/// no subscription ids, leases or retries, which mean nothing anywhere else.
+#[derive(Deserialize, Serialize)]
+pub struct Export {
+    pub broadcasters: Vec<Broadcaster>,
+    pub profiles: BTreeMap<String, Vec<SubscriptionKind>>,
+    pub assignments: Vec<StoredAssignment>
+}
+
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = anyhow::Result<T>> + Send + 'a>>;
+
+/// Where snapshots are persisted. Saves replace the whole stored state, so only one replica
+/// should be writing to a shared backend at a time.
+pub trait StateStore: Send + Sync {
+    fn load(&self) -> StoreFuture<'_, Snapshot>;
+    fn save<'a>(&'a self, snapshot: &'a Snapshot) -> StoreFuture<'a, ()>;
+}
+
+/// Picks the backend from `store.url`'s scheme, with leases, assignments and retries moved to
+/// Redis when `store.redis_url` is set.
+pub async fn open(config: &Config) -> anyhow::Result<Box<dyn StateStore>> {
+    let store = connect(&config.store.url).await?;
+    if config.store.redis_url.is_empty() {
+        return Ok(store);
+    }
+
+    Ok(Box::new(redis::RedisStore::connect(&config.store.redis_url, config.workers.lease_ttl_secs, store).await?))
+}
+
+async fn connect(url: &str) -> anyhow::Result<Box<dyn StateStore>> {
+    if url.is_empty() || url.starts_with("memory:") {
+        Ok(Box::new(memory::MemoryStore))
+    } else if url.starts_with("sqlite:") {
+        Ok(Box::new(sql::SqlStore::connect(url, sql::Dialect::Sqlite).await?))
+    } else if url.starts_with("postgres:") || url.starts_with("postgresql:") {
+        Ok(Box::new(sql::SqlStore::connect(url, sql::Dialect::Postgres).await?))
+    } else {
+        Err(anyhow::anyhow!("unsupported store url {url}, expected memory:, sqlite: or postgres:"))
+    }
+}
+
+const TABLES: &[&str] = &["broadcasters", "assignments", "workers", "retries", "api_keys", "audit", "user_tokens", "meta", "webhook_shards", "routed_assignments", "worker_details", "oauth_pending"];
+
+/// A snapshot flattened into the column values the SQL backends store.
+#[derive(Default)]
+struct Rows {
+    broadcasters: Vec<(String, String)>,
+    assignments: Vec<(i64, String, Option<String>)>,
+    /// The secrets of the webhook shards among `assignments`.
+    webhook_secrets: Vec<(i64, String)>,
+    /// Assignments on the `[conduits]`, whole.
+    routed_assignments: Vec<(String, i64, String)>,
+    workers: Vec<String>,
+    /// The rest of each worker among `workers`, whole.
+    worker_details: Vec<(String, String)>,
+    retries: Vec<(String, String, String)>,
+    keys: Vec<(String, String)>,
+    audit: Vec<(i64, String)>,
+    tokens: Vec<(String, String)>,
+    /// Invites and authorizations by token or state, and which of the two they are.
+    oauth_pending: Vec<(String, String, String)>,
+    /// Single values by name, e.g. `conduit_id` (or `conduit_id:<name>` for the `[conduits]`) and
+    /// `callback_secret`.
+    meta: Vec<(String, String)>
+}
+
+impl Rows {
+    fn from_snapshot(snapshot: &Snapshot) -> anyhow::Result<Self> {
+        Ok(Self {
+            broadcasters: snapshot.broadcasters.iter().map(|broadcaster| Ok((broadcaster.login.clone(), serde_json::to_string(broadcaster)?))).collect::<anyhow::Result<_>>()?,
+            assignments: snapshot.assignments.iter().filter(|stored| stored.conduit.is_none()).map(|stored| Ok((i64::try_from(stored.shard)?, stored.assignment.session_id.clone(), stored.assignment.worker_id.clone()))).collect::<anyhow::Result<_>>()?,
+            webhook_secrets: snapshot.assignments.iter().filter(|stored| stored.conduit.is_none()).filter_map(|stored| stored.assignment.webhook_secret.as_ref().map(|secret| Ok((i64::try_from(stored.shard)?, secret.clone())))).collect::<anyhow::Result<_>>()?,
+            routed_assignments: snapshot.assignments.iter().filter_map(|stored| stored.conduit.as_ref().map(|conduit| Ok((conduit.clone(), i64::try_from(stored.shard)?, serde_json::to_string(&stored.assignment)?)))).collect::<anyhow::Result<_>>()?,
+            workers: snapshot.workers.iter().map(|worker| worker.id.clone()).collect(),
+            worker_details: snapshot.workers.iter().map(|worker| Ok((worker.id.clone(), serde_json::to_string(worker)?))).collect::<anyhow::Result<_>>()?,
+            retries: snapshot.retries.iter().map(|entry| Ok((entry.login.clone(), entry.kind.name().to_owned(), serde_json::to_string(entry)?))).collect::<anyhow::Result<_>>()?,
+            keys: snapshot.keys.iter().map(|key| Ok((key.id.clone(), serde_json::to_string(key)?))).collect::<anyhow::Result<_>>()?,
+            audit: snapshot.audit.iter().zip(0i64..).map(|(entry, seq)| Ok((seq, serde_json::to_string(entry)?))).collect::<anyhow::Result<_>>()?,
+            tokens: snapshot.tokens.iter().map(|token| Ok((token.user_id.to_string(), serde_json::to_string(token)?))).collect::<anyhow::Result<_>>()?,
+            oauth_pending: snapshot.invites.iter().map(|(token, invite)| ("invite", token, invite))
+                .chain(snapshot.authorizations.iter().map(|(state, pending)| ("authorization", state, pending)))
+                .map(|(kind, key, outstanding)| Ok((key.clone(), kind.to_owned(), serde_json::to_string(outstanding)?))).collect::<anyhow::Result<_>>()?,
+            meta: snapshot.conduit_ids.iter().map(|(name, id)| (if name == DEFAULT { "conduit_id".to_owned() } else { format!("conduit_id:{name}") }, id.clone()))
+                .chain(snapshot.callback_secret.iter().map(|secret| ("callback_secret".to_owned(), secret.clone()))).collect()
+        })
+    }
+
+    fn into_snapshot(self) -> anyhow::Result<Snapshot> {
+        let webhook_secrets: BTreeMap<i64, String> = self.webhook_secrets.into_iter().collect();
+        let worker_details: BTreeMap<String, String> = self.worker_details.into_iter().collect();
+        Ok(Snapshot {
+            broadcasters: self.broadcasters.iter().map(|(_, data)| serde_json::from_str(data)).collect::<Result<_, _>>()?,
+            assignments: self.assignments.into_iter().map(|(shard, session_id, worker_id)| Ok(StoredAssignment {
+                conduit: None,
+                shard: usize::try_from(shard)?,
+                assignment: ShardAssignment { session_id, worker_id, webhook_secret: webhook_secrets.get(&shard).cloned(), assigned_at: None }
+            })).chain(self.routed_assignments.iter().map(|(conduit, shard, data)| Ok(StoredAssignment {
+                conduit: Some(conduit.clone()),
+                shard: usize::try_from(*shard)?,
+                assignment: serde_json::from_str(data)?
+            }))).collect::<anyhow::Result<_>>()?,
+            workers: self.workers.into_iter().map(|id| worker_details.get(&id).map_or_else(|| Ok(SavedWorker::bare(id)), |data| serde_json::from_str(data))).collect::<Result<_, _>>()?,
+            retries: self.retries.iter().map(|(_, _, data)| serde_json::from_str(data)).collect::<Result<_, _>>()?,
+            keys: self.keys.iter().map(|(_, data)| serde_json::from_str(data)).collect::<Result<_, _>>()?,
+            audit: self.audit.iter().map(|(_, data)| serde_json::from_str(data)).collect::<Result<_, _>>()?,
+            tokens: self.tokens.iter().map(|(_, data)| serde_json::from_str(data)).collect::<Result<_, _>>()?,
+            conduit_ids: self.meta.iter().filter_map(|(key, value)| match key.strip_prefix("conduit_id") {
+                Some("") => Some((DEFAULT.to_owned(), value.clone())),
+                Some(name) => Some((name.strip_prefix(':')?.to_owned(), value.clone())),
+                None => None
+            }).collect(),
+            invites: self.oauth_pending.iter().filter(|(_, kind, _)| kind == "invite").map(|(token, _, data)| Ok((token.clone(), serde_json::from_str(data)?))).collect::<anyhow::Result<_>>()?,
+            authorizations: self.oauth_pending.iter().filter(|(_, kind, _)| kind == "authorization").map(|(state, _, data)| Ok((state.clone(), serde_json::from_str(data)?))).collect::<anyhow::Result<_>>()?,
+            callback_secret: self.meta.into_iter().find_map(|(key, value)| (key == "callback_secret").then_some(value))
+        })
+    }
+}
+
+pub async fn snapshot(control_state: &ControlState<'_>) -> Snapshot {
+    let broadcasters = control_state.broadcasters.read().await.values().cloned().collect();
+    let assignments = assignments(control_state).await;
+    let workers = control_state.workers.lock().await.iter().filter(|worker| worker.state != crate::workers::WorkerState::Expired).map(SavedWorker::from).collect();
+    let retries = control_state.retries.lock().await.iter().cloned().collect();
+    let keys = control_state.keys.all().await;
+    let audit = control_state.audit.all().await;
+    let tokens = control_state.tokens.all().await;
+
+    let mut conduit_ids = BTreeMap::new();
+    for lane in control_state.conduits.iter() {
+        conduit_ids.insert(lane.name.clone(), lane.conduit.read().await.id.to_string());
+    }
+
+    let (invites, authorizations) = control_state.oauth.saved().await;
+    let callback_secret = Some(control_state.callback.secret().await);
+
+    Snapshot { broadcasters, assignments, workers, retries, keys, audit, tokens, conduit_ids, invites, authorizations, callback_secret }
+}
+
+pub async fn export(control_state: &ControlState<'_>) -> Export {
+    let broadcasters = control_state.broadcasters.read().await.values().cloned().map(|broadcaster| Broadcaster { subscriptions: BTreeMap::new(), ..broadcaster }).collect();
+
+    Export {
+        broadcasters,
+        profiles: control_state.config.profiles.clone(),
+        assignments: assignments(control_state).await
+    }
+}
+
+async fn assignments(control_state: &ControlState<'_>) -> Vec<StoredAssignment> {
+    let mut assignments = Vec::new();
+    for lane in control_state.conduits.iter() {
+        let conduit = Some(lane.name.clone()).filter(|name| name != DEFAULT);
+        let scheduler = lane.scheduler.lock().await;
+        assignments.extend(scheduler.assignments().map(|(shard, assignment)| StoredAssignment { conduit: conduit.clone(), shard, assignment: assignment.clone() }));
+        drop(scheduler);
+    }
+    assignments
+}
+
+/// Puts `assignments` back on their conduits' schedulers, `what` naming where they came from.
+async fn restore_assignments(control_state: &ControlState<'_>, assignments: Vec<StoredAssignment>, what: &str) {
+    for stored in assignments {
+        let Some(lane) = control_state.conduits.get(stored.conduit.as_deref()) else {
+            tracing::warn!("dropped {what} assignment of shard {} on conduit {:?}, which isn't configured", stored.shard, stored.conduit);
+            continue;
+        };
+
+        if !lane.scheduler.lock().await.restore(stored.shard, stored.assignment) {
+            tracing::warn!("dropped {what} assignment of shard {}, the {} conduit has fewer shards", stored.shard, lane.name);
+        }
+    }
+}
+
+/// Replaces the broadcaster registry and shard assignments with `export`; the next
+/// reconciliation finds or creates the subscriptions and drops ones no longer wanted.
+/// Profiles come from config, so imported ones only label the types broadcasters carry.
+pub async fn import(control_state: &ControlState<'_>, export: Export) {
+    for name in export.profiles.keys().filter(|name| !control_state.config.profiles.contains_key(*name)) {
+        tracing::warn!("imported profile {name} isn't configured here, broadcasters keep their exported types");
+    }
+
+    let guard = control_state.subscription_lock.lock().await;
+    *control_state.broadcasters.write().await = export.broadcasters.into_iter().map(|broadcaster| (broadcaster.login.clone(), broadcaster)).collect();
+    drop(guard);
+
+    for lane in control_state.conduits.iter() {
+        lane.scheduler.lock().await.clear();
+    }
+    restore_assignments(control_state, export.assignments, "imported").await;
+}
+
+/// Replaces the runtime state with a stored snapshot, over what config seeds; the same whether
+/// starting up, taking over from another leader or following one. Broadcasters from config keep
+/// their configured types and profile, but get back their state and subscription ids.
+pub async fn restore(control_state: &ControlState<'_>, snapshot: Snapshot) {
+    let guard = control_state.subscription_lock.lock().await;
+    let mut broadcasters = control_state.broadcasters.write().await;
+    // whatever else this replica had was added at runtime, and is only kept if it was stored
+    broadcasters.retain(|login, _| crate::is_seeded(control_state, login));
+    for seeded in broadcasters.values_mut() {
+        seeded.state = crate::BroadcasterState::Active;
+        seeded.subscriptions.clear();
+    }
+    for stored in snapshot.broadcasters {
+        match broadcasters.get_mut(&stored.login) {
+            Some(seeded) => {
+                seeded.state = stored.state;
+                seeded.subscriptions = stored.subscriptions;
+            },
+            None => {
+                broadcasters.insert(stored.login.clone(), stored);
+            }
+        }
+    }
+    drop(broadcasters);
+    drop(guard);
+
+    for lane in control_state.conduits.iter() {
+        lane.scheduler.lock().await.clear();
+    }
+    restore_assignments(control_state, snapshot.assignments, "stored").await;
+
+    let mut workers = control_state.workers.lock().await;
+    workers.clear();
+    for saved in snapshot.workers {
+        workers.restore(saved);
+    }
+    drop(workers);
+
+    let mut retries = control_state.retries.lock().await;
+    retries.clear();
+    for entry in snapshot.retries {
+        retries.restore(entry);
+    }
+    drop(retries);
+
+    control_state.keys.restore(snapshot.keys).await;
+    control_state.audit.restore(snapshot.audit).await;
+    control_state.oauth.restore(snapshot.invites, snapshot.authorizations).await;
+    control_state.callback.restore(snapshot.callback_secret).await;
+
+    // the bot's token comes from config or bootstrap rather than the store
+    let stored: BTreeSet<&UserId> = snapshot.tokens.iter().map(|token| &token.user_id).collect();
+    control_state.tokens.retain(|user_id| stored.contains(user_id) || *user_id == control_state.my_user.id).await;
+
+    let client_id = ClientId::new(control_state.config.twitch.client_id.clone());
+    let client_secret = ClientSecret::new(control_state.secrets.client_secret().await);
+    control_state.tokens.restore(&control_state.client, &client_id, &client_secret, snapshot.tokens).await;
+}
+
+/// Restores what was last saved, e.g. by another leader.
+pub async fn reload(control_state: &ControlState<'_>) -> anyhow::Result<()> {
+    let snapshot = control_state.store.load().await?;
+    restore(control_state, snapshot).await;
+    Ok(())
+}
+
+pub async fn flush(control_state: &ControlState<'_>) -> anyhow::Result<()> {
+    control_state.store.save(&snapshot(control_state).await).await
+}
+
+/// Flushes the in-memory state to the store on an interval, skipping writes when nothing changed
+/// unless half a lease ttl has passed, so expiring keys (see the Redis store) get refreshed.
+pub async fn run(control_state: Arc<ControlState<'static>>) -> ! {
+    let mut interval = tokio::time::interval(Duration::from_secs(control_state.config.store.flush_interval_secs));
+    let refresh_every = Duration::from_secs(control_state.config.workers.lease_ttl_secs) / 2;
+    let mut last = String::new();
+    let mut saved_at = Instant::now();
+    loop {
+        interval.tick().await;
+        if !control_state.leader.is_leader() {
+            continue;
+        }
+
+        let snapshot = snapshot(&control_state).await;
+        let serialized = serde_json::to_string(&snapshot).unwrap_or_default();
+        if serialized == last && saved_at.elapsed() < refresh_every {
+            continue;
+        }
+
+        match control_state.store.save(&snapshot).await {
+            Ok(()) => {
+                last = serialized;
+                saved_at = Instant::now();
+            },
+            Err(e) => tracing::error!("failed to persist state: {e:?}")
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use super::Snapshot;
+use super::StateStore;
+use super::StoreFuture;
+use alloc::boxed::Box;
+
+/// Keeps nothing across restarts; the default when no store url is configured.
+#[derive(Debug)]
+pub struct MemoryStore;
+
+impl StateStore for MemoryStore {
+    fn load(&self) -> StoreFuture<'_, Snapshot> {
+        Box::pin(async { Ok(Snapshot::default()) })
+    }
+
+    fn save<'a>(&'a self, _snapshot: &'a Snapshot) -> StoreFuture<'a, ()> {
+        Box::pin(async { Ok(()) })
+    }
+}
@@ -0,0 +1,123 @@
+use super::Snapshot;
+use super::StateStore;
+use super::StoreFuture;
+use super::StoredAssignment;
+use crate::workers::SavedWorker;
+use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
+use redis::AsyncCommands as _;
+use redis::aio::ConnectionManager;
+use tokio::sync::Mutex;
+
+const PREFIX: &str = "control:";
+
+/// Worker leases, shard assignments and the retry queue, layered over another store that keeps
+/// the rest. Lease and assignment keys expire after the lease ttl unless refreshed by a save,
+/// so they lapse on their own if the control plane goes away.
+pub struct RedisStore {
+    inner: Box<dyn StateStore>,
+    connection: ConnectionManager,
+    ttl_secs: u64,
+    /// Keys written by the last save, so ones that dropped out can be deleted right away.
+    written: Mutex<BTreeSet<String>>
+}
+
+impl RedisStore {
+    pub async fn connect(url: &str, ttl_secs: u64, inner: Box<dyn StateStore>) -> anyhow::Result<Self> {
+        let connection = ConnectionManager::new(redis::Client::open(url)?).await?;
+
+        Ok(Self {
+            inner,
+            connection,
+            ttl_secs,
+            written: Mutex::new(BTreeSet::new())
+        })
+    }
+
+    async fn keys(&self, pattern: &str) -> anyhow::Result<Vec<String>> {
+        let mut connection = self.connection.clone();
+        let mut iter = connection.scan_match::<_, String>(format!("{PREFIX}{pattern}")).await?;
+
+        let mut keys = Vec::new();
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+
+        Ok(keys)
+    }
+
+    async fn values(&self, keys: &[String]) -> anyhow::Result<Vec<String>> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let values: Vec<Option<String>> = self.connection.clone().mget(keys).await?;
+        Ok(values.into_iter().flatten().collect())
+    }
+
+    async fn load(&self) -> anyhow::Result<Snapshot> {
+        let mut snapshot = self.inner.load().await?;
+
+        let worker_keys = self.keys("worker:*").await?;
+        let assignment_keys = self.keys("assignment:*").await?;
+        let retries: Vec<String> = self.connection.clone().hvals(format!("{PREFIX}retries")).await?;
+
+        // values from before workers were stored whole are bare ids
+        snapshot.workers = self.values(&worker_keys).await?.into_iter().map(|data| serde_json::from_str(&data).unwrap_or_else(|_err| SavedWorker::bare(data))).collect();
+        snapshot.assignments = self.values(&assignment_keys).await?.iter().map(|data| serde_json::from_str(data)).collect::<Result<_, _>>()?;
+        snapshot.retries = retries.iter().map(|data| serde_json::from_str(data)).collect::<Result<_, _>>()?;
+
+        self.written.lock().await.extend(worker_keys.into_iter().chain(assignment_keys));
+
+        Ok(snapshot)
+    }
+
+    async fn save(&self, snapshot: &Snapshot) -> anyhow::Result<()> {
+        self.inner.save(snapshot).await?;
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+
+        let mut written = BTreeSet::new();
+        for worker in &snapshot.workers {
+            let key = format!("{PREFIX}worker:{}", worker.id);
+            pipe.set_ex(&key, serde_json::to_string(worker)?, self.ttl_secs).ignore();
+            written.insert(key);
+        }
+
+        for stored in &snapshot.assignments {
+            let key = stored.conduit.as_ref().map_or_else(|| format!("{PREFIX}assignment:{}", stored.shard), |conduit| format!("{PREFIX}assignment:{conduit}:{}", stored.shard));
+            pipe.set_ex(&key, serde_json::to_string::<StoredAssignment>(stored)?, self.ttl_secs).ignore();
+            written.insert(key);
+        }
+
+        let mut previous = self.written.lock().await;
+        let stale: Vec<&String> = previous.difference(&written).collect();
+        if !stale.is_empty() {
+            pipe.del(stale).ignore();
+        }
+
+        let retries_key = format!("{PREFIX}retries");
+        pipe.del(&retries_key).ignore();
+        let retries = snapshot.retries.iter().map(|entry| Ok((format!("{}/{}", entry.login, entry.kind), serde_json::to_string(entry)?))).collect::<anyhow::Result<Vec<_>>>()?;
+        if !retries.is_empty() {
+            pipe.hset_multiple(&retries_key, &retries).ignore();
+        }
+
+        pipe.query_async::<()>(&mut self.connection.clone()).await?;
+        *previous = written;
+        drop(previous);
+
+        Ok(())
+    }
+}
+
+impl StateStore for RedisStore {
+    fn load(&self) -> StoreFuture<'_, Snapshot> {
+        Box::pin(self.load())
+    }
+
+    fn save<'a>(&'a self, snapshot: &'a Snapshot) -> StoreFuture<'a, ()> {
+        Box::pin(self.save(snapshot))
+    }
+}
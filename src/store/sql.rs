@@ -0,0 +1,187 @@
+use super::Rows;
+use super::Snapshot;
+use super::StateStore;
+use super::StoreFuture;
+use super::TABLES;
+use alloc::boxed::Box;
+use sqlx::AnyPool;
+use sqlx::any::AnyPoolOptions;
+
+const SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS broadcasters (login TEXT PRIMARY KEY, data TEXT NOT NULL)",
+    "CREATE TABLE IF NOT EXISTS assignments (shard {integer} PRIMARY KEY, session_id TEXT NOT NULL, worker_id TEXT)",
+    "CREATE TABLE IF NOT EXISTS workers (id TEXT PRIMARY KEY)",
+    "CREATE TABLE IF NOT EXISTS retries (login TEXT NOT NULL, kind TEXT NOT NULL, data TEXT NOT NULL, PRIMARY KEY (login, kind))",
+    "CREATE TABLE IF NOT EXISTS api_keys (id TEXT PRIMARY KEY, data TEXT NOT NULL)",
+    "CREATE TABLE IF NOT EXISTS audit (seq {integer} PRIMARY KEY, data TEXT NOT NULL)",
+    "CREATE TABLE IF NOT EXISTS user_tokens (user_id TEXT PRIMARY KEY, data TEXT NOT NULL)",
+    "CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT NOT NULL)",
+    "CREATE TABLE IF NOT EXISTS webhook_shards (shard {integer} PRIMARY KEY, secret TEXT NOT NULL)",
+    "CREATE TABLE IF NOT EXISTS routed_assignments (conduit TEXT NOT NULL, shard {integer} NOT NULL, data TEXT NOT NULL, PRIMARY KEY (conduit, shard))",
+    "CREATE TABLE IF NOT EXISTS worker_details (id TEXT PRIMARY KEY, data TEXT NOT NULL)",
+    "CREATE TABLE IF NOT EXISTS oauth_pending (state TEXT PRIMARY KEY, kind TEXT NOT NULL, data TEXT NOT NULL)"
+];
+
+/// What differs between the SQL backends; the schema and queries are otherwise shared.
+#[derive(Clone, Copy, Debug)]
+pub enum Dialect {
+    Sqlite,
+    Postgres
+}
+
+impl Dialect {
+    const fn integer(self) -> &'static str {
+        match self {
+            Self::Sqlite => "INTEGER",
+            Self::Postgres => "BIGINT"
+        }
+    }
+
+    fn placeholder(self, n: usize) -> String {
+        match self {
+            Self::Sqlite => "?".to_owned(),
+            Self::Postgres => format!("${n}")
+        }
+    }
+
+    /// SQLite allows one writer at a time anyway.
+    const fn max_connections(self) -> u32 {
+        match self {
+            Self::Sqlite => 1,
+            Self::Postgres => 4
+        }
+    }
+
+    /// The Any driver parses the url itself, so SQLite's database file is asked for there.
+    fn url(self, url: &str) -> String {
+        match self {
+            Self::Sqlite if !url.contains("mode=") => format!("{url}{}mode=rwc", if url.contains('?') { '&' } else { '?' }),
+            _ => url.to_owned()
+        }
+    }
+
+    fn insert(self, table: &str, columns: &[&str]) -> String {
+        let placeholders: Vec<String> = (1..=columns.len()).map(|n| self.placeholder(n)).collect();
+        format!("INSERT INTO {table} ({}) VALUES ({})", columns.join(", "), placeholders.join(", "))
+    }
+}
+
+#[derive(Debug)]
+pub struct SqlStore {
+    pool: AnyPool,
+    dialect: Dialect
+}
+
+impl SqlStore {
+    pub async fn connect(url: &str, dialect: Dialect) -> anyhow::Result<Self> {
+        sqlx::any::install_default_drivers();
+        let pool = AnyPoolOptions::new().max_connections(dialect.max_connections()).connect(&dialect.url(url)).await?;
+
+        for statement in SCHEMA {
+            sqlx::query(&statement.replace("{integer}", dialect.integer())).execute(&pool).await?;
+        }
+
+        Ok(Self { pool, dialect })
+    }
+
+    async fn load(&self) -> anyhow::Result<Snapshot> {
+        Rows {
+            broadcasters: sqlx::query_as("SELECT login, data FROM broadcasters").fetch_all(&self.pool).await?,
+            assignments: sqlx::query_as("SELECT shard, session_id, worker_id FROM assignments").fetch_all(&self.pool).await?,
+            webhook_secrets: sqlx::query_as("SELECT shard, secret FROM webhook_shards").fetch_all(&self.pool).await?,
+            routed_assignments: sqlx::query_as("SELECT conduit, shard, data FROM routed_assignments").fetch_all(&self.pool).await?,
+            workers: sqlx::query_scalar("SELECT id FROM workers").fetch_all(&self.pool).await?,
+            worker_details: sqlx::query_as("SELECT id, data FROM worker_details").fetch_all(&self.pool).await?,
+            retries: sqlx::query_as("SELECT login, kind, data FROM retries").fetch_all(&self.pool).await?,
+            keys: sqlx::query_as("SELECT id, data FROM api_keys").fetch_all(&self.pool).await?,
+            audit: sqlx::query_as("SELECT seq, data FROM audit ORDER BY seq").fetch_all(&self.pool).await?,
+            tokens: sqlx::query_as("SELECT user_id, data FROM user_tokens").fetch_all(&self.pool).await?,
+            oauth_pending: sqlx::query_as("SELECT state, kind, data FROM oauth_pending").fetch_all(&self.pool).await?,
+            meta: sqlx::query_as("SELECT key, value FROM meta").fetch_all(&self.pool).await?
+        }.into_snapshot()
+    }
+
+    async fn save(&self, snapshot: &Snapshot) -> anyhow::Result<()> {
+        let rows = Rows::from_snapshot(snapshot)?;
+        let dialect = self.dialect;
+        let mut tx = self.pool.begin().await?;
+
+        for table in TABLES {
+            sqlx::query(&format!("DELETE FROM {table}")).execute(&mut *tx).await?;
+        }
+
+        let insert = dialect.insert("broadcasters", &["login", "data"]);
+        for (login, data) in &rows.broadcasters {
+            sqlx::query(&insert).bind(login).bind(data).execute(&mut *tx).await?;
+        }
+
+        let insert = dialect.insert("assignments", &["shard", "session_id", "worker_id"]);
+        for (shard, session_id, worker_id) in &rows.assignments {
+            sqlx::query(&insert).bind(shard).bind(session_id).bind(worker_id).execute(&mut *tx).await?;
+        }
+
+        let insert = dialect.insert("webhook_shards", &["shard", "secret"]);
+        for (shard, secret) in &rows.webhook_secrets {
+            sqlx::query(&insert).bind(shard).bind(secret).execute(&mut *tx).await?;
+        }
+
+        let insert = dialect.insert("routed_assignments", &["conduit", "shard", "data"]);
+        for (conduit, shard, data) in &rows.routed_assignments {
+            sqlx::query(&insert).bind(conduit).bind(shard).bind(data).execute(&mut *tx).await?;
+        }
+
+        let insert = dialect.insert("workers", &["id"]);
+        for id in &rows.workers {
+            sqlx::query(&insert).bind(id).execute(&mut *tx).await?;
+        }
+
+        let insert = dialect.insert("worker_details", &["id", "data"]);
+        for (id, data) in &rows.worker_details {
+            sqlx::query(&insert).bind(id).bind(data).execute(&mut *tx).await?;
+        }
+
+        let insert = dialect.insert("retries", &["login", "kind", "data"]);
+        for (login, kind, data) in &rows.retries {
+            sqlx::query(&insert).bind(login).bind(kind).bind(data).execute(&mut *tx).await?;
+        }
+
+        let insert = dialect.insert("api_keys", &["id", "data"]);
+        for (id, data) in &rows.keys {
+            sqlx::query(&insert).bind(id).bind(data).execute(&mut *tx).await?;
+        }
+
+        let insert = dialect.insert("audit", &["seq", "data"]);
+        for (seq, data) in &rows.audit {
+            sqlx::query(&insert).bind(seq).bind(data).execute(&mut *tx).await?;
+        }
+
+        let insert = dialect.insert("user_tokens", &["user_id", "data"]);
+        for (user_id, data) in &rows.tokens {
+            sqlx::query(&insert).bind(user_id).bind(data).execute(&mut *tx).await?;
+        }
+
+        let insert = dialect.insert("oauth_pending", &["state", "kind", "data"]);
+        for (state, kind, data) in &rows.oauth_pending {
+            sqlx::query(&insert).bind(state).bind(kind).bind(data).execute(&mut *tx).await?;
+        }
+
+        let insert = dialect.insert("meta", &["key", "value"]);
+        for (key, value) in &rows.meta {
+            sqlx::query(&insert).bind(key).bind(value).execute(&mut *tx).await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+impl StateStore for SqlStore {
+    fn load(&self) -> StoreFuture<'_, Snapshot> {
+        Box::pin(self.load())
+    }
+
+    fn save<'a>(&'a self, snapshot: &'a Snapshot) -> StoreFuture<'a, ()> {
+        Box::pin(self.save(snapshot))
+    }
+}
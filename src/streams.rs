@@ -0,0 +1,138 @@
+use crate::BroadcasterState;
+use crate::ControlState;
+use crate::calendar;
+use crate::proxy;
+use crate::proxy::Rejection;
+use crate::subscription::SubscriptionKind;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use axum::extract::Path;
+use axum::extract::State;
+use axum::Json;
+use core::slice;
+use core::time::Duration;
+use futures_util::TryStreamExt as _;
+use serde::Serialize;
+use std::time::Instant;
+use std::time::SystemTime;
+use tokio::sync::RwLock;
+use twitch_api::helix::ClientRequestError;
+use twitch_api::helix::streams::Stream;
+use twitch_api::types::Collection;
+use twitch_api::types::Timestamp;
+use twitch_api::types::UserId;
+
+/// How long a status fetched on request is served from cache.
+const STREAM_TTL: Duration = Duration::from_secs(30);
+
+/// When the status was fetched, how long it's good for, and the stream if live.
+type Cached = (Instant, Duration, Option<Stream>);
+
+/// Stream statuses last fetched, by broadcaster.
+#[derive(Default)]
+pub struct Streams {
+    cached: RwLock<BTreeMap<UserId, Cached>>
+}
+
+#[derive(Serialize)]
+pub struct StreamStatus {
+    live: bool,
+    title: Option<String>,
+    category: Option<String>,
+    viewers: Option<usize>,
+    started_at: Option<Timestamp>,
+    uptime_secs: Option<u64>,
+    /// How long ago Twitch was asked.
+    age_secs: u64
+}
+
+impl From<&Cached> for StreamStatus {
+    fn from((fetched, _, stream): &Cached) -> Self {
+        Self {
+            live: stream.is_some(),
+            title: stream.as_ref().map(|stream| stream.title.clone()),
+            category: stream.as_ref().map(|stream| stream.game_name.clone()),
+            viewers: stream.as_ref().map(|stream| stream.viewer_count),
+            started_at: stream.as_ref().map(|stream| stream.started_at.clone()),
+            uptime_secs: stream.as_ref().and_then(|stream| uptime(&stream.started_at)),
+            age_secs: fetched.elapsed().as_secs()
+        }
+    }
+}
+
+/// Whether the broadcaster is live, and for how long with how many viewers.
+pub async fn status(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(broadcaster): Path<String>
+) -> Result<Json<StreamStatus>, Rejection> {
+    let broadcaster_id = proxy::user_id(&control_state, &broadcaster).await?;
+
+    if let Some(cached) = control_state.streams.cached.read().await.get(&broadcaster_id).filter(|(fetched, ttl, _)| fetched.elapsed() < *ttl) {
+        return Ok(Json(StreamStatus::from(cached)));
+    }
+
+    let mut live = fetch(&control_state, slice::from_ref(&broadcaster_id)).await.map_err(|e| proxy::rejection(&e))?;
+    let cached = (Instant::now(), STREAM_TTL, live.remove(&broadcaster_id));
+    let status = StreamStatus::from(&cached);
+    control_state.streams.cached.write().await.insert(broadcaster_id, cached);
+
+    Ok(Json(status))
+}
+
+/// The live streams among `ids`; anyone missing is offline.
+async fn fetch(control_state: &ControlState<'_>, ids: &[UserId]) -> Result<BTreeMap<UserId, Stream>, ClientRequestError<reqwest::Error>> {
+    let ids = Collection::from(ids);
+    let token = control_state.app_token.get().await;
+    let streams: Vec<Stream> = control_state.metrics.helix("get_streams", control_state.client.helix.get_streams_from_ids(&ids, &token).try_collect()).await?;
+
+    Ok(streams.into_iter().map(|stream| (stream.user_id.clone(), stream)).collect())
+}
+
+/// Keeps statuses warm for broadcasters we can't count on hearing stream.online and
+/// stream.offline for: those without both subscriptions in place, or with them on a conduit
+/// that's unhealthy.
+pub async fn run(control_state: Arc<ControlState<'static>>) -> ! {
+    let poll_interval = Duration::from_secs(control_state.config.streams.poll_interval_secs);
+    let mut interval = tokio::time::interval(poll_interval);
+    loop {
+        interval.tick().await;
+
+        let ids = degraded(&control_state).await;
+        if ids.is_empty() {
+            continue;
+        }
+
+        match fetch(&control_state, &ids).await {
+            Ok(mut live) => {
+                let fetched = Instant::now();
+                let mut cached = control_state.streams.cached.write().await;
+                for id in ids {
+                    let stream = live.remove(&id);
+                    // good until a round or two later, in case one fails
+                    cached.insert(id, (fetched, poll_interval.saturating_mul(2), stream));
+                }
+                drop(cached);
+            },
+            Err(e) => tracing::warn!("failed to poll stream statuses: {e:?}")
+        }
+    }
+}
+
+async fn degraded(control_state: &ControlState<'_>) -> Vec<UserId> {
+    let mut unhealthy = false;
+    for kind in [SubscriptionKind::StreamOnline, SubscriptionKind::StreamOffline] {
+        unhealthy |= !control_state.conduits.for_kind(&control_state.config, kind).health.read().await.healthy;
+    }
+
+    control_state.broadcasters.read().await.values()
+        .filter(|broadcaster| broadcaster.state == BroadcasterState::Active)
+        .filter(|broadcaster| unhealthy || !broadcaster.subscriptions.contains_key(&SubscriptionKind::StreamOnline) || !broadcaster.subscriptions.contains_key(&SubscriptionKind::StreamOffline))
+        .map(|broadcaster| broadcaster.user_id.clone())
+        .collect()
+}
+
+/// Seconds since `started_at`, which Twitch always gives in UTC.
+fn uptime(started_at: &Timestamp) -> Option<u64> {
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).ok()?.as_secs();
+    now.checked_sub(calendar::unix_secs(started_at.as_str())?)
+}
@@ -0,0 +1,530 @@
+use crate::ControlState;
+use crate::budget::CostBudget;
+use crate::events::ControlEvent;
+use axum::http::StatusCode;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::str::FromStr;
+use serde::Deserialize;
+use serde::Serialize;
+use twitch_api::eventsub::automod::AutomodMessageHoldV2;
+use twitch_api::eventsub::automod::AutomodMessageUpdateV2;
+use twitch_api::eventsub::channel::ChannelBanV1;
+use twitch_api::eventsub::channel::ChannelChatMessageV1;
+use twitch_api::eventsub::channel::ChannelChatNotificationV1;
+use twitch_api::eventsub::channel::ChannelCheerV1;
+use twitch_api::eventsub::channel::ChannelFollowV2;
+use twitch_api::eventsub::channel::ChannelHypeTrainBeginV1;
+use twitch_api::eventsub::channel::ChannelHypeTrainEndV1;
+use twitch_api::eventsub::channel::ChannelHypeTrainProgressV1;
+use twitch_api::eventsub::channel::ChannelPointsCustomRewardRedemptionAddV1;
+use twitch_api::eventsub::channel::ChannelPollBeginV1;
+use twitch_api::eventsub::channel::ChannelPollEndV1;
+use twitch_api::eventsub::channel::ChannelPollProgressV1;
+use twitch_api::eventsub::channel::ChannelPredictionBeginV1;
+use twitch_api::eventsub::channel::ChannelPredictionEndV1;
+use twitch_api::eventsub::channel::ChannelPredictionLockV1;
+use twitch_api::eventsub::channel::ChannelPredictionProgressV1;
+use twitch_api::eventsub::channel::ChannelRaidV1;
+use twitch_api::eventsub::channel::ChannelShoutoutCreateV1;
+use twitch_api::eventsub::channel::ChannelShoutoutReceiveV1;
+use twitch_api::eventsub::channel::ChannelSubscribeV1;
+use twitch_api::eventsub::channel::ChannelSubscriptionGiftV1;
+use twitch_api::eventsub::channel::ChannelSubscriptionMessageV1;
+use twitch_api::eventsub::channel::ChannelUnbanV1;
+use twitch_api::eventsub::channel::ChannelUpdateV2;
+use twitch_api::eventsub::stream::StreamOfflineV1;
+use twitch_api::eventsub::stream::StreamOnlineV1;
+use twitch_api::eventsub::user::UserAuthorizationRevokeV1;
+use twitch_api::eventsub::user::UserWhisperMessageV1;
+use twitch_api::eventsub::EventSubSubscription;
+use twitch_api::eventsub::EventSubscription;
+use twitch_api::eventsub::EventType;
+use twitch_api::eventsub::Transport;
+use twitch_api::eventsub::TransportResponse;
+use twitch_api::helix::eventsub::EventSubSubscriptions;
+use twitch_api::twitch_oauth2::Scope;
+use twitch_api::twitch_oauth2::scopes::validator::Sized;
+use twitch_api::twitch_oauth2::Validator;
+use twitch_api::types::ConduitId;
+use twitch_api::types::EventSubId;
+use twitch_api::types::UserId;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "&'static str")]
+pub enum SubscriptionKind {
+    ChannelChatMessage,
+    StreamOnline,
+    StreamOffline,
+    ChannelPointsRedemptionAdd,
+    ChannelFollow,
+    ChannelSubscribe,
+    ChannelSubscriptionGift,
+    ChannelSubscriptionMessage,
+    ChannelRaidTo,
+    ChannelRaidFrom,
+    ChannelCheer,
+    ChannelBan,
+    ChannelUnban,
+    ChannelChatNotification,
+    AutomodMessageHold,
+    AutomodMessageUpdate,
+    ChannelPollBegin,
+    ChannelPollProgress,
+    ChannelPollEnd,
+    ChannelPredictionBegin,
+    ChannelPredictionProgress,
+    ChannelPredictionLock,
+    ChannelPredictionEnd,
+    ChannelHypeTrainBegin,
+    ChannelHypeTrainProgress,
+    ChannelHypeTrainEnd,
+    ChannelShoutoutCreate,
+    ChannelShoutoutReceive,
+    UserWhisperMessage,
+    ChannelUpdate
+}
+
+impl SubscriptionKind {
+    pub const ALL: &[Self] = &[
+        Self::ChannelChatMessage,
+        Self::StreamOnline,
+        Self::StreamOffline,
+        Self::ChannelPointsRedemptionAdd,
+        Self::ChannelFollow,
+        Self::ChannelSubscribe,
+        Self::ChannelSubscriptionGift,
+        Self::ChannelSubscriptionMessage,
+        Self::ChannelRaidTo,
+        Self::ChannelRaidFrom,
+        Self::ChannelCheer,
+        Self::ChannelBan,
+        Self::ChannelUnban,
+        Self::ChannelChatNotification,
+        Self::AutomodMessageHold,
+        Self::AutomodMessageUpdate,
+        Self::ChannelPollBegin,
+        Self::ChannelPollProgress,
+        Self::ChannelPollEnd,
+        Self::ChannelPredictionBegin,
+        Self::ChannelPredictionProgress,
+        Self::ChannelPredictionLock,
+        Self::ChannelPredictionEnd,
+        Self::ChannelHypeTrainBegin,
+        Self::ChannelHypeTrainProgress,
+        Self::ChannelHypeTrainEnd,
+        Self::ChannelShoutoutCreate,
+        Self::ChannelShoutoutReceive,
+        Self::UserWhisperMessage,
+        Self::ChannelUpdate
+    ];
+
+    /// Usually the event type, except where one type has several condition shapes (e.g. raids).
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::ChannelChatMessage         => "channel.chat.message",
+            Self::StreamOnline               => "stream.online",
+            Self::StreamOffline              => "stream.offline",
+            Self::ChannelPointsRedemptionAdd => "channel.channel_points_custom_reward_redemption.add",
+            Self::ChannelFollow              => "channel.follow",
+            Self::ChannelSubscribe           => "channel.subscribe",
+            Self::ChannelSubscriptionGift    => "channel.subscription.gift",
+            Self::ChannelSubscriptionMessage => "channel.subscription.message",
+            Self::ChannelRaidTo              => "channel.raid.to",
+            Self::ChannelRaidFrom            => "channel.raid.from",
+            Self::ChannelCheer               => "channel.cheer",
+            Self::ChannelBan                 => "channel.ban",
+            Self::ChannelUnban               => "channel.unban",
+            Self::ChannelChatNotification    => "channel.chat.notification",
+            Self::AutomodMessageHold         => "automod.message.hold",
+            Self::AutomodMessageUpdate       => "automod.message.update",
+            Self::ChannelPollBegin           => "channel.poll.begin",
+            Self::ChannelPollProgress        => "channel.poll.progress",
+            Self::ChannelPollEnd             => "channel.poll.end",
+            Self::ChannelPredictionBegin     => "channel.prediction.begin",
+            Self::ChannelPredictionProgress  => "channel.prediction.progress",
+            Self::ChannelPredictionLock      => "channel.prediction.lock",
+            Self::ChannelPredictionEnd       => "channel.prediction.end",
+            Self::ChannelHypeTrainBegin      => "channel.hype_train.begin",
+            Self::ChannelHypeTrainProgress   => "channel.hype_train.progress",
+            Self::ChannelHypeTrainEnd        => "channel.hype_train.end",
+            Self::ChannelShoutoutCreate      => "channel.shoutout.create",
+            Self::ChannelShoutoutReceive     => "channel.shoutout.receive",
+            Self::UserWhisperMessage         => "user.whisper.message",
+            Self::ChannelUpdate              => "channel.update"
+        }
+    }
+
+    /// Whose authorization the subscription rides on, i.e. whose token must carry its scopes.
+    pub const fn authorizer(self) -> Option<Authorizer> {
+        match self {
+            Self::ChannelChatMessage         => None,
+            Self::StreamOnline               => None,
+            Self::StreamOffline              => None,
+            Self::ChannelPointsRedemptionAdd => Some(Authorizer::Broadcaster),
+            Self::ChannelFollow              => Some(Authorizer::Bot),
+            Self::ChannelSubscribe           => Some(Authorizer::Broadcaster),
+            Self::ChannelSubscriptionGift    => Some(Authorizer::Broadcaster),
+            Self::ChannelSubscriptionMessage => Some(Authorizer::Broadcaster),
+            Self::ChannelRaidTo              => None,
+            Self::ChannelRaidFrom            => None,
+            Self::ChannelCheer               => Some(Authorizer::Broadcaster),
+            Self::ChannelBan                 => Some(Authorizer::Broadcaster),
+            Self::ChannelUnban               => Some(Authorizer::Broadcaster),
+            Self::ChannelChatNotification    => None,
+            Self::AutomodMessageHold         => Some(Authorizer::Bot),
+            Self::AutomodMessageUpdate       => Some(Authorizer::Bot),
+            Self::ChannelPollBegin           => Some(Authorizer::Broadcaster),
+            Self::ChannelPollProgress        => Some(Authorizer::Broadcaster),
+            Self::ChannelPollEnd             => Some(Authorizer::Broadcaster),
+            Self::ChannelPredictionBegin     => Some(Authorizer::Broadcaster),
+            Self::ChannelPredictionProgress  => Some(Authorizer::Broadcaster),
+            Self::ChannelPredictionLock      => Some(Authorizer::Broadcaster),
+            Self::ChannelPredictionEnd       => Some(Authorizer::Broadcaster),
+            Self::ChannelHypeTrainBegin      => Some(Authorizer::Broadcaster),
+            Self::ChannelHypeTrainProgress   => Some(Authorizer::Broadcaster),
+            Self::ChannelHypeTrainEnd        => Some(Authorizer::Broadcaster),
+            Self::ChannelShoutoutCreate      => Some(Authorizer::Bot),
+            Self::ChannelShoutoutReceive     => Some(Authorizer::Bot),
+            Self::UserWhisperMessage         => Some(Authorizer::Broadcaster),
+            Self::ChannelUpdate              => None
+        }
+    }
+}
+
+impl fmt::Display for SubscriptionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SubscriptionKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.iter().copied().find(|kind| kind.name() == s).ok_or_else(|| anyhow::anyhow!("unsupported subscription type {s}"))
+    }
+}
+
+impl TryFrom<String> for SubscriptionKind {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<SubscriptionKind> for &'static str {
+    fn from(kind: SubscriptionKind) -> Self {
+        kind.name()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Authorizer {
+    Broadcaster,
+    Bot
+}
+
+/// Who a subscription is for; each kind picks the ids its condition needs.
+#[derive(Clone, Copy, Debug)]
+pub struct Target<'a> {
+    pub broadcaster_id: &'a UserId,
+    pub bot_id: &'a UserId
+}
+
+/// What a subscription looks like on Twitch's side, for matching against get_eventsub_subscriptions.
+#[derive(Debug)]
+pub struct Description {
+    pub event_type: EventType,
+    pub version: &'static str,
+    pub condition: serde_json::Value
+}
+
+impl Description {
+    pub fn of<E: EventSubscription>(subscription: &E) -> Self {
+        Self {
+            event_type: E::EVENT_TYPE,
+            version: E::VERSION,
+            condition: subscription.condition().unwrap_or_default()
+        }
+    }
+
+    /// Twitch echoes unused condition fields back as empty strings, so treat those as absent.
+    pub fn matches(&self, event_type: &EventType, version: &str, condition: &serde_json::Value) -> bool {
+        let empty = serde_json::Map::new();
+        let desired = self.condition.as_object().unwrap_or(&empty);
+        let actual = condition.as_object().unwrap_or(&empty);
+        let is_blank = |value: Option<&serde_json::Value>| value.is_none_or(|value| value.is_null() || value.as_str() == Some(""));
+
+        self.event_type == *event_type
+            && self.version == version
+            && desired.keys().chain(actual.keys()).all(|key| match (desired.get(key), actual.get(key)) {
+                (Some(a), Some(b)) if a == b => true,
+                (a, b) => is_blank(a) && is_blank(b)
+            })
+    }
+}
+
+trait Visitor {
+    type Output;
+
+    fn visit<E: EventSubscription + Send + Sync + fmt::Debug + 'static>(self, subscription: E) -> Self::Output;
+}
+
+fn visit<V: Visitor>(kind: SubscriptionKind, target: Target<'_>, visitor: V) -> V::Output {
+    match kind {
+        SubscriptionKind::ChannelChatMessage         => visitor.visit(ChannelChatMessageV1::new(target.broadcaster_id.clone(), target.bot_id.clone())),
+        SubscriptionKind::StreamOnline               => visitor.visit(StreamOnlineV1::broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::StreamOffline              => visitor.visit(StreamOfflineV1::broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelPointsRedemptionAdd => visitor.visit(ChannelPointsCustomRewardRedemptionAddV1::broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelFollow              => visitor.visit(ChannelFollowV2::new(target.broadcaster_id.clone(), target.bot_id.clone())),
+        SubscriptionKind::ChannelSubscribe           => visitor.visit(ChannelSubscribeV1::broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelSubscriptionGift    => visitor.visit(ChannelSubscriptionGiftV1::broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelSubscriptionMessage => visitor.visit(ChannelSubscriptionMessageV1::broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelRaidTo              => visitor.visit(ChannelRaidV1::to_broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelRaidFrom            => visitor.visit(ChannelRaidV1::from_broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelCheer               => visitor.visit(ChannelCheerV1::broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelBan                 => visitor.visit(ChannelBanV1::broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelUnban               => visitor.visit(ChannelUnbanV1::broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelChatNotification    => visitor.visit(ChannelChatNotificationV1::new(target.broadcaster_id.clone(), target.bot_id.clone())),
+        SubscriptionKind::AutomodMessageHold         => visitor.visit(AutomodMessageHoldV2::new(target.broadcaster_id.clone(), target.bot_id.clone())),
+        SubscriptionKind::AutomodMessageUpdate       => visitor.visit(AutomodMessageUpdateV2::new(target.broadcaster_id.clone(), target.bot_id.clone())),
+        SubscriptionKind::ChannelPollBegin           => visitor.visit(ChannelPollBeginV1::broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelPollProgress        => visitor.visit(ChannelPollProgressV1::broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelPollEnd             => visitor.visit(ChannelPollEndV1::broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelPredictionBegin     => visitor.visit(ChannelPredictionBeginV1::broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelPredictionProgress  => visitor.visit(ChannelPredictionProgressV1::broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelPredictionLock      => visitor.visit(ChannelPredictionLockV1::broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelPredictionEnd       => visitor.visit(ChannelPredictionEndV1::broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelHypeTrainBegin      => visitor.visit(ChannelHypeTrainBeginV1::broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelHypeTrainProgress   => visitor.visit(ChannelHypeTrainProgressV1::broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelHypeTrainEnd        => visitor.visit(ChannelHypeTrainEndV1::broadcaster_user_id(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelShoutoutCreate      => visitor.visit(ChannelShoutoutCreateV1::new(target.broadcaster_id.clone(), target.bot_id.clone())),
+        SubscriptionKind::ChannelShoutoutReceive     => visitor.visit(ChannelShoutoutReceiveV1::new(target.broadcaster_id.clone(), target.bot_id.clone())),
+        SubscriptionKind::UserWhisperMessage         => visitor.visit(UserWhisperMessageV1::new(target.broadcaster_id.clone())),
+        SubscriptionKind::ChannelUpdate              => visitor.visit(ChannelUpdateV2::broadcaster_user_id(target.broadcaster_id.clone()))
+    }
+}
+
+struct Describe;
+
+impl Visitor for Describe {
+    type Output = Description;
+
+    fn visit<E: EventSubscription + Send + Sync + fmt::Debug + 'static>(self, subscription: E) -> Self::Output {
+        Description::of(&subscription)
+    }
+}
+
+struct Scopes;
+
+impl Visitor for Scopes {
+    type Output = Validator;
+
+    fn visit<E: EventSubscription + Send + Sync + fmt::Debug + 'static>(self, _: E) -> Self::Output {
+        E::SCOPE
+    }
+}
+
+/// The scopes to ask for so a token satisfies `kind`, taking the first option where Twitch
+/// accepts any of several.
+pub fn scopes(kind: SubscriptionKind) -> Vec<Scope> {
+    fn collect(validator: &Validator, scopes: &mut Vec<Scope>) {
+        match validator {
+            Validator::Scope(scope) => scopes.push(scope.clone()),
+            Validator::All(Sized(all)) => all.iter().for_each(|validator| collect(validator, scopes)),
+            Validator::Any(Sized(any)) => any.first().into_iter().for_each(|validator| collect(validator, scopes)),
+            // nothing to ask for to satisfy a Not
+            _ => {}
+        }
+    }
+
+    let mut scopes = Vec::new();
+    collect(&validator(kind), &mut scopes);
+    scopes
+}
+
+fn validator(kind: SubscriptionKind) -> Validator {
+    // only the type matters here, not who it's for
+    let placeholder = UserId::new(String::new());
+    visit(kind, Target { broadcaster_id: &placeholder, bot_id: &placeholder }, Scopes)
+}
+
+/// Checks up front that the tokens we hold carry the scopes of every type we're about to
+/// subscribe to, failing with everything that's missing grouped by type. Users without a token
+/// can't be checked, their authorization may have been granted elsewhere.
+pub async fn verify_scopes(control_state: &ControlState<'_>) -> anyhow::Result<()> {
+    let wanted: Vec<(String, UserId, SubscriptionKind)> = control_state.broadcasters.read().await.values()
+        .filter(|broadcaster| broadcaster.state == crate::BroadcasterState::Active)
+        .flat_map(|broadcaster| broadcaster.subscription_types.iter().map(|kind| (broadcaster.login.clone(), broadcaster.user_id.clone(), *kind)))
+        .collect();
+
+    let mut missing: BTreeMap<SubscriptionKind, Vec<String>> = BTreeMap::new();
+    for (login, broadcaster_id, kind) in wanted {
+        let (authorizer, who) = match kind.authorizer() {
+            Some(Authorizer::Broadcaster) => (&broadcaster_id, login),
+            Some(Authorizer::Bot) => (&control_state.my_user.id, format!("bot {} for {login}", control_state.my_user.login)),
+            None => continue
+        };
+
+        match control_state.tokens.missing_scopes(authorizer, &validator(kind)).await {
+            Some(Some(scopes)) => missing.entry(kind).or_default().push(format!("{who} lacks {scopes}")),
+            Some(None) => {},
+            None => tracing::warn!("no token for {who}, can't verify {kind} scopes {} up front", validator(kind))
+        }
+    }
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let report: Vec<String> = missing.iter().map(|(kind, problems)| format!("{kind}: {}", problems.join(", "))).collect();
+    Err(anyhow::anyhow!("tokens are missing scopes for configured subscription types:\n  {}", report.join("\n  ")))
+}
+
+struct Create<'a, 'b> {
+    control_state: &'a ControlState<'b>,
+    authorizer: Option<&'a UserId>,
+    conduit_id: ConduitId
+}
+
+impl<'a> Visitor for Create<'a, '_> {
+    type Output = Pin<Box<dyn Future<Output = anyhow::Result<EventSubId>> + Send + 'a>>;
+
+    fn visit<E: EventSubscription + Send + Sync + fmt::Debug + 'static>(self, subscription: E) -> Self::Output {
+        Box::pin(async move {
+            if let Some(user_id) = self.authorizer {
+                check_scopes::<E>(self.control_state, user_id).await?;
+            }
+
+            create_one(self.control_state, subscription, self.conduit_id).await
+        })
+    }
+}
+
+/// Conduit subscriptions are made with the app token, but Twitch still wants the authorizing
+/// user to have granted the type's scopes; catch that here rather than with an opaque 403.
+async fn check_scopes<E: EventSubscription>(control_state: &ControlState<'_>, user_id: &UserId) -> anyhow::Result<()> {
+    match control_state.tokens.missing_scopes(user_id, &E::SCOPE).await {
+        Some(None) => Ok(()),
+        Some(Some(missing)) => Err(anyhow::anyhow!("token for user {user_id} is missing scopes {missing} required by {}", E::EVENT_TYPE)),
+        None => {
+            tracing::warn!("no token for user {user_id}, can't verify {} scopes {} before subscribing", E::EVENT_TYPE, E::SCOPE);
+            Ok(())
+        }
+    }
+}
+
+pub fn describe(kind: SubscriptionKind, target: Target<'_>) -> Description {
+    visit(kind, target, Describe)
+}
+
+/// Which broadcaster, if any, a subscription's condition is about.
+pub fn broadcaster_of(condition: &serde_json::Value) -> Option<&str> {
+    ["broadcaster_user_id", "to_broadcaster_user_id", "from_broadcaster_user_id", "user_id"]
+        .into_iter()
+        .find_map(|key| condition.get(key)?.as_str().filter(|id| !id.is_empty()))
+}
+
+/// Every subscription for our client id, with the cost totals Twitch reports alongside them.
+#[derive(Debug)]
+pub struct Listing {
+    pub total: usize,
+    pub total_cost: usize,
+    pub max_total_cost: usize,
+    pub subscriptions: Vec<EventSubSubscription>
+}
+
+pub async fn list(control_state: &ControlState<'_>) -> anyhow::Result<Listing> {
+    let pages: Vec<EventSubSubscriptions> = control_state.metrics.helix("get_eventsub_subscriptions", control_state.twitch.subscriptions(None, &control_state.app_token.get().await)).await?;
+
+    let (total, total_cost, max_total_cost) = pages.first().map_or((0, 0, 0), |page| (page.total, page.total_cost, page.max_total_cost));
+    *control_state.budget.write().await = CostBudget { total, total_cost, max_total_cost };
+
+    Ok(Listing {
+        total,
+        total_cost,
+        max_total_cost,
+        subscriptions: pages.into_iter().flat_map(|page| page.subscriptions).collect()
+    })
+}
+
+/// The control plane's own user.authorization.revoke subscription, not tied to any broadcaster.
+fn revocation(control_state: &ControlState<'_>) -> UserAuthorizationRevokeV1 {
+    UserAuthorizationRevokeV1::new(control_state.config.twitch.client_id.clone())
+}
+
+pub fn describe_revocation(control_state: &ControlState<'_>) -> Description {
+    Describe.visit(revocation(control_state))
+}
+
+pub async fn create_revocation(control_state: &ControlState<'_>) -> anyhow::Result<EventSubId> {
+    let conduit_id = control_state.conduits.default.conduit.read().await.id.clone();
+    create_one(control_state, revocation(control_state), conduit_id).await
+}
+
+pub async fn create(control_state: &ControlState<'_>, kind: SubscriptionKind, broadcaster_id: &UserId) -> anyhow::Result<EventSubId> {
+    let target = Target {
+        broadcaster_id,
+        bot_id: &control_state.my_user.id
+    };
+
+    let authorizer = kind.authorizer().map(|authorizer| match authorizer {
+        Authorizer::Broadcaster => target.broadcaster_id,
+        Authorizer::Bot => target.bot_id
+    });
+
+    let conduit_id = control_state.conduits.for_kind(&control_state.config, kind).conduit.read().await.id.clone();
+    let result = visit(kind, target, Create { control_state, authorizer, conduit_id }).await;
+    control_state.metrics.inc("control_subscription_creations_total", &[("type", kind.name()), ("outcome", if result.is_ok() { "ok" } else { "error" })]);
+    control_state.events.publish(match &result {
+        Ok(id) => ControlEvent::SubscriptionCreated { kind: kind.name(), broadcaster_id: broadcaster_id.to_string(), id: id.to_string() },
+        Err(e) => ControlEvent::SubscriptionFailed { kind: kind.name(), broadcaster_id: broadcaster_id.to_string(), error: e.to_string() }
+    });
+    result
+}
+
+/// The id of an existing subscription on `conduit_id` matching `description`, in any status.
+async fn find_existing(control_state: &ControlState<'_>, description: &Description, conduit_id: &ConduitId) -> anyhow::Result<Option<EventSubId>> {
+    let pages: Vec<EventSubSubscriptions> = control_state.metrics.helix("get_eventsub_subscriptions", control_state.twitch.subscriptions(Some(description.event_type), &control_state.app_token.get().await)).await?;
+
+    Ok(pages.into_iter().flat_map(|page| page.subscriptions).find(|subscription| {
+        matches!(&subscription.transport, TransportResponse::Conduit(transport) if transport.conduit_id == conduit_id.as_str())
+            && description.matches(&subscription.type_, &subscription.version, &subscription.condition)
+    }).map(|subscription| subscription.id))
+}
+
+async fn create_one<E: EventSubscription + Send + fmt::Debug>(control_state: &ControlState<'_>, subscription: E, conduit_id: ConduitId) -> anyhow::Result<EventSubId> {
+    control_state.budget.read().await.check()?;
+
+    let description = Description::of(&subscription);
+    let result = control_state.metrics.helix("create_eventsub_subscription", control_state.twitch.create_subscription(
+        &description,
+        Transport::conduit(conduit_id.clone()),
+        &control_state.app_token.get().await
+    )).await;
+
+    let event_info = match result {
+        Ok(event_info) => event_info,
+        Err(e) if crate::helix::status(&e) == Some(StatusCode::CONFLICT) => {
+            let id = find_existing(control_state, &description, &conduit_id).await?.ok_or_else(|| anyhow::Error::new(e).context("Twitch reported a conflict, but no matching subscription is on our conduit"))?;
+            tracing::info!("{} subscription already exists as {id}", description.event_type.to_str());
+            return Ok(id);
+        },
+        Err(e) => return Err(e.into())
+    };
+
+    tracing::info!("{event_info:?}");
+
+    *control_state.budget.write().await = CostBudget {
+        total: event_info.total,
+        total_cost: event_info.total_cost,
+        max_total_cost: event_info.max_total_cost
+    };
+
+    Ok(event_info.id)
+}
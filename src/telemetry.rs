@@ -0,0 +1,42 @@
+use anyhow::Context as _;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt as _;
+use tracing_subscriber::util::SubscriberInitExt as _;
+
+/// Logs to stderr filtered by `RUST_LOG`, and exports spans over OTLP/HTTP when
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` (or `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`) is set. The rest of the
+/// exporter (headers, timeout, sampling, `OTEL_SERVICE_NAME`) follows the standard `OTEL_*` vars.
+pub fn init() -> anyhow::Result<Option<SdkTracerProvider>> {
+    let provider = if std::env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT").is_some() || std::env::var_os("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT").is_some() {
+        let exporter = SpanExporter::builder().with_http().build().context("failed to build the OTLP exporter")?;
+
+        let mut resource = Resource::builder();
+        if std::env::var_os("OTEL_SERVICE_NAME").is_none() {
+            resource = resource.with_service_name(env!("CARGO_PKG_NAME"));
+        }
+
+        Some(SdkTracerProvider::builder().with_batch_exporter(exporter).with_resource(resource.build()).build())
+    } else {
+        None
+    };
+
+    tracing_subscriber::registry()
+        .with(EnvFilter::from_default_env())
+        .with(tracing_subscriber::fmt::layer())
+        .with(provider.as_ref().map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))))
+        .try_init()
+        .context("failed to install the tracing subscriber")?;
+
+    Ok(provider)
+}
+
+/// Flushes any spans still batched for export.
+pub fn shutdown(provider: Option<SdkTracerProvider>) {
+    if let Some(provider) = provider && let Err(e) = provider.shutdown() {
+        tracing::error!("failed to flush spans: {e:?}");
+    }
+}
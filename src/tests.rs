@@ -0,0 +1,262 @@
+use crate::ControlState;
+use crate::Peer;
+use crate::bootstrap;
+use crate::config::Config;
+use crate::helix;
+use crate::helix::BaseUrls;
+use crate::helix::breaker::CircuitBreaker;
+use crate::helix::bucket::RateLimits;
+use crate::helix::retry::RetryClient;
+use crate::leader::Leadership;
+use crate::metrics::Metrics;
+use crate::mock;
+use crate::mock::Twitch;
+use crate::reconcile;
+use crate::store;
+use crate::subscription::SubscriptionKind;
+use crate::twitch::TwitchControl;
+use crate::workers::SavedWorker;
+use alloc::sync::Arc;
+use axum::http::Method;
+use axum::http::StatusCode;
+use core::time::Duration;
+use serde_json::Value;
+use serde_json::json;
+use twitch_api::TwitchClient;
+
+const TOKEN: &str = "test-control-token";
+const BOT_LOGIN: &str = "mockbot";
+
+/// The control plane bootstrapped against the mock Twitch and serving on a local port.
+struct Harness {
+    url: String,
+    http: reqwest::Client,
+    twitch: Arc<Twitch>,
+    control_state: Arc<ControlState<'static>>
+}
+
+impl Harness {
+    async fn start() -> Self {
+        Self::start_with_store("").await
+    }
+
+    async fn start_with_store(store_url: &str) -> Self {
+        let (twitch_addr, twitch) = mock::serve().await.expect("mock Twitch should start");
+
+        let mut config = Config::default();
+        config.store.url = store_url.to_owned();
+        config.control.token = TOKEN.to_owned();
+        config.twitch.client_id = "mock-client-id".to_owned();
+        config.twitch.client_secret = "mock-client-secret".to_owned();
+        config.twitch.user_login = BOT_LOGIN.to_owned();
+        config.twitch.helix_url = format!("http://{twitch_addr}/helix");
+        config.twitch.auth_url = format!("http://{twitch_addr}/oauth2");
+        config.subscriptions = vec![SubscriptionKind::StreamOnline, SubscriptionKind::StreamOffline];
+
+        let breaker = Arc::new(CircuitBreaker::default());
+        let http_client = helix::http_client(&config.http).expect("HTTP client should build");
+        let client = TwitchClient::with_client(RetryClient::new(http_client, Arc::clone(&breaker), Arc::new(RateLimits::default()), BaseUrls::from(&config.twitch)));
+        let control: Box<dyn TwitchControl> = Box::new(client.helix.clone());
+        let metrics = Metrics::default();
+
+        let store = store::open(&config).await.expect("store should open");
+        let snapshot = store.load().await.expect("store should load");
+        let bootstrap = tokio::time::timeout(Duration::from_secs(10), bootstrap::run(&config, &client, &*control, &metrics, true, &snapshot.conduit_ids)).await.expect("bootstrap against the mock should succeed");
+        let leader = Arc::new(Leadership::open(&config.leader).await.expect("an uncontested leadership should open"));
+        let control_state = crate::control_state(config, client, control, breaker, metrics, leader, bootstrap, store, snapshot).await.expect("control state should build");
+
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0)).await.expect("control listener should bind");
+        let url = format!("http://{}", listener.local_addr().expect("control listener should have an address"));
+        tokio::spawn(axum::serve(listener, crate::http::router(Arc::clone(&control_state)).into_make_service_with_connect_info::<Peer>()).into_future());
+
+        Self { url, http: reqwest::Client::new(), twitch, control_state }
+    }
+
+    async fn send(&self, method: Method, path: &str, body: Option<Value>) -> (StatusCode, Value) {
+        self.send_as(Some(TOKEN), method, path, body).await
+    }
+
+    async fn send_as(&self, token: Option<&str>, method: Method, path: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let mut request = self.http.request(method, format!("{}{path}", self.url));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            request = request.header("content-type", "application/json").body(body.to_string());
+        }
+
+        let response = request.send().await.expect("control plane should answer");
+        let status = response.status();
+        let bytes = response.bytes().await.expect("control plane should send a body");
+        (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+    }
+
+    async fn conduit_id(&self) -> String {
+        self.control_state.conduits.default.conduit.read().await.id.to_string()
+    }
+}
+
+/// What's at `pointer` in `value`, null if nothing is.
+fn at(value: &Value, pointer: &str) -> Value {
+    value.pointer(pointer).cloned().unwrap_or(Value::Null)
+}
+
+#[tokio::test]
+async fn bootstrap_creates_a_conduit() {
+    let harness = Harness::start().await;
+
+    let conduits = harness.twitch.conduits().await;
+    assert_eq!(conduits, vec![(harness.conduit_id().await, 1)], "bootstrap should create one single-shard conduit");
+    assert_eq!(harness.control_state.my_user.login.as_str(), BOT_LOGIN, "bootstrap should look up the bot");
+
+    let (status, body) = harness.send(Method::GET, "/conduit/status", None).await;
+    assert_eq!(status, StatusCode::OK, "conduit status should be served");
+    assert_eq!(at(&body, "/conduit_id"), json!(harness.conduit_id().await), "conduit status should show the conduit");
+}
+
+#[tokio::test]
+async fn routes_need_a_valid_token() {
+    let harness = Harness::start().await;
+
+    let (status, _) = harness.send_as(None, Method::GET, "/healthz", None).await;
+    assert_eq!(status, StatusCode::OK, "health is public");
+
+    let (status, _) = harness.send_as(Some("wrong"), Method::GET, "/broadcasters", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED, "an unknown token should be refused");
+
+    let (status, body) = harness.send(Method::GET, "/broadcasters", None).await;
+    assert_eq!(status, StatusCode::OK, "the control token should be let in");
+    assert_eq!(body, json!([]), "no broadcasters are configured");
+}
+
+#[tokio::test]
+async fn broadcasters_are_subscribed_and_unsubscribed() {
+    let harness = Harness::start().await;
+    let user_id = harness.twitch.user_id("streamer").await;
+
+    let (status, body) = harness.send(Method::POST, "/broadcasters", Some(json!({ "login": "Streamer" }))).await;
+    assert_eq!(status, StatusCode::OK, "adding a broadcaster should succeed: {body}");
+    assert_eq!(at(&body, "/login"), json!("streamer"), "logins should be lowercased");
+    assert_eq!(at(&body, "/user_id"), json!(user_id), "the broadcaster should resolve to the mock's user");
+
+    let subscriptions = harness.twitch.subscriptions().await;
+    let mut types: Vec<&str> = subscriptions.iter().filter_map(|subscription| subscription.pointer("/type").and_then(Value::as_str)).collect();
+    types.sort_unstable();
+    assert_eq!(types, vec!["stream.offline", "stream.online"], "the default types should be subscribed");
+    let conduit_id = harness.conduit_id().await;
+    assert!(subscriptions.iter().all(|subscription| at(subscription, "/condition/broadcaster_user_id") == json!(user_id) && at(subscription, "/transport/conduit_id") == json!(conduit_id)), "subscriptions should be for the broadcaster on our conduit");
+
+    let (status, _) = harness.send(Method::POST, "/broadcasters", Some(json!({ "login": "streamer" }))).await;
+    assert_eq!(status, StatusCode::CONFLICT, "adding a broadcaster twice should conflict");
+
+    let (status, body) = harness.send(Method::GET, "/subscriptions", None).await;
+    assert_eq!(status, StatusCode::OK, "subscriptions should be listed");
+    assert_eq!(at(&body, "/total"), json!(2), "Twitch's total should be passed on");
+    assert_eq!(at(&body, "/broadcasters/0/login"), json!("streamer"), "subscriptions should be grouped by broadcaster");
+
+    let (status, _) = harness.send(Method::DELETE, "/broadcasters/streamer", None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT, "removing the broadcaster should succeed");
+    assert!(harness.twitch.subscriptions().await.is_empty(), "removing the broadcaster should delete its subscriptions");
+
+    let (status, _) = harness.send(Method::DELETE, "/broadcasters/streamer", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "removing a broadcaster twice should find nothing");
+}
+
+#[tokio::test]
+async fn reconciliation_recreates_lost_subscriptions() {
+    let harness = Harness::start().await;
+
+    let (status, _) = harness.send(Method::POST, "/broadcasters", Some(json!({ "login": "streamer", "subscriptions": ["stream.online"] }))).await;
+    assert_eq!(status, StatusCode::OK, "adding a broadcaster should succeed");
+    harness.twitch.delete_subscriptions().await;
+
+    reconcile::reconcile(&harness.control_state).await.expect("reconciliation should succeed");
+
+    let subscriptions = harness.twitch.subscriptions().await;
+    let mut types: Vec<&str> = subscriptions.iter().filter_map(|subscription| subscription.pointer("/type").and_then(Value::as_str)).collect();
+    types.sort_unstable();
+    assert_eq!(types, vec!["stream.online", "user.authorization.revoke"], "the lost subscription and the revocation one should be created");
+
+    let online = subscriptions.iter().find(|subscription| at(subscription, "/type") == json!("stream.online")).and_then(|subscription| subscription.pointer("/id").and_then(Value::as_str)).map(str::to_owned);
+    let registered = harness.control_state.broadcasters.read().await.get("streamer").and_then(|broadcaster| broadcaster.subscriptions.get(&SubscriptionKind::StreamOnline)).map(ToString::to_string);
+    assert_eq!(registered, online, "the registry should have the new subscription id");
+
+    reconcile::reconcile(&harness.control_state).await.expect("reconciliation should succeed again");
+    assert_eq!(harness.twitch.subscriptions().await.len(), 2, "a second pass should leave things as they are");
+}
+
+#[tokio::test]
+async fn workers_are_assigned_shards() {
+    let harness = Harness::start().await;
+    let conduit_id = harness.conduit_id().await;
+
+    let (status, body) = harness.send(Method::POST, "/workers/register", None).await;
+    assert_eq!(status, StatusCode::OK, "registering a worker should succeed");
+    let worker_id = at(&body, "/worker_id").as_str().expect("registration should return a worker id").to_owned();
+
+    let (status, body) = harness.send(Method::POST, "/session/assign", Some(json!({ "session_id": "session-a", "worker_id": worker_id }))).await;
+    assert_eq!(status, StatusCode::OK, "the worker should be given a shard: {body}");
+    assert_eq!(at(&body, "/shard"), json!(0), "the only shard should be given out");
+    assert_eq!(at(&body, "/conduit_id"), json!(conduit_id), "the shard should be on our conduit");
+
+    let shard = harness.twitch.shard(&conduit_id, 0).await.expect("Twitch should have the shard's transport");
+    assert_eq!(at(&shard, "/transport/session_id"), json!("session-a"), "Twitch should deliver to the worker's session");
+
+    let (status, _) = harness.send(Method::POST, "/session/assign", Some(json!({ "session_id": "session-b" }))).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "there should be no shard left while the first is enabled");
+
+    harness.twitch.disconnect(&conduit_id, 0).await;
+    let (status, body) = harness.send(Method::POST, "/session/assign", Some(json!({ "session_id": "session-b" }))).await;
+    assert_eq!(status, StatusCode::OK, "a disconnected shard should be reclaimed: {body}");
+    let shard = harness.twitch.shard(&conduit_id, 0).await.expect("Twitch should have the shard's transport");
+    assert_eq!(at(&shard, "/transport/session_id"), json!("session-b"), "Twitch should deliver to the new session");
+}
+
+#[tokio::test]
+async fn sqlite_store_round_trips() {
+    let path = std::env::temp_dir().join(format!("control-plane-{}.db", crate::keys::hex(&rand::random::<[u8; 8]>())));
+    let mut config = Config::default();
+    config.store.url = format!("sqlite://{}", path.display());
+
+    let store = store::open(&config).await.expect("sqlite store should open");
+    let mut snapshot = store::Snapshot::default();
+    let worker = SavedWorker { standby: true, capabilities: ["chat".to_owned()].into(), ..SavedWorker::bare("worker-a".to_owned()) };
+    snapshot.workers.push(worker.clone());
+    snapshot.conduit_ids.insert("default".to_owned(), "conduit-a".to_owned());
+    snapshot.authorizations.insert("state-a".to_owned(), serde_json::from_value(json!({ "profile": "vip", "since": 1 })).expect("an outstanding authorization"));
+    snapshot.callback_secret = Some("secret-a".to_owned());
+    store.save(&snapshot).await.expect("sqlite store should save");
+
+    let loaded = store.load().await.expect("sqlite store should load");
+    std::fs::remove_file(&path).ok();
+    assert_eq!(loaded.workers, vec![worker], "workers should be stored with their capabilities and standby flag");
+    assert_eq!(loaded.conduit_ids.get("default").map(String::as_str), Some("conduit-a"), "the conduit id should be stored");
+    assert!(loaded.invites.is_empty() && loaded.authorizations.contains_key("state-a"), "outstanding authorizations should be stored apart from invites");
+    assert_eq!(loaded.callback_secret.as_deref(), Some("secret-a"), "the callback secret should be stored");
+}
+
+#[tokio::test]
+async fn a_key_revoked_by_the_old_leader_stays_revoked_after_a_takeover() {
+    let path = std::env::temp_dir().join(format!("control-plane-{}.db", crate::keys::hex(&rand::random::<[u8; 8]>())));
+    let store_url = format!("sqlite://{}", path.display());
+
+    let old = Harness::start_with_store(&store_url).await;
+    let (status, created) = old.send(Method::POST, "/keys", Some(json!({ "name": "reader", "scope": "read" }))).await;
+    assert_eq!(status, StatusCode::CREATED, "the old leader should create the key: {created}");
+    let key = at(&created, "/key").as_str().expect("the created key should be shown").to_owned();
+    store::flush(&old.control_state).await.expect("the old leader should save the key");
+
+    // a replica that already knows the key from the store
+    let new = Harness::start_with_store(&store_url).await;
+    let (status, _) = new.send_as(Some(&key), Method::GET, "/broadcasters", None).await;
+    assert_eq!(status, StatusCode::OK, "the new replica should accept the key before it's revoked");
+
+    let (status, _) = old.send(Method::DELETE, &format!("/keys/{}", at(&created, "/id").as_str().unwrap_or_default()), None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT, "the old leader should revoke the key");
+    store::flush(&old.control_state).await.expect("the old leader should save the revocation");
+
+    store::reload(&new.control_state).await.expect("the new leader should take over the stored state");
+    std::fs::remove_file(&path).ok();
+    let (status, _) = new.send_as(Some(&key), Method::GET, "/broadcasters", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED, "the revoked key should stay revoked on the new leader");
+}
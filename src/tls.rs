@@ -0,0 +1,142 @@
+use alloc::sync::Arc;
+use anyhow::Context as _;
+use axum::extract::connect_info::Connected;
+use axum::serve::IncomingStream;
+use axum::serve::Listener;
+use core::net::SocketAddr;
+use core::time::Duration;
+use std::time::SystemTime;
+use crate::config::TlsConfig;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::RootCertStore;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::CertificateDer;
+use tokio_rustls::rustls::pki_types::PrivateKeyDer;
+use tokio_rustls::rustls::pki_types::pem::PemObject as _;
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::server::TlsStream;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What the control plane knows about the other end of a connection.
+#[derive(Clone, Copy, Debug)]
+pub struct Peer {
+    pub addr: SocketAddr,
+    /// Presented a client certificate that chains to `tls.client_ca_path`.
+    pub client_verified: bool
+}
+
+impl Connected<IncomingStream<'_, TcpListener>> for Peer {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        Self { addr: *stream.remote_addr(), client_verified: false }
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for Peer {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        Self {
+            addr: *stream.remote_addr(),
+            client_verified: stream.io().get_ref().1.peer_certificates().is_some_and(|certificates| !certificates.is_empty())
+        }
+    }
+}
+
+/// Terminates TLS on accepted connections, dropping ones whose handshake fails. New connections
+/// pick up whatever acceptor `reload` last sent.
+pub struct TlsListener {
+    tcp: TcpListener,
+    acceptor: watch::Receiver<TlsAcceptor>
+}
+
+impl TlsListener {
+    pub const fn new(tcp: TcpListener, acceptor: watch::Receiver<TlsAcceptor>) -> Self {
+        Self { tcp, acceptor }
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        loop {
+            let (stream, addr) = Listener::accept(&mut self.tcp).await;
+            let acceptor = self.acceptor.borrow().clone();
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => return (stream, addr),
+                Ok(Err(e)) => tracing::debug!("TLS handshake with {addr} failed: {e}"),
+                Err(_elapsed) => tracing::debug!("TLS handshake with {addr} timed out")
+            }
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        self.tcp.local_addr()
+    }
+}
+
+/// `None` when TLS isn't configured. With a client CA, client certificates are asked for and
+/// verified but not required at the handshake, so probes without one still reach `/healthz`;
+/// routes that need one check `Peer::client_verified`.
+pub fn acceptor(config: &TlsConfig) -> anyhow::Result<Option<TlsAcceptor>> {
+    if config.cert_path.is_empty() {
+        return Ok(None);
+    }
+
+    let certificates = CertificateDer::pem_file_iter(&config.cert_path)
+        .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+        .with_context(|| format!("failed to read certificates from {}", config.cert_path))?;
+    let key = PrivateKeyDer::from_pem_file(&config.key_path).with_context(|| format!("failed to read private key from {}", config.key_path))?;
+
+    let provider = Arc::new(ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(Arc::clone(&provider)).with_safe_default_protocol_versions()?;
+
+    let builder = if config.client_ca_path.is_empty() {
+        builder.with_no_client_auth()
+    } else {
+        let mut roots = RootCertStore::empty();
+        for certificate in CertificateDer::pem_file_iter(&config.client_ca_path).with_context(|| format!("failed to read client CA from {}", config.client_ca_path))? {
+            roots.add(certificate?)?;
+        }
+
+        builder.with_client_cert_verifier(WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider).allow_unauthenticated().build()?)
+    };
+
+    let mut server_config = builder.with_single_cert(certificates, key)?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(Some(TlsAcceptor::from(Arc::new(server_config))))
+}
+
+/// Rebuilds the acceptor whenever one of the configured files changes, so rotated certificates
+/// are served without a restart. A bad rotation is logged and the previous files keep serving.
+pub async fn reload(config: TlsConfig, acceptor: watch::Sender<TlsAcceptor>) -> ! {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.reload_interval_secs));
+    let mut loaded = modified(&config);
+    loop {
+        interval.tick().await;
+
+        let current = modified(&config);
+        if current == loaded {
+            continue;
+        }
+
+        match self::acceptor(&config) {
+            Ok(Some(reloaded)) => {
+                acceptor.send_replace(reloaded);
+                loaded = current;
+                tracing::info!("reloaded TLS certificates from {}", config.cert_path);
+            },
+            Ok(None) => {},
+            Err(e) => tracing::error!("failed to reload TLS certificates, keeping the current ones: {e:?}")
+        }
+    }
+}
+
+fn modified(config: &TlsConfig) -> [Option<SystemTime>; 3] {
+    [&config.cert_path, &config.key_path, &config.client_ca_path].map(|path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok())
+}
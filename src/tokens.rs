@@ -0,0 +1,288 @@
+use crate::ControlState;
+use crate::events::ControlEvent;
+use crate::events::Events;
+use crate::helix::retry::RetryClient;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
+use core::time::Duration;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::RwLock;
+use twitch_api::TwitchClient;
+use twitch_api::twitch_oauth2::AccessToken;
+use twitch_api::twitch_oauth2::AppAccessToken;
+use twitch_api::twitch_oauth2::ClientId;
+use twitch_api::twitch_oauth2::ClientSecret;
+use twitch_api::twitch_oauth2::RefreshToken;
+use twitch_api::twitch_oauth2::Scope;
+use twitch_api::twitch_oauth2::TwitchToken as _;
+use twitch_api::twitch_oauth2::UserToken;
+use twitch_api::twitch_oauth2::Validator;
+use twitch_api::twitch_oauth2::tokens::errors::ValidationError;
+use twitch_api::types::UserId;
+
+/// How close to expiry a token gets refreshed, and how often that's checked.
+const REFRESH_BEFORE: Duration = Duration::from_secs(10 * 60);
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Twitch requires tokens be validated at least hourly.
+const VALIDATE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The app access token, replaced when Twitch stops accepting it.
+#[derive(Debug)]
+pub struct AppToken {
+    token: RwLock<AppAccessToken>,
+    valid: AtomicBool
+}
+
+impl AppToken {
+    pub fn new(token: AppAccessToken) -> Self {
+        Self {
+            token: RwLock::new(token),
+            valid: AtomicBool::new(true)
+        }
+    }
+
+    pub async fn get(&self) -> AppAccessToken {
+        self.token.read().await.clone()
+    }
+
+    pub async fn is_valid(&self) -> bool {
+        self.valid.load(Ordering::Relaxed) && !self.token.read().await.is_elapsed()
+    }
+
+    /// Validates the token, getting a new one if Twitch rejects it or it's about to expire.
+    async fn validate(&self, client: &TwitchClient<'_, RetryClient>, client_id: &ClientId, client_secret: &ClientSecret, events: &Events) {
+        let token = self.get().await;
+        match token.validate_token(client).await {
+            Ok(_) if token.expires_in() > VALIDATE_INTERVAL => {
+                self.valid.store(true, Ordering::Relaxed);
+                return;
+            },
+            Ok(_) | Err(ValidationError::NotAuthorized) => {},
+            Err(e) => {
+                tracing::warn!("failed to validate app access token, keeping it: {e:?}");
+                return;
+            }
+        }
+
+        match AppAccessToken::get_app_access_token(client, client_id.clone(), client_secret.clone(), Vec::new()).await {
+            Ok(token) => {
+                tracing::info!("replaced app access token");
+                *self.token.write().await = token;
+                self.valid.store(true, Ordering::Relaxed);
+                events.publish(ControlEvent::AppTokenReplaced);
+            },
+            Err(e) => {
+                tracing::error!("failed to replace app access token: {e:?}");
+                self.valid.store(false, Ordering::Relaxed);
+                events.publish(ControlEvent::AppTokenReplaceFailed { error: e.to_string() });
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TokenInfo {
+    pub user_id: UserId,
+    pub login: String,
+    pub scopes: Vec<Scope>,
+    pub expires_in_secs: u64,
+    /// Unexpired, and neither the last validation nor refresh failed.
+    pub valid: bool,
+    pub refreshable: bool,
+    pub error: Option<String>
+}
+
+impl From<&StoredToken> for TokenInfo {
+    fn from(stored: &StoredToken) -> Self {
+        let token = &stored.token;
+        Self {
+            user_id: token.user_id.clone(),
+            login: token.login.to_string(),
+            scopes: token.scopes().to_vec(),
+            expires_in_secs: token.expires_in().as_secs(),
+            valid: !token.is_elapsed() && stored.error.is_none(),
+            refreshable: token.refresh_token.is_some(),
+            error: stored.error.clone()
+        }
+    }
+}
+
+/// A user token as persisted, enough to validate or refresh it again after a restart.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SavedToken {
+    pub user_id: UserId,
+    pub access_token: String,
+    pub refresh_token: Option<String>
+}
+
+#[derive(Debug)]
+struct StoredToken {
+    token: UserToken,
+    error: Option<String>
+}
+
+/// User access tokens known to the control plane, keyed by the user they belong to.
+#[derive(Debug, Default)]
+pub struct TokenStore {
+    tokens: RwLock<BTreeMap<UserId, StoredToken>>
+}
+
+impl TokenStore {
+    pub async fn insert(&self, token: UserToken) -> TokenInfo {
+        let stored = StoredToken { token, error: None };
+        let info = TokenInfo::from(&stored);
+        self.tokens.write().await.insert(stored.token.user_id.clone(), stored);
+        info
+    }
+
+    pub async fn remove(&self, user_id: &UserId) -> Option<UserToken> {
+        self.tokens.write().await.remove(user_id).map(|stored| stored.token)
+    }
+
+    pub async fn get(&self, user_id: &UserId) -> Option<UserToken> {
+        self.tokens.read().await.get(user_id).map(|stored| stored.token.clone())
+    }
+
+    pub async fn list(&self) -> Vec<TokenInfo> {
+        self.tokens.read().await.values().map(TokenInfo::from).collect()
+    }
+
+    pub async fn all(&self) -> Vec<SavedToken> {
+        self.tokens.read().await.values().map(|stored| SavedToken {
+            user_id: stored.token.user_id.clone(),
+            access_token: stored.token.access_token.secret().to_owned(),
+            refresh_token: stored.token.refresh_token.as_ref().map(|token| token.secret().to_owned())
+        }).collect()
+    }
+
+    /// Drops the tokens of users `keep` turns down.
+    pub async fn retain<F: Fn(&UserId) -> bool>(&self, keep: F) {
+        self.tokens.write().await.retain(|user_id, _| keep(user_id));
+    }
+
+    /// Revalidates saved tokens, refreshing ones that expired while we were down. Tokens for
+    /// users who already have one (e.g. the bot's from config) are left alone.
+    pub async fn restore(&self, client: &TwitchClient<'_, RetryClient>, client_id: &ClientId, client_secret: &ClientSecret, saved: Vec<SavedToken>) {
+        for saved in saved {
+            if self.tokens.read().await.contains_key(&saved.user_id) {
+                continue;
+            }
+
+            let access_token = AccessToken::new(saved.access_token);
+            let result = match saved.refresh_token {
+                Some(refresh_token) => UserToken::from_existing_or_refresh_token(client, access_token, RefreshToken::new(refresh_token), client_id.clone(), client_secret.clone()).await.map_err(anyhow::Error::from),
+                None => UserToken::from_existing(client, access_token, None, client_secret.clone()).await.map_err(anyhow::Error::from)
+            };
+
+            match result {
+                Ok(token) => {
+                    self.insert(token).await;
+                },
+                Err(e) => tracing::warn!("dropped stored user token for {}, it's no longer valid: {e:?}", saved.user_id)
+            }
+        }
+    }
+
+    /// Whether `user_id` has a token Twitch still accepts, `None` without one.
+    pub async fn is_valid(&self, user_id: &UserId) -> Option<bool> {
+        self.tokens.read().await.get(user_id).map(|stored| TokenInfo::from(stored).valid)
+    }
+
+    /// `None` when there's no token for `user_id` to check against, otherwise the scopes it lacks.
+    pub async fn missing_scopes(&self, user_id: &UserId, required: &Validator) -> Option<Option<Validator>> {
+        self.tokens.read().await.get(user_id).map(|stored| required.missing(stored.token.scopes()))
+    }
+
+    /// Refreshes the tokens that expire within `REFRESH_BEFORE`.
+    async fn refresh_expiring(&self, client: &TwitchClient<'_, RetryClient>, client_secret: ClientSecret, events: &Events) {
+        let expiring: Vec<UserToken> = self.tokens.read().await.values()
+            .filter(|stored| stored.token.refresh_token.is_some() && !stored.token.never_expires() && stored.token.expires_in() < REFRESH_BEFORE)
+            .map(|stored| stored.token.clone())
+            .collect();
+
+        self.refresh(client, client_secret, expiring, events).await;
+    }
+
+    /// Validates every token, refreshing the ones Twitch rejects.
+    async fn validate_all(&self, client: &TwitchClient<'_, RetryClient>, client_secret: ClientSecret, events: &Events) {
+        let tokens: Vec<UserToken> = self.tokens.read().await.values().map(|stored| stored.token.clone()).collect();
+
+        let mut rejected = Vec::new();
+        for token in tokens {
+            match token.validate_token(client).await {
+                Ok(_) => {},
+                Err(ValidationError::NotAuthorized) => rejected.push(token),
+                Err(e) => tracing::warn!("failed to validate user token for {} ({}): {e:?}", token.login, token.user_id)
+            }
+        }
+
+        for token in &rejected {
+            tracing::warn!("user token for {} ({}) is no longer valid, refreshing it", token.login, token.user_id);
+            if token.refresh_token.is_none() && let Some(stored) = self.tokens.write().await.get_mut(&token.user_id) {
+                stored.error = Some("rejected by Twitch, and can't be refreshed".to_owned());
+            }
+        }
+        self.refresh(client, client_secret, rejected.into_iter().filter(|token| token.refresh_token.is_some()).collect(), events).await;
+    }
+
+    /// Each token is refreshed on a copy outside the lock, as a failed refresh consumes the
+    /// refresh token.
+    async fn refresh(&self, client: &TwitchClient<'_, RetryClient>, client_secret: ClientSecret, tokens: Vec<UserToken>, events: &Events) {
+        for mut token in tokens {
+            token.set_secret(Some(client_secret.clone()));
+            let result = token.refresh_token(client).await;
+
+            let mut tokens = self.tokens.write().await;
+            let Some(stored) = tokens.get_mut(&token.user_id) else {
+                continue;
+            };
+            match result {
+                Ok(()) => {
+                    tracing::info!("refreshed user token for {} ({})", token.login, token.user_id);
+                    events.publish(ControlEvent::TokenRefreshed { user_id: token.user_id.to_string(), login: token.login.to_string() });
+                    stored.token = token;
+                    stored.error = None;
+                },
+                Err(e) => {
+                    tracing::warn!("failed to refresh user token for {} ({}): {e:?}", token.login, token.user_id);
+                    events.publish(ControlEvent::TokenRefreshFailed { user_id: token.user_id.to_string(), login: token.login.to_string(), error: e.to_string() });
+                    stored.error = Some(e.to_string());
+                }
+            }
+            drop(tokens);
+        }
+    }
+}
+
+/// Keeps user tokens fresh, so subscriptions and chat sends relying on them don't lapse.
+pub async fn run(control_state: Arc<ControlState<'static>>) -> ! {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        if !control_state.leader.is_leader() {
+            continue;
+        }
+
+        let client_secret = ClientSecret::new(control_state.secrets.client_secret().await);
+        control_state.tokens.refresh_expiring(&control_state.client, client_secret, &control_state.events).await;
+    }
+}
+
+/// Validates the app and user tokens hourly as Twitch requires, replacing ones it rejects.
+/// Readiness reflects the app token and the bot's. User tokens are the leader's to replace.
+pub async fn validate(control_state: Arc<ControlState<'static>>) -> ! {
+    let mut interval = tokio::time::interval(VALIDATE_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let client_id = ClientId::new(control_state.config.twitch.client_id.clone());
+        let client_secret = ClientSecret::new(control_state.secrets.client_secret().await);
+        control_state.app_token.validate(&control_state.client, &client_id, &client_secret, &control_state.events).await;
+        if control_state.leader.is_leader() {
+            control_state.tokens.validate_all(&control_state.client, client_secret, &control_state.events).await;
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use crate::helix::retry::RetryClient;
+use crate::subscription::Description;
+use alloc::borrow::Cow;
+use axum::body::Bytes;
+use core::pin::Pin;
+use futures_util::TryStreamExt as _;
+use serde::Serialize;
+use twitch_api::HelixClient;
+use twitch_api::eventsub::Conduit;
+use twitch_api::eventsub::EventSubSubscription;
+use twitch_api::eventsub::EventType;
+use twitch_api::eventsub::Shard;
+use twitch_api::eventsub::ShardResponse;
+use twitch_api::eventsub::Transport;
+use twitch_api::helix::BodyError;
+use twitch_api::helix::ClientRequestError;
+use twitch_api::helix::HelixRequestBody;
+use twitch_api::helix::Request;
+use twitch_api::helix::RequestPost;
+use twitch_api::helix::eventsub::EventSubSubscriptions;
+use twitch_api::helix::eventsub::UpdateConduitShardsResponse;
+use twitch_api::helix::users::GetUsersRequest;
+use twitch_api::helix::users::User;
+use twitch_api::twitch_oauth2::AppAccessToken;
+use twitch_api::twitch_oauth2::Validator;
+use twitch_api::types::ConduitIdRef;
+use twitch_api::types::EventSubId;
+use twitch_api::types::EventSubIdRef;
+use twitch_api::types::UserName;
+
+pub type TwitchError = ClientRequestError<reqwest::Error>;
+pub type TwitchFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, TwitchError>> + Send + 'a>>;
+
+/// A subscription Twitch created, with the cost totals as of its creation.
+#[derive(Debug)]
+pub struct Created {
+    pub id: EventSubId,
+    pub total: usize,
+    pub total_cost: usize,
+    pub max_total_cost: usize
+}
+
+/// The Helix calls the control logic makes: conduits, their shards, EventSub subscriptions and
+/// users. Endpoints only proxied for workers go through the Helix client directly.
+pub trait TwitchControl: Send + Sync {
+    fn conduits<'a>(&'a self, token: &'a AppAccessToken) -> TwitchFuture<'a, Vec<Conduit>>;
+    fn create_conduit<'a>(&'a self, shard_count: usize, token: &'a AppAccessToken) -> TwitchFuture<'a, Conduit>;
+    fn update_conduit<'a>(&'a self, conduit_id: &'a ConduitIdRef, shard_count: usize, token: &'a AppAccessToken) -> TwitchFuture<'a, Conduit>;
+    fn delete_conduit<'a>(&'a self, conduit_id: &'a ConduitIdRef, token: &'a AppAccessToken) -> TwitchFuture<'a, ()>;
+    fn shards<'a>(&'a self, conduit_id: &'a ConduitIdRef, token: &'a AppAccessToken) -> TwitchFuture<'a, Vec<ShardResponse>>;
+    fn update_shards<'a>(&'a self, conduit_id: &'a ConduitIdRef, shards: &'a [Shard], token: &'a AppAccessToken) -> TwitchFuture<'a, UpdateConduitShardsResponse>;
+    /// Every page of our subscriptions, optionally of one type.
+    fn subscriptions<'a>(&'a self, event_type: Option<EventType>, token: &'a AppAccessToken) -> TwitchFuture<'a, Vec<EventSubSubscriptions>>;
+    fn create_subscription<'a>(&'a self, subscription: &'a Description, transport: Transport, token: &'a AppAccessToken) -> TwitchFuture<'a, Created>;
+    fn delete_subscription<'a>(&'a self, id: &'a EventSubIdRef, token: &'a AppAccessToken) -> TwitchFuture<'a, ()>;
+    /// The users among `logins` that exist, at most 100 at a time.
+    fn users<'a>(&'a self, logins: &'a [UserName], token: &'a AppAccessToken) -> TwitchFuture<'a, Vec<User>>;
+}
+
+impl TwitchControl for HelixClient<'_, RetryClient> {
+    fn conduits<'a>(&'a self, token: &'a AppAccessToken) -> TwitchFuture<'a, Vec<Conduit>> {
+        Box::pin(self.get_conduits(token))
+    }
+
+    fn create_conduit<'a>(&'a self, shard_count: usize, token: &'a AppAccessToken) -> TwitchFuture<'a, Conduit> {
+        Box::pin(self.create_conduit(shard_count, token))
+    }
+
+    fn update_conduit<'a>(&'a self, conduit_id: &'a ConduitIdRef, shard_count: usize, token: &'a AppAccessToken) -> TwitchFuture<'a, Conduit> {
+        Box::pin(self.update_conduit(conduit_id, shard_count, token))
+    }
+
+    fn delete_conduit<'a>(&'a self, conduit_id: &'a ConduitIdRef, token: &'a AppAccessToken) -> TwitchFuture<'a, ()> {
+        Box::pin(self.delete_conduit(conduit_id, token))
+    }
+
+    fn shards<'a>(&'a self, conduit_id: &'a ConduitIdRef, token: &'a AppAccessToken) -> TwitchFuture<'a, Vec<ShardResponse>> {
+        Box::pin(self.get_conduit_shards(conduit_id, None, token).try_collect())
+    }
+
+    fn update_shards<'a>(&'a self, conduit_id: &'a ConduitIdRef, shards: &'a [Shard], token: &'a AppAccessToken) -> TwitchFuture<'a, UpdateConduitShardsResponse> {
+        Box::pin(self.update_conduit_shards(conduit_id, shards, token))
+    }
+
+    fn subscriptions<'a>(&'a self, event_type: Option<EventType>, token: &'a AppAccessToken) -> TwitchFuture<'a, Vec<EventSubSubscriptions>> {
+        Box::pin(self.get_eventsub_subscriptions(None, event_type, None, token).try_collect())
+    }
+
+    fn create_subscription<'a>(&'a self, subscription: &'a Description, transport: Transport, token: &'a AppAccessToken) -> TwitchFuture<'a, Created> {
+        let body = CreateBody {
+            type_: subscription.event_type,
+            version: subscription.version,
+            condition: subscription.condition.clone(),
+            transport
+        };
+
+        Box::pin(async move {
+            let response = self.req_post(CreateRequest::default(), body, token).await?;
+            let created = response.data.into_iter().next().ok_or_else(|| ClientRequestError::Custom(Cow::Borrowed("Twitch created no subscription")))?;
+            let other = |key: &str| response.other.as_ref().and_then(|other| other.get(key)?.as_u64()).and_then(|value| usize::try_from(value).ok()).unwrap_or_default();
+
+            Ok(Created {
+                id: created.id,
+                total: response.total.and_then(|total| usize::try_from(total).ok()).unwrap_or_default(),
+                total_cost: other("total_cost"),
+                max_total_cost: other("max_total_cost")
+            })
+        })
+    }
+
+    fn delete_subscription<'a>(&'a self, id: &'a EventSubIdRef, token: &'a AppAccessToken) -> TwitchFuture<'a, ()> {
+        Box::pin(async move {
+            self.delete_eventsub_subscription(id, token).await?;
+            Ok(())
+        })
+    }
+
+    fn users<'a>(&'a self, logins: &'a [UserName], token: &'a AppAccessToken) -> TwitchFuture<'a, Vec<User>> {
+        Box::pin(async move { Ok(self.req_get(GetUsersRequest::logins(logins), token).await?.data) })
+    }
+}
+
+/// Create EventSub Subscription for a type only known at runtime, which twitch_api's own request
+/// can't express.
+#[derive(Default, Serialize)]
+#[expect(clippy::empty_structs_with_brackets, reason = "requests serialize to their query string, which a unit struct can't")]
+struct CreateRequest {}
+
+impl Request for CreateRequest {
+    const PATH: &'static str = "eventsub/subscriptions";
+    const SCOPE: Validator = twitch_api::twitch_oauth2::validator![];
+
+    type Response = Vec<EventSubSubscription>;
+}
+
+impl RequestPost for CreateRequest {
+    type Body = CreateBody;
+}
+
+#[derive(Serialize)]
+struct CreateBody {
+    #[serde(rename = "type")]
+    type_: EventType,
+    version: &'static str,
+    condition: serde_json::Value,
+    transport: Transport
+}
+
+impl HelixRequestBody for CreateBody {
+    fn try_to_body(&self) -> Result<Bytes, BodyError> {
+        Ok(serde_json::to_vec(self)?.into())
+    }
+}
@@ -0,0 +1,96 @@
+use crate::ControlState;
+use crate::twitch::TwitchError;
+use alloc::collections::BTreeMap;
+use core::slice;
+use core::time::Duration;
+use futures_util::StreamExt as _;
+use std::time::Instant;
+use tokio::sync::RwLock;
+use twitch_api::types::UserId;
+use twitch_api::types::UserName;
+
+/// How long a login stays resolved. Logins can be changed, and freed ones taken by someone else.
+const USER_TTL: Duration = Duration::from_secs(60 * 60);
+/// The most logins Get Users takes at once.
+const BATCH_SIZE: usize = 100;
+/// How many batches are looked up at a time.
+const BATCH_CONCURRENCY: usize = 4;
+
+/// Logins resolved to user ids, so the same users aren't looked up over and over.
+#[derive(Default)]
+pub struct UserCache {
+    ids: RwLock<BTreeMap<String, (Instant, UserId)>>
+}
+
+impl UserCache {
+    pub async fn get(&self, login: &str) -> Option<UserId> {
+        self.ids.read().await.get(&login.to_lowercase()).filter(|(resolved, _)| resolved.elapsed() < USER_TTL).map(|(_, id)| id.clone())
+    }
+
+    pub async fn insert(&self, login: &str, id: UserId) {
+        self.ids.write().await.insert(login.to_lowercase(), (Instant::now(), id));
+    }
+
+    /// Takes users we already know, e.g. broadcasters restored from the store, as just resolved.
+    pub async fn seed(&self, users: Vec<(String, UserId)>) {
+        let now = Instant::now();
+        let mut ids = self.ids.write().await;
+        for (login, id) in users {
+            ids.insert(login.to_lowercase(), (now, id));
+        }
+        drop(ids);
+    }
+}
+
+/// The id behind `login`, from the cache or else Helix; `None` when there's no such user.
+pub async fn resolve(control_state: &ControlState<'_>, login: &str) -> Result<Option<UserId>, TwitchError> {
+    if let Some(id) = control_state.users.get(login).await {
+        return Ok(Some(id));
+    }
+
+    let user = control_state.metrics.helix("get_users", control_state.twitch.users(slice::from_ref(&UserName::new(login.to_lowercase())), &control_state.app_token.get().await)).await?.into_iter().next();
+    if let Some(user) = &user {
+        control_state.users.insert(user.login.as_str(), user.id.clone()).await;
+    }
+
+    Ok(user.map(|user| user.id))
+}
+
+/// Resolves many logins at once, in batches of `BATCH_SIZE` for those not cached. Logins that
+/// don't exist, or were in a batch that failed, are left out.
+pub async fn resolve_many(control_state: &ControlState<'_>, logins: &[String]) -> BTreeMap<String, UserId> {
+    let mut resolved = BTreeMap::new();
+    let mut missing = Vec::new();
+    for login in logins {
+        match control_state.users.get(login).await {
+            Some(id) => {
+                resolved.insert(login.to_lowercase(), id);
+            },
+            None => missing.push(UserName::new(login.to_lowercase()))
+        }
+    }
+    missing.sort();
+    missing.dedup();
+
+    let token = &control_state.app_token.get().await;
+    let mut batches = futures_util::stream::iter(missing.chunks(BATCH_SIZE))
+        .map(|batch| async move {
+            let result = control_state.metrics.helix("get_users", control_state.twitch.users(batch, token)).await;
+            (batch.len(), result)
+        })
+        .buffer_unordered(BATCH_CONCURRENCY);
+
+    while let Some((len, result)) = batches.next().await {
+        match result {
+            Ok(users) => {
+                for user in users {
+                    control_state.users.insert(user.login.as_str(), user.id.clone()).await;
+                    resolved.insert(user.login.to_string(), user.id);
+                }
+            },
+            Err(e) => tracing::warn!("failed to resolve a batch of {len} logins: {e:?}")
+        }
+    }
+
+    resolved
+}
@@ -0,0 +1,98 @@
+use crate::ControlState;
+use crate::workers::WorkerState;
+use alloc::sync::Arc;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::Sse;
+use axum::response::sse::Event;
+use axum::response::sse::KeepAlive;
+use futures_util::Stream;
+use futures_util::StreamExt as _;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+/// What the control plane wants a worker to do, pushed over GET /assignments/watch.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Notice {
+    /// A shard was reserved for the worker; assign a session to it with the worker id.
+    Assign { conduit: String, shard: usize },
+    /// The worker's session moved to another shard in a resize, already pointed at it.
+    Moved { conduit: String, shard: usize },
+    /// The worker's session lost its shard; reconnect and assign again.
+    Reconnect { conduit: String, shard: usize },
+    /// Hand the shards over, closing once POST /workers/{id}/drain says it's safe.
+    Drain
+}
+
+/// Fans notices out to the workers watching, by worker id. Nobody watching drops them: polling
+/// the heartbeat and drain endpoints still works without a watch.
+pub struct Watch {
+    sender: broadcast::Sender<(String, Notice)>
+}
+
+impl Default for Watch {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(256).0
+        }
+    }
+}
+
+impl Watch {
+    pub fn notify(&self, worker_id: &str, notice: Notice) {
+        tracing::debug!("notifying worker {worker_id}: {notice:?}");
+        // only fails with no receivers
+        drop(self.sender.send((worker_id.to_owned(), notice)));
+    }
+}
+
+#[derive(Deserialize)]
+pub struct WatchQuery {
+    worker_id: String
+}
+
+/// Server-sent events for one worker, starting with any shard already reserved for it.
+pub async fn stream(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Query(query): Query<WatchQuery>
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, StatusCode> {
+    let state = control_state.workers.lock().await.get(&query.worker_id).map(|worker| worker.state);
+    match state {
+        None => return Err(StatusCode::NOT_FOUND),
+        Some(WorkerState::Expired) => return Err(StatusCode::GONE),
+        Some(_) => {}
+    }
+
+    // subscribed before looking for reservations, so none made in between are missed
+    let receiver = control_state.watch.sender.subscribe();
+
+    let mut pending = Vec::new();
+    for lane in control_state.conduits.iter() {
+        let reserved = lane.scheduler.lock().await.reserved_for(&query.worker_id);
+        if let Some(shard) = reserved {
+            pending.push(Notice::Assign { conduit: lane.name.clone(), shard });
+        }
+    }
+
+    let worker_id = query.worker_id;
+    let notices = futures_util::stream::unfold(receiver, move |mut receiver| {
+        let worker_id = worker_id.clone();
+        async move {
+            loop {
+                match receiver.recv().await {
+                    Ok((to, notice)) if to == worker_id => return Some((notice, receiver)),
+                    Ok(_) => {},
+                    Err(RecvError::Lagged(skipped)) => tracing::warn!("watch of worker {worker_id} fell behind, skipped {skipped} notices"),
+                    Err(RecvError::Closed) => return None
+                }
+            }
+        }
+    });
+
+    let events = futures_util::stream::iter(pending).chain(notices).map(|notice| Event::default().json_data(notice));
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
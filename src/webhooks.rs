@@ -0,0 +1,74 @@
+use crate::ControlState;
+use crate::config::WebhookConfig;
+use crate::events;
+use crate::events::Stamped;
+use alloc::sync::Arc;
+use core::time::Duration;
+use hmac::Hmac;
+use hmac::Mac as _;
+use sha2::Sha256;
+
+const ATTEMPTS: u32 = 3;
+const BASE_DELAY: Duration = Duration::from_secs(1);
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// POSTs the events `webhook` asks for to it, one at a time and in order. Each is tried a few
+/// times before being given up on, so a dead endpoint only loses its own events.
+pub async fn run(control_state: Arc<ControlState<'static>>, webhook: WebhookConfig) -> ! {
+    let client = reqwest::Client::new();
+    let mut events = control_state.events.subscribe();
+    let sink = format!("webhook {}", webhook.url);
+    loop {
+        let stamped = events::next(&mut events, &sink).await;
+
+        let name = stamped.event.name();
+        if !webhook.events.iter().any(|event| event == name) {
+            continue;
+        }
+
+        let outcome = match deliver(&client, &webhook, &stamped).await {
+            Ok(()) => "ok",
+            Err(e) => {
+                tracing::warn!("failed to deliver {name} event to webhook {}: {e:?}", webhook.url);
+                "error"
+            }
+        };
+        control_state.metrics.inc("control_event_publishes_total", &[("sink", "webhook"), ("outcome", outcome)]);
+    }
+}
+
+async fn deliver(client: &reqwest::Client, webhook: &WebhookConfig, stamped: &Stamped) -> anyhow::Result<()> {
+    let body = serde_json::to_vec(stamped)?;
+    let timestamp = stamped.at.to_string();
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(webhook.secret.as_bytes())?;
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(&body);
+    let signature = format!("sha256={}", crate::keys::hex(&mac.finalize().into_bytes()));
+
+    let mut delay = BASE_DELAY;
+    let mut attempt = 1;
+    loop {
+        let result = client.post(&webhook.url)
+            .timeout(TIMEOUT)
+            .header("Content-Type", "application/json")
+            .header("X-Control-Event", stamped.event.name())
+            .header("X-Control-Timestamp", &timestamp)
+            .header("X-Control-Signature", &signature)
+            .body(body.clone())
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status);
+
+        match result {
+            Ok(_) => return Ok(()),
+            Err(e) if attempt >= ATTEMPTS => return Err(e.into()),
+            Err(e) => tracing::debug!("webhook {} failed attempt {attempt}, retrying in {delay:?}: {e}", webhook.url)
+        }
+
+        tokio::time::sleep(delay).await;
+        delay = delay.saturating_mul(2);
+        attempt += 1;
+    }
+}
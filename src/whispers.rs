@@ -0,0 +1,114 @@
+use crate::ControlState;
+use crate::proxy;
+use crate::proxy::Rejection;
+use alloc::collections::BTreeMap;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::http::header::RETRY_AFTER;
+use axum::response::IntoResponse as _;
+use axum::response::Response;
+use axum::Json;
+use core::time::Duration;
+use serde::Deserialize;
+use std::time::Instant;
+use tokio::sync::Mutex;
+use twitch_api::helix::Request as _;
+use twitch_api::helix::whispers::SendWhisperRequest;
+use twitch_api::types::UserId;
+
+/// Twitch's whisper limits for a sender: so many a second and a minute, and only so many
+/// different recipients a day.
+const LIMITS: &[(usize, Duration)] = &[(3, Duration::from_secs(1)), (100, Duration::from_secs(60))];
+const MAX_RECIPIENTS: usize = 40;
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long a whisper may wait its turn before it's turned away instead.
+const MAX_WAIT: Duration = Duration::from_secs(30);
+
+/// Paces the bot's whispers under Twitch's limits. Senders queue on the lock in turn, each
+/// sleeping out whatever window it has to before going out.
+#[derive(Default)]
+pub struct Whispers {
+    sent: Mutex<Sent>
+}
+
+#[derive(Default)]
+struct Sent {
+    /// When recent whispers went out, oldest first.
+    at: VecDeque<Instant>,
+    /// When each recipient was first whispered within the last day.
+    recipients: BTreeMap<UserId, Instant>
+}
+
+impl Sent {
+    /// How long until another whisper fits in every window.
+    fn wait(&self, now: Instant) -> Duration {
+        LIMITS.iter().filter_map(|&(limit, window)| {
+            let in_window = self.at.iter().rev().take_while(|at| now.duration_since(**at) < window).count();
+            let oldest = *self.at.iter().rev().nth(limit.checked_sub(1)?)?;
+            (in_window >= limit).then(|| window.saturating_sub(now.duration_since(oldest)))
+        }).max().unwrap_or_default()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SendWhisper {
+    message: String
+}
+
+/// Whispers `user` as the bot, queued behind others being paced under Twitch's limits. A whisper
+/// that would wait too long, or would be to one recipient too many today, comes back 429.
+pub async fn send(
+    State(control_state): State<Arc<ControlState<'_>>>,
+    Path(user): Path<String>,
+    Json(body): Json<SendWhisper>
+) -> Result<Response, Rejection> {
+    if body.message.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "the message is empty".to_owned()));
+    }
+
+    let user_id = proxy::user_id(&control_state, &user).await?;
+    let token = proxy::bot_token(&control_state, &SendWhisperRequest::SCOPE).await?;
+
+    let Ok(mut sent) = tokio::time::timeout(MAX_WAIT, control_state.whispers.sent.lock()).await else {
+        return Ok(too_many(MAX_WAIT, "too many whispers are queued".to_owned()));
+    };
+
+    let now = Instant::now();
+    sent.recipients.retain(|_, first| now.duration_since(*first) < DAY);
+    if let Some(oldest) = sent.recipients.values().min().copied()
+        && sent.recipients.len() >= MAX_RECIPIENTS
+        && !sent.recipients.contains_key(&user_id)
+    {
+        drop(sent);
+        return Ok(too_many(DAY.saturating_sub(now.duration_since(oldest)), format!("the bot has whispered {MAX_RECIPIENTS} different users today")));
+    }
+
+    let wait = sent.wait(now);
+    if wait > MAX_WAIT {
+        drop(sent);
+        return Ok(too_many(wait, "whispers are rate limited".to_owned()));
+    }
+    tokio::time::sleep(wait).await;
+
+    let result = control_state.metrics.helix("send_whisper", control_state.client.helix.send_whisper(&control_state.my_user.id, &user_id, body.message.as_str(), &token)).await;
+    if result.is_ok() {
+        let now = Instant::now();
+        sent.at.push_back(now);
+        let longest = LIMITS.iter().map(|&(_, window)| window).max().unwrap_or_default();
+        while sent.at.front().is_some_and(|at| now.duration_since(*at) >= longest) {
+            sent.at.pop_front();
+        }
+        sent.recipients.entry(user_id).or_insert(now);
+    }
+    drop(sent);
+
+    result.map_err(|e| proxy::rejection(&e))?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+fn too_many(retry_after: Duration, message: String) -> Response {
+    (StatusCode::TOO_MANY_REQUESTS, [(RETRY_AFTER, retry_after.as_secs().saturating_add(1).to_string())], message).into_response()
+}
@@ -0,0 +1,186 @@
+use alloc::collections::BTreeMap;
+use alloc::collections::BTreeSet;
+use core::time::Duration;
+use serde::Deserialize;
+use serde::Serialize;
+use std::time::Instant;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkerState {
+    #[default]
+    Active,
+    /// Shutting down once its shards are handed over; gets no new ones.
+    Draining,
+    Expired
+}
+
+impl WorkerState {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Active => "active",
+            Self::Draining => "draining",
+            Self::Expired => "expired"
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Worker {
+    pub id: String,
+    pub state: WorkerState,
+    /// Waits without a shard to take over ones whose worker died.
+    pub standby: bool,
+    /// The kinds of event the worker can process, matched against a conduit's capability. A
+    /// worker that declares none is taken to handle anything.
+    pub capabilities: BTreeSet<String>,
+    /// Shards handed to standby workers while draining, by conduit name.
+    pub handed_over: Vec<(String, usize)>,
+    pub last_heartbeat: Instant
+}
+
+impl Worker {
+    pub fn can_run(&self, capability: &str) -> bool {
+        capability.is_empty() || self.capabilities.is_empty() || self.capabilities.contains(capability)
+    }
+}
+
+/// A worker as stored, without its lease, which starts over on restore.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SavedWorker {
+    pub id: String,
+    #[serde(default)]
+    pub state: WorkerState,
+    #[serde(default)]
+    pub standby: bool,
+    #[serde(default)]
+    pub capabilities: BTreeSet<String>,
+    #[serde(default)]
+    pub handed_over: Vec<(String, usize)>
+}
+
+impl SavedWorker {
+    /// One stored before there was more to it than its id.
+    pub const fn bare(id: String) -> Self {
+        Self { id, state: WorkerState::Active, standby: false, capabilities: BTreeSet::new(), handed_over: Vec::new() }
+    }
+}
+
+impl From<&Worker> for SavedWorker {
+    fn from(worker: &Worker) -> Self {
+        Self {
+            id: worker.id.clone(),
+            state: worker.state,
+            standby: worker.standby,
+            capabilities: worker.capabilities.clone(),
+            handed_over: worker.handed_over.clone()
+        }
+    }
+}
+
+/// Liveness of registered workers, kept alive by heartbeats within `ttl`.
+#[derive(Debug)]
+pub struct WorkerRegistry {
+    workers: BTreeMap<String, Worker>,
+    ttl: Duration
+}
+
+impl WorkerRegistry {
+    pub const fn new(ttl: Duration) -> Self {
+        Self {
+            workers: BTreeMap::new(),
+            ttl
+        }
+    }
+
+    pub const fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn register(&mut self, standby: bool, capabilities: BTreeSet<String>) -> Worker {
+        let worker = Worker {
+            id: crate::keys::hex(&rand::random::<[u8; 16]>()),
+            state: WorkerState::Active,
+            standby,
+            capabilities,
+            handed_over: Vec::new(),
+            last_heartbeat: Instant::now()
+        };
+
+        self.workers.insert(worker.id.clone(), worker.clone());
+
+        worker
+    }
+
+    /// Puts back a worker from before a restart with a fresh lease, so it has a full ttl to
+    /// heartbeat again. Expired workers aren't stored, so one that was is taken to be active.
+    pub fn clear(&mut self) {
+        self.workers.clear();
+    }
+
+    pub fn restore(&mut self, saved: SavedWorker) {
+        self.workers.insert(saved.id.clone(), Worker {
+            id: saved.id,
+            state: if saved.state == WorkerState::Draining { WorkerState::Draining } else { WorkerState::Active },
+            standby: saved.standby,
+            capabilities: saved.capabilities,
+            handed_over: saved.handed_over,
+            last_heartbeat: Instant::now()
+        });
+    }
+
+    /// Returns `None` for unknown workers and `Some(false)` for ones whose lease already lapsed.
+    pub fn heartbeat(&mut self, id: &str) -> Option<bool> {
+        let worker = self.workers.get_mut(id)?;
+        if worker.state == WorkerState::Expired {
+            return Some(false);
+        }
+
+        worker.last_heartbeat = Instant::now();
+
+        Some(true)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Worker> {
+        self.workers.get(id)
+    }
+
+    pub fn is_active(&self, id: &str) -> bool {
+        self.workers.get(id).is_some_and(|worker| worker.state == WorkerState::Active)
+    }
+
+    /// Marks a worker as draining, adding to the shards it's handed over.
+    pub fn drain(&mut self, id: &str, handed_over: Vec<(String, usize)>) {
+        if let Some(worker) = self.workers.get_mut(id).filter(|worker| worker.state != WorkerState::Expired) {
+            worker.state = WorkerState::Draining;
+            worker.handed_over.extend(handed_over);
+        }
+    }
+
+    /// Marks lapsed leases as expired and returns their ids; long-expired entries are forgotten.
+    pub fn expire(&mut self, now: Instant) -> Vec<String> {
+        let ttl = self.ttl;
+        self.workers.retain(|_, worker| worker.state != WorkerState::Expired || now.duration_since(worker.last_heartbeat) < ttl * 10);
+
+        self.workers.values_mut()
+            .filter(|worker| worker.state != WorkerState::Expired && now.duration_since(worker.last_heartbeat) >= ttl)
+            .map(|worker| {
+                worker.state = WorkerState::Expired;
+                worker.id.clone()
+            })
+            .collect()
+    }
+
+    pub fn active(&self) -> usize {
+        self.workers.values().filter(|worker| worker.state == WorkerState::Active).count()
+    }
+
+    /// Active standby workers that can take a shard needing `capability`.
+    pub fn standbys(&self, capability: &str) -> impl Iterator<Item = &str> {
+        self.workers.values().filter(move |worker| worker.state == WorkerState::Active && worker.standby && worker.can_run(capability)).map(|worker| worker.id.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Worker> {
+        self.workers.values()
+    }
+}